@@ -0,0 +1,230 @@
+//! Run:
+//!
+//! ```bash
+//! cargo run --example dns-resolver
+//! ```
+//!
+//! Starts a stub DNS server on a loopback port, which answers every query over UDP with a
+//! truncated reply, and over TCP with an A record. A DnsResolver pointed at the stub gets the
+//! truncated reply, repeats the query over TCP, and prints the address it finds.
+
+extern crate libuv;
+use libuv::prelude::*;
+use libuv::{
+    AddrInfo, AddressFamily, Buf, DnsConfig, DnsResolver, ReadonlyBuf, SocketType, TcpBindFlags,
+    UdpBindFlags, UdpHandle, UdpRecvFlags,
+};
+use std::cell::RefCell;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::rc::Rc;
+
+/// The address the stub server gives for every name.
+const ADDRESS: [u8; 4] = [192, 0, 2, 53];
+
+const TYPE_A: u16 = 1;
+
+fn alloc_buffer(_: Handle, suggested_size: usize) -> Option<Buf> {
+    Buf::with_capacity(suggested_size).ok()
+}
+
+/// Build a reply to a query. A truncated reply has no answers and the TC bit set; otherwise,
+/// queries for A records are answered with ADDRESS.
+fn reply_to(query: &[u8], truncated: bool) -> Option<Vec<u8>> {
+    // the question is the name, followed by the type and class
+    let mut pos = 12;
+    while *query.get(pos)? != 0 {
+        pos += 1 + query[pos] as usize;
+    }
+    let end = pos + 5;
+    let qtype = u16::from_be_bytes([*query.get(end - 4)?, *query.get(end - 3)?]);
+
+    let mut reply = query.get(..end)?.to_vec();
+    reply[2] |= if truncated { 0x82 } else { 0x80 };
+    reply[3] = 0x80;
+    for count in &mut reply[6..12] {
+        *count = 0;
+    }
+    if !truncated && qtype == TYPE_A {
+        reply[7] = 1;
+
+        // a pointer to the name in the question, type A, class IN, a TTL of 300 and 4 bytes of
+        // data
+        reply.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 1, 0x2c, 0, 4]);
+        reply.extend_from_slice(&ADDRESS);
+    }
+    Some(reply)
+}
+
+fn on_udp_query(
+    handle: UdpHandle,
+    nread: libuv::Result<usize>,
+    mut buf: ReadonlyBuf,
+    addr: SocketAddr,
+    _flags: UdpRecvFlags,
+) {
+    match nread {
+        Ok(len) if len > 0 => {
+            if let Some(reply) = reply_to(&buf[..len], true) {
+                println!("Stub: truncated reply over UDP");
+
+                // new_from_bytes() adds a null terminator; resolvers ignore the extra byte
+                match Buf::new_from_bytes(&reply) {
+                    Ok(mut reply) => {
+                        let sent = handle.send(Some(&addr), &[reply], move |_, status| {
+                            if let Err(e) = status {
+                                eprintln!("Stub: send error {}", e);
+                            }
+                            reply.destroy();
+                        });
+                        if let Err(e) = sent {
+                            eprintln!("Stub: send error {}", e);
+                            reply.destroy();
+                        }
+                    }
+                    Err(e) => eprintln!("Stub: couldn't allocate a reply {}", e),
+                }
+            }
+        }
+        Ok(_) => (),
+        Err(e) => eprintln!("Stub: recv error {}", e),
+    }
+    buf.dealloc();
+}
+
+/// Read a query over TCP, which is prefixed by its length, and reply in kind.
+fn on_tcp_query(
+    mut client: StreamHandle,
+    received: &RefCell<Vec<u8>>,
+    nread: libuv::Result<usize>,
+    mut buf: ReadonlyBuf,
+) {
+    match nread {
+        Ok(len) if len > 0 => received.borrow_mut().extend_from_slice(&buf[..len]),
+        Ok(_) => (),
+        Err(e) => {
+            if e != libuv::Error::EOF {
+                eprintln!("Stub: read error {}", e);
+            }
+            client.close(());
+        }
+    }
+    buf.dealloc();
+
+    let reply = {
+        let received = received.borrow();
+        if received.len() < 2 {
+            return;
+        }
+        let len = u16::from_be_bytes([received[0], received[1]]) as usize;
+        match received.get(2..2 + len) {
+            Some(query) => reply_to(query, false),
+            None => return,
+        }
+    };
+    if let Err(e) = client.read_stop() {
+        eprintln!("Stub: cannot stop read {}", e);
+    }
+    let reply = match reply {
+        Some(reply) => reply,
+        None => {
+            client.close(());
+            return;
+        }
+    };
+
+    println!("Stub: full reply over TCP");
+    let mut framed = (reply.len() as u16).to_be_bytes().to_vec();
+    framed.extend_from_slice(&reply);
+    match Buf::new_from_bytes(&framed) {
+        Ok(mut framed) => {
+            let written = client.write(&[framed], move |_, status| {
+                if let Err(e) = status {
+                    eprintln!("Stub: write error {}", e);
+                }
+                framed.destroy();
+                client.close(());
+            });
+            if let Err(e) = written {
+                eprintln!("Stub: write error {}", e);
+                framed.destroy();
+                client.close(());
+            }
+        }
+        Err(e) => {
+            eprintln!("Stub: couldn't allocate a reply {}", e);
+            client.close(());
+        }
+    }
+}
+
+fn on_tcp_connection(mut server: StreamHandle, status: libuv::Result<u32>) {
+    if let Err(e) = status {
+        eprintln!("Stub: connection error {}", e);
+        return;
+    }
+
+    if let Ok(client) = server.get_loop().tcp().as_mut() {
+        let mut client = client.to_stream();
+        if let Err(e) = server.accept(&mut client) {
+            eprintln!("Stub: error accepting connection {}", e);
+            client.close(());
+            return;
+        }
+
+        let received = RefCell::new(Vec::new());
+        let started = client.read_start(
+            alloc_buffer,
+            move |client: StreamHandle, nread: libuv::Result<usize>, buf: ReadonlyBuf| {
+                on_tcp_query(client, &received, nread, buf)
+            },
+        );
+        if let Err(e) = started {
+            eprintln!("Stub: error starting read {}", e);
+            client.close(());
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut r#loop = Loop::default()?;
+
+    // the stub listens for UDP and TCP on the same port, as name servers do
+    let mut udp = r#loop.udp()?;
+    udp.bind(&(Ipv4Addr::LOCALHOST, 0).into(), UdpBindFlags::empty())?;
+    let server = SocketAddr::new(Ipv4Addr::LOCALHOST.into(), udp.getsockname()?.port());
+    udp.recv_start(alloc_buffer, on_udp_query)?;
+    let mut tcp = r#loop.tcp()?;
+    tcp.bind(&server, TcpBindFlags::empty())?;
+    tcp.listen(8, on_tcp_connection)?;
+
+    let config = DnsConfig {
+        nameservers: vec![server],
+        timeout: 1_000,
+        attempts: 1,
+        ..DnsConfig::default()
+    };
+    r#loop
+        .resolver_builder()
+        .resolver(Rc::new(DnsResolver::new(config)))
+        .node("stub.example")
+        .family(AddressFamily::Inet)
+        .socket_type(SocketType::Stream)
+        .resolve(move |result: libuv::Result<Vec<AddrInfo>>| {
+            match result {
+                Ok(infos) => {
+                    for info in infos {
+                        if let Some(addr) = info.addr {
+                            println!("stub.example resolved to {}", addr.ip());
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Could not resolve stub.example: {}", e),
+            }
+            udp.close(());
+            tcp.close(());
+        })?;
+
+    r#loop.run(RunMode::Default)?;
+
+    Ok(())
+}
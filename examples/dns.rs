@@ -6,8 +6,10 @@
 
 extern crate libuv;
 use libuv::prelude::*;
-use libuv::{AddrInfo, Buf, ConnectReq, GetAddrInfoReq, ReadonlyBuf};
-use libuv_sys2::{AF_INET, IPPROTO_TCP, SOCK_STREAM};
+use libuv::{
+    AddrInfo, AddressFamily, AiFlags, Buf, ConnectReq, GetAddrInfoReq, Protocol, ReadonlyBuf,
+    SocketType,
+};
 
 fn alloc_buffer(_: Handle, suggested_size: usize) -> Option<Buf> {
     Buf::with_capacity(suggested_size).ok()
@@ -77,14 +79,12 @@ fn on_resolved(req: GetAddrInfoReq, status: libuv::Result<u32>, res: Vec<AddrInf
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut r#loop = Loop::default()?;
 
-    let hints = AddrInfo {
-        flags: 0,
-        family: AF_INET,
-        socktype: SOCK_STREAM,
-        protocol: IPPROTO_TCP as _,
-        canonical_name: None,
-        addr: None,
-    };
+    let hints = AddrInfo::hints(
+        AddressFamily::Inet,
+        SocketType::Stream,
+        Protocol::Tcp,
+        AiFlags::empty(),
+    );
     r#loop.getaddrinfo(
         Some("irc.libera.chat"),
         Some("6667"),
@@ -0,0 +1,38 @@
+//! Run:
+//!
+//! ```bash
+//! cargo run --example graceful-shutdown
+//! ```
+//!
+//! Then send SIGHUP to "reload", and SIGINT (Ctrl+C) or SIGTERM to shut down:
+//!
+//! ```bash
+//! kill -HUP PID
+//! kill -TERM PID
+//! ```
+
+extern crate libuv;
+use libuv::prelude::*;
+use libuv::{getpid, Signal, SignalHandle, SignalSet, TimerHandle};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut r#loop = Loop::default()?;
+    println!("PID {}", getpid());
+
+    // some "work" that keeps the loop alive until we are asked to shut down
+    let mut ticker = r#loop.timer()?;
+    ticker.start(1000, 1000, |_: TimerHandle| println!("tick"))?;
+
+    SignalSet::graceful_shutdown(
+        &r#loop,
+        move |_: SignalHandle, signal: Signal| {
+            println!("Received {}, shutting down", signal);
+            ticker.close(());
+        },
+        |_: SignalHandle, signal: Signal| println!("Received {}, reloading", signal),
+    )?;
+
+    r#loop.run(RunMode::Default)?;
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Run:
+//!
+//! ```bash
+//! cargo run --example happy-eyeballs
+//! ```
+//!
+//! Starts a server on a loopback port, then connects to it with tcp_connect_host(), using a
+//! resolver which lists an unroutable address (from TEST-NET-1) before the loopback one. The
+//! attempt on the unroutable address won't complete, so after attempt_delay an attempt on the
+//! loopback address is started alongside it, wins, and the first attempt is abandoned. If the
+//! machine has no route to TEST-NET-1 at all, the first attempt fails immediately instead, and the
+//! loopback address is tried right away.
+
+extern crate libuv;
+use libuv::prelude::*;
+use libuv::{
+    AddrInfo, ResolveCB, Resolver, TcpBindFlags, TcpConnectOptions, TcpHandle, TimerHandle,
+};
+use std::net::{Ipv4Addr, SocketAddr};
+use std::rc::Rc;
+
+/// A Resolver which always returns the same addresses.
+struct FixedResolver(Vec<SocketAddr>);
+
+impl Resolver for FixedResolver {
+    fn resolve(
+        &self,
+        r#loop: &Loop,
+        _node: Option<&str>,
+        _service: Option<&str>,
+        hints: &AddrInfo,
+        mut cb: ResolveCB<'static>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let infos: Vec<AddrInfo> = self
+            .0
+            .iter()
+            .map(|addr| AddrInfo {
+                addr: Some(*addr),
+                ..hints.clone()
+            })
+            .collect();
+
+        // the callback must not be called before resolve() returns
+        let mut timer = r#loop.timer()?;
+        timer.start(0, 0, move |mut timer: TimerHandle| {
+            if let ResolveCB::CB(f) = &mut cb {
+                f(Ok(infos.clone()));
+            }
+            timer.close(());
+        })?;
+        Ok(())
+    }
+}
+
+fn on_new_connection(mut server: StreamHandle, status: libuv::Result<u32>) {
+    if let Err(e) = status {
+        eprintln!("New connection error: {}", e);
+        return;
+    }
+
+    if let Ok(client) = server.get_loop().tcp().as_mut() {
+        match server.accept(&mut client.to_stream()) {
+            Ok(_) => println!("Server accepted a connection"),
+            Err(e) => eprintln!("Error accepting connection: {}", e),
+        }
+        client.close(());
+    }
+    server.close(());
+}
+
+fn on_connect(mut server: TcpHandle, result: libuv::Result<TcpHandle>) {
+    match result {
+        Ok(mut handle) => {
+            match handle.getpeername() {
+                Ok(addr) => println!("Connected to {}", addr),
+                Err(e) => eprintln!("Connected, but getpeername() failed: {}", e),
+            }
+            handle.close(());
+        }
+        Err(e) => {
+            eprintln!("Could not connect: {}", e);
+            server.close(());
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut r#loop = Loop::default()?;
+
+    let mut server = r#loop.tcp()?;
+    let addr = (Ipv4Addr::LOCALHOST, 0).into();
+    server.bind(&addr, TcpBindFlags::empty())?;
+    server.listen(1, on_new_connection)?;
+    let port = server.getsockname()?.port();
+
+    let unroutable = (Ipv4Addr::new(192, 0, 2, 1), port).into();
+    let loopback = (Ipv4Addr::LOCALHOST, port).into();
+    let options = TcpConnectOptions {
+        attempt_delay: 100,
+        resolver: Some(Rc::new(FixedResolver(vec![unroutable, loopback]))),
+    };
+    r#loop.tcp_connect_host_with_options(
+        "example.invalid",
+        port,
+        options,
+        move |result: libuv::Result<TcpHandle>| on_connect(server, result),
+    )?;
+
+    r#loop.run(RunMode::Default)?;
+
+    Ok(())
+}
@@ -14,8 +14,7 @@
 
 extern crate libuv;
 use libuv::prelude::*;
-use libuv::{Buf, ReadonlyBuf, SignalHandle};
-use libuv_sys2::SIGINT;
+use libuv::{Buf, ReadonlyBuf, Signal, SignalHandle};
 
 #[cfg(windows)]
 const PIPENAME: &str = r"\\?\pipe\echo.sock";
@@ -85,7 +84,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             let _ = sig.stop();
             server.close(());
         },
-        SIGINT as _,
+        Signal::SIGINT,
     )?;
 
     r#loop.run(RunMode::Default)?;
@@ -7,15 +7,14 @@
 extern crate libuv;
 use libuv::prelude::*;
 use libuv::AsyncHandle;
-use std::cell::Cell;
-use std::rc::Rc;
+use std::sync::{Arc, Mutex};
 
 const SIZE: usize = 10240;
 
-fn fake_download(notify: &mut AsyncHandle, progress: &Rc<Cell<f32>>) {
+fn fake_download(notify: &mut AsyncHandle, progress: &Arc<Mutex<f32>>) {
     let mut downloaded = 0usize;
     while downloaded < SIZE {
-        progress.replace((downloaded as f32) * 100.0 / (SIZE as f32));
+        *progress.lock().unwrap() = (downloaded as f32) * 100.0 / (SIZE as f32);
         if let Err(e) = notify.send() {
             eprintln!("Failed to send async notification {}", e);
         }
@@ -29,14 +28,14 @@ fn after(notify: &mut AsyncHandle) {
     let _ = notify.close(());
 }
 
-fn print_progress(progress: &Rc<Cell<f32>>) {
-    println!("Downloaded {}%", progress.get());
+fn print_progress(progress: &Arc<Mutex<f32>>) {
+    println!("Downloaded {}%", *progress.lock().unwrap());
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut r#loop = Loop::default()?;
 
-    let progress = Rc::new(Cell::new(0f32));
+    let progress = Arc::new(Mutex::new(0f32));
     let progress2 = progress.clone();
     let mut notify = r#loop.r#async(move |_| print_progress(&progress))?;
     r#loop.queue_work(
@@ -13,12 +13,9 @@
 
 extern crate libuv;
 use libuv::prelude::*;
-use libuv::{getpid, SignalHandle};
+use libuv::{getpid, Signal, SignalHandle};
 use std::thread;
 
-#[cfg(not(windows))]
-use libuv_sys2::{SIGUSR1, SIGUSR2};
-
 #[cfg(not(windows))]
 fn signal_handler(mut handle: SignalHandle, signum: i32) {
     println!("Signal received {}", signum);
@@ -34,10 +31,10 @@ fn thread_worker1() {
         let mut r#loop = Loop::new()?;
 
         let mut sig1 = r#loop.signal()?;
-        sig1.start(signal_handler, SIGUSR1 as _)?;
+        sig1.start(signal_handler, Signal::SIGUSR1)?;
 
         let mut sig2 = r#loop.signal()?;
-        sig2.start(signal_handler, SIGUSR2 as _)?;
+        sig2.start(signal_handler, Signal::SIGUSR2)?;
 
         r#loop.run(RunMode::Default)?;
 
@@ -62,10 +59,10 @@ fn thread_worker2() {
         let mut loop2 = Loop::new()?;
 
         let mut sig1 = loop1.signal()?;
-        sig1.start(signal_handler, SIGUSR1 as _)?;
+        sig1.start(signal_handler, Signal::SIGUSR1)?;
 
         let mut sig2 = loop2.signal()?;
-        sig2.start(signal_handler, SIGUSR2 as _)?;
+        sig2.start(signal_handler, Signal::SIGUSR2)?;
 
         loop {
             let ret1 = loop1.run(RunMode::NoWait)?;
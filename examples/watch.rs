@@ -0,0 +1,38 @@
+//! Run:
+//!
+//! ```bash
+//! cargo run --example watch -- DIRECTORY
+//! ```
+//!
+//! Then create, modify, rename and delete files in the directory
+
+extern crate libuv;
+use libuv::prelude::*;
+use libuv::{WatchEvent, Watcher};
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() != 2 {
+        eprintln!("Usage: {} <directory>", args[0]);
+        return Ok(());
+    }
+
+    let mut r#loop = Loop::default()?;
+
+    let mut watcher = r#loop.watcher()?;
+    watcher.start(
+        &args[1],
+        |_: Watcher, events: Result<Vec<WatchEvent>, Box<dyn std::error::Error>>| match events {
+            Ok(events) => {
+                for event in events {
+                    println!("{:?}", event);
+                }
+            }
+            Err(e) => eprintln!("error while watching: {}", e),
+        },
+    )?;
+
+    r#loop.run(RunMode::Default)?;
+
+    Ok(())
+}
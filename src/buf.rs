@@ -255,6 +255,31 @@ impl Buf {
         Ok(Box::into_raw(buf).into_inner())
     }
 
+    /// Create a Buf holding a copy of exactly the given bytes. Unlike new_from_bytes(), no null
+    /// terminator is added, so the whole Buf can be written to a file as-is. An empty slice
+    /// creates an unallocated Buf of length 0.
+    pub(crate) fn new_from_bytes_exact(bytes: &[u8]) -> crate::Result<Buf> {
+        let len = bytes.len();
+        let base = if len == 0 {
+            std::ptr::null_mut()
+        } else {
+            let base = Buf::alloc(len)?;
+            unsafe { base.copy_from_nonoverlapping(bytes.as_ptr() as _, len) };
+            base
+        };
+
+        let buf = Box::new(unsafe { uv_buf_init(base, len as _) });
+        Ok(Box::into_raw(buf).into_inner())
+    }
+
+    /// Returns the first len bytes of the Buf. len must not exceed the length of the Buf.
+    pub(crate) fn as_bytes(&self, len: usize) -> &[u8] {
+        if len == 0 || !self.is_allocated() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts((*self.buf).base as *const u8, len) }
+    }
+
     /// Create a Buf with the given capacity - the memory is not initialized
     pub fn with_capacity(size: usize) -> crate::Result<Buf> {
         let base = Buf::alloc(size)?;
@@ -1,8 +1,9 @@
 include!("./error.inc.rs");
 
+use crate::FromInner;
 use std::ffi::CStr;
 use std::fmt::{Display, Formatter};
-use uv::{uv_err_name, uv_strerror};
+use uv::{uv_err_name, uv_strerror, uv_translate_sys_error};
 
 impl Error {
     /// The name of the error.
@@ -22,6 +23,26 @@ impl Error {
                 .into_owned()
         }
     }
+
+    /// The error from the last system call made on this thread (errno, or GetLastError() on
+    /// Windows), for code which calls the OS directly instead of going through libuv.
+    pub(crate) fn last_os_error() -> Error {
+        let code = std::io::Error::last_os_error().raw_os_error().unwrap_or(0);
+        Error::from_inner(unsafe { uv_translate_sys_error(code) } as uv::uv_errno_t)
+    }
+
+    /// The Error inside a boxed error from a function which can only fail in libuv, such as one
+    /// which only takes SocketAddrs, or paths which have already been checked. Anything else is a
+    /// bug in the caller, so it panics in debug builds; release builds report it as EINVAL.
+    pub(crate) fn from_uv_boxed(e: Box<dyn std::error::Error>) -> Error {
+        match e.downcast_ref::<Error>() {
+            Some(e) => *e,
+            None => {
+                debug_assert!(false, "expected an error from libuv, got: {}", e);
+                Error::EINVAL
+            }
+        }
+    }
 }
 
 impl Display for Error {
@@ -32,6 +53,13 @@ impl Display for Error {
 
 impl std::error::Error for Error {}
 
+/// For conversions which can't fail, such as those from a TimeSpec to itself.
+impl From<std::convert::Infallible> for Error {
+    fn from(e: std::convert::Infallible) -> Error {
+        match e {}
+    }
+}
+
 #[derive(Clone, Copy, Debug)]
 pub struct ConversionError {
     from: crate::HandleType,
@@ -51,3 +79,45 @@ impl Display for ConversionError {
 }
 
 impl std::error::Error for ConversionError {}
+
+/// The error returned by a BlockingTask which did not run to completion.
+pub enum TaskError {
+    /// The task was cancelled before a threadpool thread picked it up, so its function never ran.
+    Canceled,
+
+    /// The task's function panicked. This holds the panic's payload, which can be passed to
+    /// std::panic::resume_unwind() to carry on unwinding on the loop thread.
+    Panicked(Box<dyn std::any::Any + Send + 'static>),
+}
+
+impl TaskError {
+    /// Returns true if the task was cancelled.
+    pub fn is_canceled(&self) -> bool {
+        matches!(self, TaskError::Canceled)
+    }
+
+    /// Returns true if the task's function panicked.
+    pub fn is_panic(&self) -> bool {
+        matches!(self, TaskError::Panicked(_))
+    }
+}
+
+impl std::fmt::Debug for TaskError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::Canceled => write!(f, "Canceled"),
+            TaskError::Panicked(_) => write!(f, "Panicked(..)"),
+        }
+    }
+}
+
+impl Display for TaskError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskError::Canceled => write!(f, "Task was canceled"),
+            TaskError::Panicked(_) => write!(f, "Task panicked"),
+        }
+    }
+}
+
+impl std::error::Error for TaskError {}
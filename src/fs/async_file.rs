@@ -0,0 +1,422 @@
+use crate::{Buf, File, FsModeFlags, FsOpenFlags, FsReq};
+use std::cell::{Cell, RefCell};
+use std::io::SeekFrom;
+use std::rc::Rc;
+
+callbacks! {
+    pub AsyncFileOpenCB(file: crate::Result<AsyncFile>);
+    pub AsyncFileCB(file: AsyncFile, result: crate::Result<u64>);
+    pub AsyncFileReadCB(file: AsyncFile, result: crate::Result<Vec<u8>>);
+}
+
+/// The smallest and largest reads done by read_to_end().
+pub(crate) const MIN_READ: usize = 64 * 1024;
+pub(crate) const MAX_READ: usize = 16 * 1024 * 1024;
+
+struct FileState {
+    r#loop: crate::Loop,
+    file: File,
+    pos: Cell<u64>,
+    closed: Cell<bool>,
+
+    /// Set if the file was opened with FsOpenFlags::APPEND, so writes ignore the position
+    append: bool,
+}
+
+impl Drop for FileState {
+    fn drop(&mut self) {
+        if !self.closed.get() {
+            let _ = self.r#loop.fs_close(self.file, |_: FsReq| ());
+        }
+    }
+}
+
+/// State for read_to_end(), which may take several reads to complete.
+struct ReadToEnd {
+    data: Vec<u8>,
+    cb: AsyncFileReadCB<'static>,
+}
+
+/// State for write_all(), which may take several writes to complete.
+struct WriteAll {
+    data: Vec<u8>,
+    written: usize,
+    cb: AsyncFileCB<'static>,
+}
+
+/// An open file with a cursor. Reads and writes start at the current position and advance it,
+/// like read(2) and write(2) on a file descriptor, but the position is tracked by the AsyncFile
+/// itself so that it can be changed with seek().
+///
+/// AsyncFiles are cheap to clone: clones share the same file and position. The file is closed
+/// (asynchronously, with fs_close()) when the last clone is dropped, unless it was already closed
+/// with close(). An AsyncFile must therefore not outlive its loop.
+///
+/// Only one operation should be in progress at a time: the position is advanced when each
+/// operation completes, so concurrent reads or writes would all start at the same position.
+///
+/// If the file was opened with open() or open_sync() and FsOpenFlags::APPEND, writes always go to
+/// the end of the file, whatever the position.
+#[derive(Clone)]
+pub struct AsyncFile {
+    state: Rc<FileState>,
+}
+
+impl AsyncFile {
+    /// Open a file, then call cb with the AsyncFile. See Loop::fs_open().
+    pub fn open<CB: Into<AsyncFileOpenCB<'static>>>(
+        r#loop: &crate::Loop,
+        path: &str,
+        flags: FsOpenFlags,
+        mode: FsModeFlags,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        let file_loop = r#loop.clone();
+        r#loop.fs_open(path, flags, mode, move |req: FsReq| {
+            cb.call(
+                req.result()
+                    .map(|file| AsyncFile::new(&file_loop, file as _, flags)),
+            )
+        })?;
+        Ok(())
+    }
+
+    /// Open a file synchronously. See Loop::fs_open_sync().
+    pub fn open_sync(
+        r#loop: &crate::Loop,
+        path: &str,
+        flags: FsOpenFlags,
+        mode: FsModeFlags,
+    ) -> Result<AsyncFile, Box<dyn std::error::Error>> {
+        let file = r#loop.fs_open_sync(path, flags, mode)?;
+        Ok(AsyncFile::new(r#loop, file, flags))
+    }
+
+    /// Wrap an already open file. The AsyncFile takes ownership of the file, and will close it.
+    /// The position starts at 0.
+    pub fn from_file(r#loop: &crate::Loop, file: File) -> AsyncFile {
+        AsyncFile::new(r#loop, file, FsOpenFlags::empty())
+    }
+
+    /// Wrap a file opened with the given flags.
+    fn new(r#loop: &crate::Loop, file: File, flags: FsOpenFlags) -> AsyncFile {
+        AsyncFile {
+            state: Rc::new(FileState {
+                r#loop: r#loop.clone(),
+                file,
+                pos: Cell::new(0),
+                closed: Cell::new(false),
+                append: flags.contains(FsOpenFlags::APPEND),
+            }),
+        }
+    }
+
+    /// Returns the underlying file.
+    pub fn file(&self) -> File {
+        self.state.file
+    }
+
+    /// Returns the current position.
+    pub fn position(&self) -> u64 {
+        self.state.pos.get()
+    }
+
+    /// Returns EBADF if the file has been closed.
+    fn check_open(&self) -> crate::Result<()> {
+        if self.state.closed.get() {
+            Err(crate::Error::EBADF)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Read up to len bytes at the current position. The callback is passed the data that was
+    /// read, which is empty at the end of the file.
+    pub fn read<CB: Into<AsyncFileReadCB<'static>>>(
+        &self,
+        len: usize,
+        cb: CB,
+    ) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        self.read_chunk(len, move |file, result| cb.call(file, result))
+    }
+
+    /// Read one chunk at the current position, advancing it by the number of bytes read.
+    fn read_chunk<F>(&self, len: usize, done: F) -> crate::Result<()>
+    where
+        F: FnOnce(AsyncFile, crate::Result<Vec<u8>>) + 'static,
+    {
+        let mut buf = Buf::with_capacity(len.max(1))?;
+        let file = self.clone();
+        let mut done = Some(done);
+        let result = self.state.r#loop.fs_read(
+            self.state.file,
+            &[buf],
+            self.state.pos.get() as _,
+            move |req: FsReq| {
+                let result = req.result().map(|n| {
+                    file.state.pos.set(file.state.pos.get() + n as u64);
+                    buf.as_bytes(n).to_vec()
+                });
+                buf.destroy();
+                if let Some(done) = done.take() {
+                    done(file.clone(), result);
+                }
+            },
+        );
+        if let Err(e) = result {
+            buf.destroy();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Read from the current position to the end of the file. The file's size is used to size
+    /// the buffer up front.
+    pub fn read_to_end<CB: Into<AsyncFileReadCB<'static>>>(&self, cb: CB) -> crate::Result<()> {
+        self.check_open()?;
+        let op = Rc::new(RefCell::new(ReadToEnd {
+            data: Vec::new(),
+            cb: cb.into(),
+        }));
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_fstat(self.state.file, move |req: FsReq| {
+                if req.result().is_ok() {
+                    let hint = req.stat().size.saturating_sub(file.position());
+                    op.borrow_mut().data.reserve(hint as _);
+                }
+                if let Err(e) = file.read_to_end_step(op.clone()) {
+                    file.finish_read_to_end(&op, Err(e));
+                }
+            })?;
+        Ok(())
+    }
+
+    fn read_to_end_step(&self, op: Rc<RefCell<ReadToEnd>>) -> crate::Result<()> {
+        let want = {
+            let op = op.borrow();
+            (op.data.capacity() - op.data.len())
+                .max(MIN_READ)
+                .min(MAX_READ)
+        };
+        self.read_chunk(want, move |file, result| {
+            let result = match result {
+                Ok(chunk) if chunk.is_empty() => {
+                    let data = std::mem::take(&mut op.borrow_mut().data);
+                    return file.finish_read_to_end(&op, Ok(data));
+                }
+                Ok(chunk) => {
+                    op.borrow_mut().data.extend_from_slice(&chunk);
+                    file.read_to_end_step(op.clone())
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                file.finish_read_to_end(&op, Err(e));
+            }
+        })
+    }
+
+    fn finish_read_to_end(&self, op: &Rc<RefCell<ReadToEnd>>, result: crate::Result<Vec<u8>>) {
+        let mut cb = std::mem::take(&mut op.borrow_mut().cb);
+        cb.call(self.clone(), result);
+    }
+
+    /// Write all of data at the current position, issuing more writes if the file accepts only
+    /// part of it. The callback is passed the number of bytes written.
+    pub fn write_all<CB: Into<AsyncFileCB<'static>>>(
+        &self,
+        data: &[u8],
+        cb: CB,
+    ) -> crate::Result<()> {
+        self.check_open()?;
+        let op = Rc::new(RefCell::new(WriteAll {
+            data: data.to_vec(),
+            written: 0,
+            cb: cb.into(),
+        }));
+        self.write_all_step(op)
+    }
+
+    fn write_all_step(&self, op: Rc<RefCell<WriteAll>>) -> crate::Result<()> {
+        let mut buf = {
+            let op = op.borrow();
+            Buf::new_from_bytes_exact(&op.data[op.written..])?
+        };
+        let file = self.clone();
+        let result = self.state.r#loop.fs_write(
+            self.state.file,
+            &[buf],
+            self.write_offset(),
+            move |req: FsReq| {
+                buf.destroy();
+                let result = req.result().and_then(|n| {
+                    file.state.pos.set(file.state.pos.get() + n as u64);
+                    let mut state = op.borrow_mut();
+                    state.written += n;
+                    if state.written == state.data.len() {
+                        Ok(true)
+                    } else if n == 0 {
+                        Err(crate::Error::EIO)
+                    } else {
+                        Ok(false)
+                    }
+                });
+                let result = match result {
+                    Ok(true) => Ok(op.borrow().written as u64),
+                    Ok(false) => match file.write_all_step(op.clone()) {
+                        Ok(_) => return,
+                        Err(e) => Err(e),
+                    },
+                    Err(e) => Err(e),
+                };
+                let mut cb = std::mem::take(&mut op.borrow_mut().cb);
+                cb.call(file.clone(), result);
+            },
+        );
+        if let Err(e) = result {
+            buf.destroy();
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// The offset for the next write: the position, or -1 to write at the file descriptor's
+    /// offset, which O_APPEND keeps at the end of the file.
+    fn write_offset(&self) -> i64 {
+        if self.state.append {
+            -1
+        } else {
+            self.state.pos.get() as _
+        }
+    }
+
+    /// Change the position, returning the new position. Seeking to a negative position fails with
+    /// EINVAL. Seeking relative to the end of the file requires the file's size, which can't be
+    /// retrieved without blocking the loop thread, so SeekFrom::End also fails with EINVAL; use
+    /// seek_end() instead.
+    pub fn seek(&self, pos: SeekFrom) -> crate::Result<u64> {
+        self.check_open()?;
+        let pos = match pos {
+            SeekFrom::Start(pos) => pos,
+            SeekFrom::Current(offset) => offset_position(self.state.pos.get(), offset)?,
+            SeekFrom::End(_) => return Err(crate::Error::EINVAL),
+        };
+        self.state.pos.set(pos);
+        Ok(pos)
+    }
+
+    /// Change the position to offset bytes from the end of the file, which is found with
+    /// fs_fstat(). The callback is passed the new position. Seeking to a negative position fails
+    /// with EINVAL, and leaves the position unchanged.
+    pub fn seek_end<CB: Into<AsyncFileCB<'static>>>(
+        &self,
+        offset: i64,
+        cb: CB,
+    ) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_fstat(self.state.file, move |req: FsReq| {
+                let result = req
+                    .result()
+                    .and_then(|_| offset_position(req.stat().size, offset))
+                    .map(|pos| {
+                        file.state.pos.set(pos);
+                        pos
+                    });
+                cb.call(file.clone(), result)
+            })?;
+        Ok(())
+    }
+
+    /// Truncate or extend the file to len bytes. The position is not changed. The callback is
+    /// passed the new length.
+    pub fn set_len<CB: Into<AsyncFileCB<'static>>>(&self, len: u64, cb: CB) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_ftruncate(self.state.file, len as _, move |req: FsReq| {
+                cb.call(file.clone(), req.result().map(|_| len))
+            })?;
+        Ok(())
+    }
+
+    /// Flush the file's data and metadata to disk. See Loop::fs_fsync().
+    pub fn sync_all<CB: Into<AsyncFileCB<'static>>>(&self, cb: CB) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_fsync(self.state.file, move |req: FsReq| {
+                cb.call(file.clone(), req.result().map(|_| 0))
+            })?;
+        Ok(())
+    }
+
+    /// Flush the file's data, but not necessarily its metadata, to disk. See
+    /// Loop::fs_fdatasync().
+    pub fn sync_data<CB: Into<AsyncFileCB<'static>>>(&self, cb: CB) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_fdatasync(self.state.file, move |req: FsReq| {
+                cb.call(file.clone(), req.result().map(|_| 0))
+            })?;
+        Ok(())
+    }
+
+    /// Close the file. Any further operations fail with EBADF.
+    pub fn close<CB: Into<AsyncFileCB<'static>>>(&self, cb: CB) -> crate::Result<()> {
+        self.check_open()?;
+        let mut cb = cb.into();
+        let file = self.clone();
+        self.state
+            .r#loop
+            .fs_close(self.state.file, move |req: FsReq| {
+                cb.call(file.clone(), req.result().map(|_| 0))
+            })?;
+        self.state.closed.set(true);
+        Ok(())
+    }
+}
+
+impl crate::watchdog::WatchdogSource for AsyncFile {
+    fn watchdog_source(&self) -> crate::CallbackSource {
+        crate::CallbackSource::Req(crate::ReqType::FS)
+    }
+}
+
+impl crate::Loop {
+    /// Open a file as an AsyncFile. See AsyncFile::open().
+    pub fn fs_open_file<CB: Into<AsyncFileOpenCB<'static>>>(
+        &self,
+        path: &str,
+        flags: FsOpenFlags,
+        mode: FsModeFlags,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        AsyncFile::open(self, path, flags, mode, cb)
+    }
+}
+
+/// Returns base moved by offset, or EINVAL if that would be negative or overflow.
+fn offset_position(base: u64, offset: i64) -> crate::Result<u64> {
+    if offset >= 0 {
+        base.checked_add(offset as u64)
+    } else {
+        base.checked_sub(offset.wrapping_neg() as u64)
+    }
+    .ok_or(crate::Error::EINVAL)
+}
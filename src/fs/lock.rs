@@ -0,0 +1,189 @@
+use crate::{File, WorkReq};
+
+callbacks! {
+    pub FsLockCB(result: crate::Result<LockGuard>);
+}
+
+/// The kind of lock to take.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockKind {
+    /// A shared (read) lock. Any number of shared locks may be held at once.
+    Shared,
+
+    /// An exclusive (write) lock, which excludes every other lock.
+    Exclusive,
+}
+
+/// What a lock covers.
+#[derive(Clone, Copy, Debug)]
+enum LockRange {
+    /// The whole file, with flock(2).
+    File,
+
+    /// A range of bytes, with fcntl(2) record locks. A len of 0 extends to the end of the file,
+    /// however large it grows.
+    Bytes { start: u64, len: u64 },
+}
+
+/// Lock, or unlock if kind is None. If wait is false and the lock is held elsewhere, this fails
+/// with EAGAIN instead of blocking.
+fn lock_sys(file: File, range: LockRange, kind: Option<LockKind>, wait: bool) -> crate::Result<()> {
+    loop {
+        let ret = match range {
+            LockRange::File => {
+                let mut op = match kind {
+                    Some(LockKind::Shared) => libc::LOCK_SH,
+                    Some(LockKind::Exclusive) => libc::LOCK_EX,
+                    None => libc::LOCK_UN,
+                };
+                if !wait {
+                    op |= libc::LOCK_NB;
+                }
+                unsafe { libc::flock(file, op) }
+            }
+            LockRange::Bytes { start, len } => {
+                let mut fl: libc::flock = unsafe { std::mem::zeroed() };
+                fl.l_type = match kind {
+                    Some(LockKind::Shared) => libc::F_RDLCK,
+                    Some(LockKind::Exclusive) => libc::F_WRLCK,
+                    None => libc::F_UNLCK,
+                } as _;
+                fl.l_whence = libc::SEEK_SET as _;
+                fl.l_start = start as _;
+                fl.l_len = len as _;
+                let cmd = if wait { libc::F_SETLKW } else { libc::F_SETLK };
+                unsafe { libc::fcntl(file, cmd, &fl as *const libc::flock) }
+            }
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+
+        match crate::Error::last_os_error() {
+            crate::Error::EINTR => continue,
+            // fcntl reports a conflicting lock as either EACCES or EAGAIN, depending on platform
+            crate::Error::EACCES => return Err(crate::Error::EAGAIN),
+            e => return Err(e),
+        }
+    }
+}
+
+/// A lock held on a file, or on a range of bytes in a file. The lock is released when the guard
+/// is dropped.
+///
+/// Note that closing the file releases its locks too: flock() locks are released when the last
+/// descriptor for the open file is closed, and record locks are released when the process closes
+/// *any* descriptor for the file.
+pub struct LockGuard {
+    file: File,
+    kind: LockKind,
+    range: LockRange,
+    locked: bool,
+}
+
+impl LockGuard {
+    fn new(file: File, kind: LockKind, range: LockRange) -> LockGuard {
+        LockGuard {
+            file,
+            kind,
+            range,
+            locked: true,
+        }
+    }
+
+    /// Returns the locked file.
+    pub fn file(&self) -> File {
+        self.file
+    }
+
+    /// Returns the kind of lock held.
+    pub fn kind(&self) -> LockKind {
+        self.kind
+    }
+
+    /// Release the lock, returning any error. Releasing a lock never blocks.
+    pub fn unlock(mut self) -> crate::Result<()> {
+        self.locked = false;
+        lock_sys(self.file, self.range, None, false)
+    }
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        if self.locked {
+            let _ = lock_sys(self.file, self.range, None, false);
+        }
+    }
+}
+
+impl crate::Loop {
+    /// Take the lock on the threadpool, since acquiring it may block.
+    fn queue_lock<CB: Into<FsLockCB<'static>>>(
+        &self,
+        file: File,
+        kind: LockKind,
+        range: LockRange,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        let mut cb = cb.into();
+        self.queue_blocking(
+            move || lock_sys(file, range, Some(kind), true),
+            move |result: crate::Result<()>| {
+                cb.call(result.map(|_| LockGuard::new(file, kind, range)))
+            },
+        )
+    }
+
+    /// Lock the whole file with flock(2), waiting until the lock is available. The lock is
+    /// acquired on the threadpool, so the loop is never blocked; the callback is passed a
+    /// LockGuard which releases the lock when dropped.
+    ///
+    /// Note: while waiting, the lock occupies a thread in the threadpool, which delays all other
+    /// threadpool work (including fs operations) if enough locks are waiting at once. The request
+    /// can be cancelled with Req::cancel() until it starts waiting, but not after.
+    pub fn fs_flock<CB: Into<FsLockCB<'static>>>(
+        &self,
+        file: File,
+        kind: LockKind,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        self.queue_lock(file, kind, LockRange::File, cb)
+    }
+
+    /// Try to lock the whole file with flock(2) without waiting. Fails with EAGAIN if the lock is
+    /// held elsewhere.
+    pub fn fs_try_flock(&self, file: File, kind: LockKind) -> crate::Result<LockGuard> {
+        lock_sys(file, LockRange::File, Some(kind), false)
+            .map(|_| LockGuard::new(file, kind, LockRange::File))
+    }
+
+    /// Lock len bytes of the file, starting at start, with a POSIX record lock (fcntl(2)),
+    /// waiting until the lock is available. A len of 0 locks to the end of the file, however large
+    /// it grows. See fs_flock() for how the lock is acquired.
+    ///
+    /// Note: record locks are owned by the process, not the file descriptor, so they do not
+    /// exclude other locks taken by the same process.
+    pub fn fs_lock_range<CB: Into<FsLockCB<'static>>>(
+        &self,
+        file: File,
+        kind: LockKind,
+        start: u64,
+        len: u64,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        self.queue_lock(file, kind, LockRange::Bytes { start, len }, cb)
+    }
+
+    /// Try to lock len bytes of the file, starting at start, without waiting. Fails with EAGAIN
+    /// if the range is locked elsewhere. See fs_lock_range().
+    pub fn fs_try_lock_range(
+        &self,
+        file: File,
+        kind: LockKind,
+        start: u64,
+        len: u64,
+    ) -> crate::Result<LockGuard> {
+        let range = LockRange::Bytes { start, len };
+        lock_sys(file, range, Some(kind), false).map(|_| LockGuard::new(file, kind, range))
+    }
+}
@@ -0,0 +1,317 @@
+use crate::{File, WorkReq};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+callbacks! {
+    pub FsMmapCB(result: crate::Result<Mmap>);
+    pub FsMmapMutCB(result: crate::Result<MmapMut>);
+    pub FsMsyncCB(result: crate::Result<()>);
+}
+
+/// Options for fs_mmap() and fs_mmap_mut().
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MmapOptions {
+    /// Where the mapping starts in the file. The offset does not need to be a multiple of the page
+    /// size.
+    pub offset: u64,
+
+    /// The number of bytes to map. If None, the file is mapped from offset to its current end.
+    pub len: Option<usize>,
+
+    /// Advise the kernel with MADV_WILLNEED that the whole mapping will be needed soon, so that
+    /// it starts reading the file into memory before the pages are touched.
+    pub willneed: bool,
+}
+
+/// A mapped region of memory. The region is unmapped when the Mapping is dropped.
+struct Mapping {
+    base: *mut libc::c_void,
+    map_len: usize,
+
+    /// The distance from base to the requested offset, which need not be page-aligned
+    delta: usize,
+    len: usize,
+}
+
+// the mapping is plain memory which may be read or written from any thread
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(file: File, options: MmapOptions, writable: bool) -> crate::Result<Mapping> {
+        let len = match options.len {
+            Some(len) => len,
+            None => {
+                let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+                if unsafe { libc::fstat(file, &mut stat) } != 0 {
+                    return Err(crate::Error::last_os_error());
+                }
+                (stat.st_size as u64).saturating_sub(options.offset) as usize
+            }
+        };
+
+        // mmap() fails for empty mappings, but an empty file is not an error
+        if len == 0 {
+            return Ok(Mapping {
+                base: std::ptr::null_mut(),
+                map_len: 0,
+                delta: 0,
+                len: 0,
+            });
+        }
+
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as u64;
+        let delta = (options.offset % page_size) as usize;
+        let map_len = len.checked_add(delta).ok_or(crate::Error::EINVAL)?;
+        let prot = if writable {
+            libc::PROT_READ | libc::PROT_WRITE
+        } else {
+            libc::PROT_READ
+        };
+        let base = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                map_len,
+                prot,
+                libc::MAP_SHARED,
+                file,
+                (options.offset - delta as u64) as _,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(crate::Error::last_os_error());
+        }
+
+        let mapping = Mapping {
+            base,
+            map_len,
+            delta,
+            len,
+        };
+        if options.willneed {
+            // the advice is only a hint, so failing to give it is not an error
+            unsafe { libc::madvise(base, map_len, libc::MADV_WILLNEED) };
+        }
+        Ok(mapping)
+    }
+
+    fn as_ptr(&self) -> *mut u8 {
+        if self.base.is_null() {
+            std::ptr::NonNull::dangling().as_ptr()
+        } else {
+            unsafe { (self.base as *mut u8).add(self.delta) }
+        }
+    }
+
+    /// Flush changes to the file with msync(), waiting until they have been written.
+    fn sync(&self) -> crate::Result<()> {
+        if self.map_len == 0 {
+            return Ok(());
+        }
+        if unsafe { libc::msync(self.base, self.map_len, libc::MS_SYNC) } != 0 {
+            return Err(crate::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        if self.map_len > 0 {
+            unsafe { libc::munmap(self.base, self.map_len) };
+        }
+    }
+}
+
+/// A read-only memory map of a file, created with Loop::fs_mmap(). Mmap dereferences to the
+/// mapped bytes, and unmaps them when dropped.
+///
+/// The map is shared with the file: changes made to the file by this or any other process are
+/// visible through the map. If the file is truncated while it is mapped, accessing the pages past
+/// the new end of the file raises SIGBUS.
+pub struct Mmap {
+    mapping: Arc<Mapping>,
+}
+
+impl Mmap {
+    /// Returns the number of bytes mapped.
+    pub fn len(&self) -> usize {
+        self.mapping.len
+    }
+
+    /// Returns true if nothing is mapped.
+    pub fn is_empty(&self) -> bool {
+        self.mapping.len == 0
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mapping.as_ptr(), self.mapping.len) }
+    }
+}
+
+impl AsRef<[u8]> for Mmap {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+/// A writable memory map of a file, created with Loop::fs_mmap_mut(). MmapMut dereferences to the
+/// mapped bytes, and unmaps them when dropped. Writes are carried through to the file, but are
+/// not guaranteed to reach the disk until flushed with Loop::fs_msync().
+///
+/// See Mmap for caveats about sharing the map with the file.
+pub struct MmapMut {
+    mapping: Arc<Mapping>,
+}
+
+impl MmapMut {
+    /// Returns the number of bytes mapped.
+    pub fn len(&self) -> usize {
+        self.mapping.len
+    }
+
+    /// Returns true if nothing is mapped.
+    pub fn is_empty(&self) -> bool {
+        self.mapping.len == 0
+    }
+
+    /// Make the map read-only. Changes which have not been flushed may still be written to the
+    /// file at any time.
+    pub fn make_read_only(self) -> Mmap {
+        Mmap {
+            mapping: self.mapping,
+        }
+    }
+}
+
+impl Deref for MmapMut {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.mapping.as_ptr(), self.mapping.len) }
+    }
+}
+
+impl DerefMut for MmapMut {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.mapping.as_ptr(), self.mapping.len) }
+    }
+}
+
+impl AsRef<[u8]> for MmapMut {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl AsMut<[u8]> for MmapMut {
+    fn as_mut(&mut self) -> &mut [u8] {
+        self
+    }
+}
+
+impl crate::Loop {
+    /// Map a file into memory, read-only. The file must have been opened for reading. The map is
+    /// made (and prefetched, if options.willneed is set) on the threadpool; the callback is passed
+    /// the Mmap. The file may be closed once the map has been made.
+    ///
+    /// # Safety
+    ///
+    /// The map is shared with the file, so the bytes behind the Mmap can change underneath it. The
+    /// caller must ensure that the file is not modified, by this or any other process, and not
+    /// truncated (which makes accessing the pages past the new end raise SIGBUS), while it is
+    /// mapped.
+    pub unsafe fn fs_mmap<CB: Into<FsMmapCB<'static>>>(
+        &self,
+        file: File,
+        options: MmapOptions,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        let mut cb = cb.into();
+        self.queue_blocking(
+            move || Mapping::new(file, options, false),
+            move |result: crate::Result<Mapping>| {
+                cb.call(result.map(|mapping| Mmap {
+                    mapping: Arc::new(mapping),
+                }))
+            },
+        )
+    }
+
+    /// Synchronous version of fs_mmap().
+    ///
+    /// # Safety
+    ///
+    /// See fs_mmap().
+    pub unsafe fn fs_mmap_sync(&self, file: File, options: MmapOptions) -> crate::Result<Mmap> {
+        Mapping::new(file, options, false).map(|mapping| Mmap {
+            mapping: Arc::new(mapping),
+        })
+    }
+
+    /// Map a file into memory, read-write. The file must have been opened for reading and
+    /// writing. See fs_mmap().
+    ///
+    /// # Safety
+    ///
+    /// The map is shared with the file, so the bytes behind the MmapMut can change underneath it.
+    /// The caller must ensure that the file is not modified, other than through this map, and not
+    /// truncated (which makes accessing the pages past the new end raise SIGBUS), while it is
+    /// mapped.
+    pub unsafe fn fs_mmap_mut<CB: Into<FsMmapMutCB<'static>>>(
+        &self,
+        file: File,
+        options: MmapOptions,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        let mut cb = cb.into();
+        self.queue_blocking(
+            move || Mapping::new(file, options, true),
+            move |result: crate::Result<Mapping>| {
+                cb.call(result.map(|mapping| MmapMut {
+                    mapping: Arc::new(mapping),
+                }))
+            },
+        )
+    }
+
+    /// Synchronous version of fs_mmap_mut().
+    ///
+    /// # Safety
+    ///
+    /// See fs_mmap_mut().
+    pub unsafe fn fs_mmap_mut_sync(
+        &self,
+        file: File,
+        options: MmapOptions,
+    ) -> crate::Result<MmapMut> {
+        Mapping::new(file, options, true).map(|mapping| MmapMut {
+            mapping: Arc::new(mapping),
+        })
+    }
+
+    /// Flush changes made through the map to the file on disk, like fs_fsync() does for writes.
+    /// The flush runs on the threadpool; the map stays mapped until it completes, even if the
+    /// MmapMut is dropped in the meantime.
+    pub fn fs_msync<CB: Into<FsMsyncCB<'static>>>(
+        &self,
+        map: &MmapMut,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        let mut cb = cb.into();
+        let mapping = map.mapping.clone();
+        self.queue_blocking(
+            move || mapping.sync(),
+            move |result: crate::Result<()>| cb.call(result),
+        )
+    }
+
+    /// Synchronous version of fs_msync().
+    pub fn fs_msync_sync(&self, map: &MmapMut) -> crate::Result<()> {
+        map.mapping.sync()
+    }
+}
@@ -15,25 +15,42 @@ include!("./fs_symlink_flags.inc.rs");
 include!("./fs_types.inc.rs");
 
 use crate::{FromInner, FsReq, Inner, IntoInner};
+use std::convert::TryInto;
 use std::ffi::CString;
 use uv::{
     uv_fs_access, uv_fs_chmod, uv_fs_chown, uv_fs_close, uv_fs_closedir, uv_fs_copyfile,
     uv_fs_fchmod, uv_fs_fchown, uv_fs_fdatasync, uv_fs_fstat, uv_fs_fsync, uv_fs_ftruncate,
-    uv_fs_futime, uv_fs_lchown, uv_fs_link, uv_fs_lstat, uv_fs_mkdir, uv_fs_mkdtemp, uv_fs_mkstemp,
-    uv_fs_open, uv_fs_opendir, uv_fs_read, uv_fs_readdir, uv_fs_readlink, uv_fs_realpath,
-    uv_fs_rename, uv_fs_rmdir, uv_fs_scandir, uv_fs_scandir_next, uv_fs_sendfile, uv_fs_stat,
-    uv_fs_statfs, uv_fs_symlink, uv_fs_unlink, uv_fs_utime, uv_fs_write,
+    uv_fs_futime, uv_fs_lchown, uv_fs_link, uv_fs_lstat, uv_fs_lutime, uv_fs_mkdir, uv_fs_mkdtemp,
+    uv_fs_mkstemp, uv_fs_open, uv_fs_opendir, uv_fs_read, uv_fs_readdir, uv_fs_readlink,
+    uv_fs_realpath, uv_fs_rename, uv_fs_rmdir, uv_fs_scandir, uv_fs_scandir_next, uv_fs_sendfile,
+    uv_fs_stat, uv_fs_statfs, uv_fs_symlink, uv_fs_unlink, uv_fs_utime, uv_fs_write,
 };
 
+pub mod async_file;
+pub use async_file::*;
+
 pub mod dir;
 pub use dir::*;
 
 pub mod dirent;
 pub use dirent::*;
 
+#[cfg(unix)]
+pub mod lock;
+#[cfg(unix)]
+pub use lock::*;
+
 pub mod misc;
 pub use misc::*;
 
+#[cfg(unix)]
+pub mod mmap;
+#[cfg(unix)]
+pub use mmap::*;
+
+pub mod read_dir;
+pub use read_dir::*;
+
 pub mod stat;
 pub use stat::*;
 
@@ -43,6 +60,17 @@ pub use statfs::*;
 pub mod timespec;
 pub use timespec::*;
 
+pub mod watcher;
+pub use watcher::*;
+
+pub mod whole_file;
+pub use whole_file::*;
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub mod xattr;
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "macos"))]
+pub use xattr::*;
+
 type FsReqResult = crate::Result<FsReq>;
 type FsReqErrResult = Result<FsReq, Box<dyn std::error::Error>>;
 type SyncResult = crate::Result<usize>;
@@ -87,6 +115,17 @@ fn destroy_req_return_boxed_result(req: FsReq) -> SyncErrResult {
     destroy_req_return_result(req).map_err(|e| Box::new(e) as _)
 }
 
+/// Converts a time passed to fs_utime() and friends to fractional seconds since the epoch
+fn utime_secs<T>(time: T) -> crate::Result<f64>
+where
+    T: TryInto<TimeSpec>,
+    T::Error: Into<crate::Error>,
+{
+    time.try_into()
+        .map(|ts| ts.as_secs_f64())
+        .map_err(Into::into)
+}
+
 impl crate::Loop {
     /// Private implementation for fs_close()
     fn _fs_close<CB: Into<crate::FsCB<'static>>>(&self, file: File, cb: CB) -> FsReqResult {
@@ -597,7 +636,11 @@ impl crate::Loop {
         flags: FsOpenFlags,
         mut cb: impl FnMut(ScandirIter) + 'static,
     ) -> FsReqErrResult {
-        self._fs_scandir(path, flags, move |req| cb(ScandirIter { req }))
+        self._fs_scandir(path, flags, move |req| {
+            // the request is destroyed once the callback returns, so the iterator must not
+            // destroy it as well
+            cb(ScandirIter::new(req))
+        })
     }
 
     /// Returns a ScandirIter that can be used to iterate over the contents of a directory.
@@ -607,7 +650,7 @@ impl crate::Loop {
         flags: FsOpenFlags,
     ) -> Result<ScandirIter, Box<dyn std::error::Error>> {
         self._fs_scandir(path, flags, ())
-            .map(|req| ScandirIter { req })
+            .map(|req| ScandirIter { req, owned: true })
     }
 
     /// Private implementation for fs_stat()
@@ -1156,24 +1199,116 @@ impl crate::Loop {
 
     /// Equivalent to utime(2).
     ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
+    /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
+    /// versions but will return ENOSYS.
+    pub fn fs_utime<T: TryInto<TimeSpec>, CB: Into<crate::FsCB<'static>>>(
+        &self,
+        path: &str,
+        atime: T,
+        mtime: T,
+        cb: CB,
+    ) -> FsReqErrResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_utime(path, utime_secs(atime)?, utime_secs(mtime)?, cb)
+    }
+
+    /// Equivalent to utime(2).
+    ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
     /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
     /// versions but will return ENOSYS.
-    pub fn fs_utime<CB: Into<crate::FsCB<'static>>>(
+    pub fn fs_utime_sync<T: TryInto<TimeSpec>>(
+        &self,
+        path: &str,
+        atime: T,
+        mtime: T,
+    ) -> SyncErrResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_utime(path, utime_secs(atime)?, utime_secs(mtime)?, ())
+            .and_then(destroy_req_return_boxed_result)
+    }
+
+    /// Private implementation for fs_lutime()
+    fn _fs_lutime<CB: Into<crate::FsCB<'static>>>(
         &self,
         path: &str,
         atime: f64,
         mtime: f64,
         cb: CB,
     ) -> FsReqErrResult {
-        self._fs_utime(path, atime, mtime, cb)
+        let cb = cb.into();
+        let uv_cb = use_c_callback!(crate::uv_fs_cb, cb);
+        let path = CString::new(path)?;
+        let mut req = FsReq::new(cb)?;
+        let result = crate::uvret(unsafe {
+            uv_fs_lutime(
+                self.into_inner(),
+                req.inner(),
+                path.as_ptr(),
+                atime,
+                mtime,
+                uv_cb,
+            )
+        })
+        .map_err(|e| Box::new(e) as _);
+        if result.is_err() {
+            req.destroy();
+        }
+        result.map(|_| req)
     }
 
-    /// Equivalent to utime(2).
+    /// Equivalent to lutimes(3). If path is a symbolic link, the times of the link itself are
+    /// changed, rather than the times of the file it points to.
+    ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
+    /// Note: z/OS: not implemented, will return ENOSYS.
+    ///
+    /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
+    /// versions but will return ENOSYS.
+    pub fn fs_lutime<T: TryInto<TimeSpec>, CB: Into<crate::FsCB<'static>>>(
+        &self,
+        path: &str,
+        atime: T,
+        mtime: T,
+        cb: CB,
+    ) -> FsReqErrResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_lutime(path, utime_secs(atime)?, utime_secs(mtime)?, cb)
+    }
+
+    /// Equivalent to lutimes(3). If path is a symbolic link, the times of the link itself are
+    /// changed, rather than the times of the file it points to.
+    ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
+    /// Note: z/OS: not implemented, will return ENOSYS.
     ///
     /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
     /// versions but will return ENOSYS.
-    pub fn fs_utime_sync(&self, path: &str, atime: f64, mtime: f64) -> SyncErrResult {
-        self._fs_utime(path, atime, mtime, ())
+    pub fn fs_lutime_sync<T: TryInto<TimeSpec>>(
+        &self,
+        path: &str,
+        atime: T,
+        mtime: T,
+    ) -> SyncErrResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_lutime(path, utime_secs(atime)?, utime_secs(mtime)?, ())
             .and_then(destroy_req_return_boxed_result)
     }
 
@@ -1206,24 +1341,36 @@ impl crate::Loop {
 
     /// Equivalent to futimes(3) respectively.
     ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
     /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
     /// versions but will return ENOSYS.
-    pub fn fs_futime<CB: Into<crate::FsCB<'static>>>(
+    pub fn fs_futime<T: TryInto<TimeSpec>, CB: Into<crate::FsCB<'static>>>(
         &self,
         file: File,
-        atime: f64,
-        mtime: f64,
+        atime: T,
+        mtime: T,
         cb: CB,
-    ) -> FsReqResult {
-        self._fs_futime(file, atime, mtime, cb)
+    ) -> FsReqResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_futime(file, utime_secs(atime)?, utime_secs(mtime)?, cb)
     }
 
     /// Equivalent to futimes(3) respectively.
     ///
+    /// The times may be given as fractional seconds since the epoch (f64), as a SystemTime, or as
+    /// a TimeSpec. Times which can't be represented fail with ERANGE.
+    ///
     /// Note: AIX: This function only works for AIX 7.1 and newer. It can still be called on older
     /// versions but will return ENOSYS.
-    pub fn fs_futime_sync(&self, file: File, atime: f64, mtime: f64) -> SyncResult {
-        self._fs_futime(file, atime, mtime, ())
+    pub fn fs_futime_sync<T: TryInto<TimeSpec>>(&self, file: File, atime: T, mtime: T) -> SyncResult
+    where
+        T::Error: Into<crate::Error>,
+    {
+        self._fs_futime(file, utime_secs(atime)?, utime_secs(mtime)?, ())
             .and_then(destroy_req_return_result)
     }
 
@@ -1598,6 +1745,16 @@ impl crate::Loop {
 /// ext2, ext3 and ext4 at the time of this writing), check the getdents(2) man page.
 pub struct ScandirIter {
     pub req: FsReq,
+    owned: bool,
+}
+
+impl ScandirIter {
+    /// Creates an iterator over the entries of a completed scandir request. The iterator does not
+    /// take ownership of the request: it is up to the caller to destroy it once it is no longer
+    /// needed.
+    pub fn new(req: FsReq) -> ScandirIter {
+        ScandirIter { req, owned: false }
+    }
 }
 
 impl Iterator for ScandirIter {
@@ -1621,6 +1778,8 @@ impl std::iter::FusedIterator for ScandirIter {}
 
 impl Drop for ScandirIter {
     fn drop(&mut self) {
-        self.req.destroy();
+        if self.owned {
+            self.req.destroy();
+        }
     }
 }
@@ -0,0 +1,302 @@
+use crate::{Dir, Dirent, DirentType, FromInner, FsReq, Inner, IntoInner, Stat};
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::path::Path;
+use std::rc::Rc;
+use uv::{uv_dir_t, uv_dirent_t, uv_fs_req_cleanup};
+
+callbacks! {
+    pub ReadDirCB(stream: ReadDir, batch: crate::Result<Vec<DirEntry>>);
+}
+
+/// The smallest and largest number of entries read by each fs_readdir(). Reads start small, and
+/// the buffer is doubled each time a read fills it.
+const MIN_ENTRIES: usize = 32;
+const MAX_ENTRIES: usize = 1024;
+
+/// Options for ReadDir.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ReadDirOptions {
+    /// Stat every entry with fs_lstat() before it is delivered. The entries in a batch are
+    /// stat'ed concurrently.
+    pub stat: bool,
+}
+
+/// An entry yielded by ReadDir.
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// The entry's name.
+    pub name: String,
+
+    /// The entry's path: the directory's path joined with the name.
+    pub path: String,
+
+    /// The entry's type. If the file system does not report types, this is filled in with
+    /// fs_lstat(); it is only UNKNOWN if that failed too.
+    pub r#type: DirentType,
+
+    /// The entry's stat, if ReadDirOptions::stat was set and fs_lstat() succeeded. It fails if,
+    /// for example, the entry was removed after the directory was read.
+    pub stat: Option<Stat>,
+}
+
+struct ReadDirState {
+    r#loop: crate::Loop,
+    path: String,
+    options: ReadDirOptions,
+
+    /// The open directory, or null before the first read and after the last
+    dir: *mut uv_dir_t,
+
+    /// The buffer fs_readdir() fills. Only its capacity is used.
+    entries: Vec<uv_dirent_t>,
+    cb: ReadDirCB<'static>,
+    busy: bool,
+    done: bool,
+}
+
+impl ReadDirState {
+    fn close(&mut self) {
+        if !self.dir.is_null() {
+            let dir = Dir::from_inner(self.dir);
+            let _ = self.r#loop.fs_closedir(&dir, |_: FsReq| ());
+            self.dir = std::ptr::null_mut();
+        }
+    }
+}
+
+impl Drop for ReadDirState {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// The entries of one batch, while they are being stat'ed.
+struct Batch {
+    entries: Vec<DirEntry>,
+    remaining: usize,
+}
+
+/// An asynchronous stream of the entries in a directory, which manages the Dir and its buffer
+/// for the caller. Each call to next() reads a batch of entries; an empty batch means the end of
+/// the directory has been reached, and the directory has been closed. The directory is also
+/// closed (asynchronously, with fs_closedir()) when the last clone of the ReadDir is dropped.
+///
+/// The entries "." and ".." are never returned.
+#[derive(Clone)]
+pub struct ReadDir {
+    state: Rc<RefCell<ReadDirState>>,
+}
+
+impl ReadDir {
+    /// Create a stream over the directory at path. The directory is opened by the first call to
+    /// next().
+    pub fn new(
+        r#loop: &crate::Loop,
+        path: &str,
+        options: ReadDirOptions,
+    ) -> Result<ReadDir, Box<dyn std::error::Error>> {
+        // check the path now, so that opening it later cannot fail because of it
+        CString::new(path)?;
+        Ok(ReadDir {
+            state: Rc::new(RefCell::new(ReadDirState {
+                r#loop: r#loop.clone(),
+                path: path.to_owned(),
+                options,
+                dir: std::ptr::null_mut(),
+                entries: Vec::with_capacity(MIN_ENTRIES),
+                cb: ().into(),
+                busy: false,
+                done: false,
+            })),
+        })
+    }
+
+    /// Returns the path of the directory.
+    pub fn path(&self) -> String {
+        self.state.borrow().path.clone()
+    }
+
+    /// Read the next batch of entries. Only one batch may be read at a time: if a read is
+    /// already in progress, this fails with EBUSY. Once the end of the directory has been
+    /// reached, this fails with EOF.
+    pub fn next<CB: Into<ReadDirCB<'static>>>(&self, cb: CB) -> crate::Result<()> {
+        let opened = {
+            let mut state = self.state.borrow_mut();
+            if state.busy {
+                return Err(crate::Error::EBUSY);
+            }
+            if state.done {
+                return Err(crate::Error::EOF);
+            }
+            state.busy = true;
+            state.cb = cb.into();
+            !state.dir.is_null()
+        };
+        let result = if opened { self.read() } else { self.open() };
+        if result.is_err() {
+            let mut state = self.state.borrow_mut();
+            state.busy = false;
+            state.cb = ().into();
+        }
+        result
+    }
+
+    fn open(&self) -> crate::Result<()> {
+        let (r#loop, path) = {
+            let state = self.state.borrow();
+            (state.r#loop.clone(), state.path.clone())
+        };
+        let stream = self.clone();
+        r#loop
+            .fs_opendir(&path, move |req: FsReq| {
+                let result = req.result().and_then(|_| {
+                    let dir = req.dir().ok_or(crate::Error::EINVAL)?;
+                    stream.state.borrow_mut().dir = (&dir).into_inner();
+                    stream.read()
+                });
+                if let Err(e) = result {
+                    stream.finish(Err(e));
+                }
+            })
+            // the path was checked in new(), so only libuv can have failed
+            .map_err(crate::Error::from_uv_boxed)?;
+        Ok(())
+    }
+
+    fn read(&self) -> crate::Result<()> {
+        let (r#loop, dir) = {
+            let mut state = self.state.borrow_mut();
+            let capacity = state.entries.capacity();
+            let dirents = state.entries.as_mut_ptr();
+            unsafe {
+                (*state.dir).dirents = dirents;
+                (*state.dir).nentries = capacity as _;
+            }
+            (state.r#loop.clone(), Dir::from_inner(state.dir))
+        };
+        let stream = self.clone();
+        r#loop._fs_readdir(&dir, move |req: FsReq| {
+            let result = req.result().map(|n| {
+                let state = stream.state.borrow();
+                let dirents: Vec<Dirent> = (0..n)
+                    .map(|i| Dirent::from_inner(unsafe { state.entries.as_ptr().add(i) }))
+                    .collect();
+                dirents
+            });
+
+            // Free the names now, rather than after this callback returns, so that the buffer can
+            // be reused, or the directory closed, before then.
+            unsafe { uv_fs_req_cleanup(Inner::<*mut uv::uv_fs_t>::inner(&req)) };
+            match result {
+                Ok(dirents) if dirents.is_empty() => {
+                    {
+                        let mut state = stream.state.borrow_mut();
+                        state.done = true;
+                        state.close();
+                    }
+                    stream.finish(Ok(Vec::new()));
+                }
+                Ok(dirents) => {
+                    let path = {
+                        let mut state = stream.state.borrow_mut();
+                        let capacity = state.entries.capacity();
+                        if dirents.len() >= capacity && capacity < MAX_ENTRIES {
+                            state.entries = Vec::with_capacity((capacity * 2).min(MAX_ENTRIES));
+                        }
+                        state.path.clone()
+                    };
+                    let entries = dirents
+                        .into_iter()
+                        .map(|dirent| DirEntry {
+                            path: Path::new(&path)
+                                .join(&dirent.name)
+                                .to_string_lossy()
+                                .into_owned(),
+                            name: dirent.name,
+                            r#type: dirent.r#type,
+                            stat: None,
+                        })
+                        .collect();
+                    stream.stat_entries(entries);
+                }
+                Err(e) => stream.finish(Err(e)),
+            }
+        })?;
+        Ok(())
+    }
+
+    /// lstat the entries which need it, then deliver the batch.
+    fn stat_entries(&self, entries: Vec<DirEntry>) {
+        let (r#loop, want_stat) = {
+            let state = self.state.borrow();
+            (state.r#loop.clone(), state.options.stat)
+        };
+        let pending: Vec<usize> = (0..entries.len())
+            .filter(|&i| want_stat || entries[i].r#type == DirentType::UNKNOWN)
+            .collect();
+        if pending.is_empty() {
+            return self.finish(Ok(entries));
+        }
+
+        let batch = Rc::new(RefCell::new(Batch {
+            entries,
+            remaining: pending.len(),
+        }));
+        for i in pending {
+            let path = batch.borrow().entries[i].path.clone();
+            let stream = self.clone();
+            let stat_batch = batch.clone();
+            let result = r#loop.fs_lstat(&path, move |req: FsReq| {
+                if req.result().is_ok() {
+                    let stat = req.stat();
+                    let entry = &mut stat_batch.borrow_mut().entries[i];
+                    if entry.r#type == DirentType::UNKNOWN {
+                        entry.r#type = stat.file_type();
+                    }
+                    if want_stat {
+                        entry.stat = Some(stat);
+                    }
+                }
+                stream.stat_done(&stat_batch);
+            });
+            if result.is_err() {
+                self.stat_done(&batch);
+            }
+        }
+    }
+
+    fn stat_done(&self, batch: &Rc<RefCell<Batch>>) {
+        let entries = {
+            let mut batch = batch.borrow_mut();
+            batch.remaining -= 1;
+            if batch.remaining > 0 {
+                return;
+            }
+            std::mem::take(&mut batch.entries)
+        };
+        self.finish(Ok(entries));
+    }
+
+    fn finish(&self, result: crate::Result<Vec<DirEntry>>) {
+        let mut cb = {
+            let mut state = self.state.borrow_mut();
+            state.busy = false;
+            std::mem::take(&mut state.cb)
+        };
+        cb.call(self.clone(), result);
+    }
+}
+
+impl crate::watchdog::WatchdogSource for ReadDir {
+    fn watchdog_source(&self) -> crate::CallbackSource {
+        crate::CallbackSource::Req(crate::ReqType::FS)
+    }
+}
+
+impl crate::Loop {
+    /// Create a ReadDir over the directory at path, with the default options. See ReadDir.
+    pub fn read_dir(&self, path: &str) -> Result<ReadDir, Box<dyn std::error::Error>> {
+        ReadDir::new(self, path, ReadDirOptions::default())
+    }
+}
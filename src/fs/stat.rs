@@ -1,7 +1,18 @@
-use crate::{FromInner, IntoInner};
+use crate::{DirentType, FromInner, FsModeFlags, IntoInner};
 use uv::uv_stat_t;
 
+// File type bits of Stat::mode. libuv uses the same values on every platform.
+const S_IFMT: u64 = 0o170000;
+const S_IFSOCK: u64 = 0o140000;
+const S_IFLNK: u64 = 0o120000;
+const S_IFREG: u64 = 0o100000;
+const S_IFBLK: u64 = 0o060000;
+const S_IFDIR: u64 = 0o040000;
+const S_IFCHR: u64 = 0o020000;
+const S_IFIFO: u64 = 0o010000;
+
 /// Portable equivalent of struct stat.
+#[derive(Clone, Debug, PartialEq)]
 pub struct Stat {
     pub dev: u64,
     pub mode: u64,
@@ -45,3 +56,114 @@ impl FromInner<*const uv_stat_t> for Stat {
         }
     }
 }
+
+bitflags! {
+    /// What changed between two Stats. See StatDiff::new().
+    pub struct StatDiff: u32 {
+        /// The size changed.
+        const SIZE = 1 << 0;
+
+        /// The modification time changed.
+        const MTIME = 1 << 1;
+
+        /// The status change time changed.
+        const CTIME = 1 << 2;
+
+        /// The file type or permissions changed.
+        const MODE = 1 << 3;
+
+        /// The owning user or group changed.
+        const OWNER = 1 << 4;
+
+        /// The device or inode changed, which means the path now refers to a different file: it
+        /// was replaced, for example by renaming another file over it.
+        const INODE = 1 << 5;
+
+        /// The file existed before, but no longer does.
+        const DELETED = 1 << 6;
+
+        /// The file did not exist before, but now does.
+        const CREATED = 1 << 7;
+    }
+}
+
+impl StatDiff {
+    /// Compare two stats of the same path. A Stat that is entirely zeroed - as FsPollHandle
+    /// reports for a path that does not exist - is treated as a missing file, in which case
+    /// only DELETED or CREATED is set.
+    pub fn new(prev: &Stat, curr: &Stat) -> StatDiff {
+        match (prev.exists(), curr.exists()) {
+            (false, false) => return StatDiff::empty(),
+            (true, false) => return StatDiff::DELETED,
+            (false, true) => return StatDiff::CREATED,
+            (true, true) => (),
+        }
+
+        let mut diff = StatDiff::empty();
+        if prev.size != curr.size {
+            diff |= StatDiff::SIZE;
+        }
+        if prev.mtim != curr.mtim {
+            diff |= StatDiff::MTIME;
+        }
+        if prev.ctim != curr.ctim {
+            diff |= StatDiff::CTIME;
+        }
+        if prev.mode != curr.mode {
+            diff |= StatDiff::MODE;
+        }
+        if prev.uid != curr.uid || prev.gid != curr.gid {
+            diff |= StatDiff::OWNER;
+        }
+        if prev.dev != curr.dev || prev.ino != curr.ino {
+            diff |= StatDiff::INODE;
+        }
+        diff
+    }
+}
+
+impl Stat {
+    /// Returns the type of the file, from the file type bits of mode.
+    pub fn file_type(&self) -> DirentType {
+        match self.mode & S_IFMT {
+            S_IFSOCK => DirentType::SOCKET,
+            S_IFLNK => DirentType::LINK,
+            S_IFREG => DirentType::FILE,
+            S_IFBLK => DirentType::BLOCK,
+            S_IFDIR => DirentType::DIR,
+            S_IFCHR => DirentType::CHAR,
+            S_IFIFO => DirentType::FIFO,
+            _ => DirentType::UNKNOWN,
+        }
+    }
+
+    /// Returns the permission bits of mode.
+    pub fn permissions(&self) -> FsModeFlags {
+        FsModeFlags::from_bits_truncate((self.mode & 0o7777) as _)
+    }
+
+    /// Returns true if this is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+
+    /// Returns true if this is a regular file.
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+
+    /// Returns true if this is a symbolic link. Only a stat from fs_lstat() can be a symlink.
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+
+    /// Returns what changed between this stat and a newer one. See StatDiff::new().
+    pub fn diff(&self, curr: &Stat) -> StatDiff {
+        StatDiff::new(self, curr)
+    }
+
+    /// Returns false if the stat is zeroed, which is how libuv represents a missing file.
+    fn exists(&self) -> bool {
+        self.mode != 0 || self.ino != 0 || self.nlink != 0
+    }
+}
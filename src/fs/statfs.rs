@@ -2,6 +2,7 @@ use crate::FromInner;
 use uv::uv_statfs_t;
 
 /// Reduced cross platform equivalent of struct statfs. Used in statfs()
+#[derive(Clone, Debug, PartialEq)]
 pub struct StatFs {
     pub r#type: u64,
     pub bsize: u64,
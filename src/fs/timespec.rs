@@ -1,12 +1,22 @@
 use crate::FromInner;
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uv::uv_timespec_t;
 
 /// Portable equivalent of struct timespec
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct TimeSpec {
     pub sec: i64,
     pub nsec: i64,
 }
 
+impl TimeSpec {
+    /// Returns the time as fractional seconds, as used by fs_utime() and friends.
+    pub(crate) fn as_secs_f64(&self) -> f64 {
+        self.sec as f64 + self.nsec as f64 / 1e9
+    }
+}
+
 impl FromInner<uv_timespec_t> for TimeSpec {
     fn from_inner(ts: uv_timespec_t) -> TimeSpec {
         TimeSpec {
@@ -15,3 +25,99 @@ impl FromInner<uv_timespec_t> for TimeSpec {
         }
     }
 }
+
+/// Interprets the TimeSpec as a time since the unix epoch. nsec may be negative or more than a
+/// second. Fails with ERANGE if the time can't be represented as a SystemTime.
+impl TryFrom<TimeSpec> for SystemTime {
+    type Error = crate::Error;
+
+    fn try_from(ts: TimeSpec) -> Result<SystemTime, crate::Error> {
+        let sec = ts
+            .sec
+            .checked_add(ts.nsec.div_euclid(1_000_000_000))
+            .ok_or(crate::Error::ERANGE)?;
+        let nsec = Duration::from_nanos(ts.nsec.rem_euclid(1_000_000_000) as u64);
+        let time = if sec >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(sec as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(sec.wrapping_neg() as u64))
+        };
+        time.and_then(|time| time.checked_add(nsec))
+            .ok_or(crate::Error::ERANGE)
+    }
+}
+
+/// Converts the SystemTime to a time since the unix epoch. Times before the epoch have a
+/// negative sec and a positive nsec, like struct timespec. Fails with ERANGE if the time is too far
+/// from the epoch to be represented.
+impl TryFrom<SystemTime> for TimeSpec {
+    type Error = crate::Error;
+
+    fn try_from(time: SystemTime) -> Result<TimeSpec, crate::Error> {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => TimeSpec::try_from(d),
+            Err(e) => {
+                let d = e.duration();
+                let sec = i64::try_from(d.as_secs()).map_err(|_| crate::Error::ERANGE)?;
+                if d.subsec_nanos() == 0 {
+                    Ok(TimeSpec { sec: -sec, nsec: 0 })
+                } else {
+                    Ok(TimeSpec {
+                        sec: -sec - 1,
+                        nsec: 1_000_000_000 - d.subsec_nanos() as i64,
+                    })
+                }
+            }
+        }
+    }
+}
+
+/// Fails with ERANGE if the Duration is too long to be represented.
+impl TryFrom<Duration> for TimeSpec {
+    type Error = crate::Error;
+
+    fn try_from(d: Duration) -> Result<TimeSpec, crate::Error> {
+        Ok(TimeSpec {
+            sec: i64::try_from(d.as_secs()).map_err(|_| crate::Error::ERANGE)?,
+            nsec: d.subsec_nanos() as _,
+        })
+    }
+}
+
+/// Fractional seconds, as accepted by utime(2) and friends. Fails with ERANGE if secs isn't
+/// finite, or is too large to be represented.
+impl TryFrom<f64> for TimeSpec {
+    type Error = crate::Error;
+
+    fn try_from(secs: f64) -> Result<TimeSpec, crate::Error> {
+        let sec = secs.floor();
+        if !(i64::MIN as f64..i64::MAX as f64).contains(&sec) {
+            return Err(crate::Error::ERANGE);
+        }
+        let mut ts = TimeSpec {
+            sec: sec as _,
+            nsec: ((secs - sec) * 1e9) as _,
+        };
+
+        // for a tiny negative secs, secs - sec rounds to 1.0
+        if ts.nsec >= 1_000_000_000 {
+            ts.sec += 1;
+            ts.nsec -= 1_000_000_000;
+        }
+        Ok(ts)
+    }
+}
+
+/// Fails with ERANGE if the TimeSpec is negative.
+impl TryFrom<TimeSpec> for Duration {
+    type Error = crate::Error;
+
+    fn try_from(ts: TimeSpec) -> Result<Duration, crate::Error> {
+        if ts.sec < 0 || ts.nsec < 0 {
+            return Err(crate::Error::ERANGE);
+        }
+        Duration::from_secs(ts.sec as u64)
+            .checked_add(Duration::from_nanos(ts.nsec as u64))
+            .ok_or(crate::Error::ERANGE)
+    }
+}
@@ -0,0 +1,667 @@
+use crate::{
+    FsEvent, FsEventFlags, FsEventHandle, FsOpenFlags, FsPollHandle, FsReq, HandleTrait,
+    TimerHandle, ToHandle,
+};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use std::rc::Rc;
+
+/// Filesystems on which inotify does not see changes made by other machines, by their f_type
+/// from statfs(2).
+#[cfg(target_os = "linux")]
+const POLLED_FILESYSTEMS: &[u64] = &[
+    0x6969,     // NFS
+    0x517b,     // SMB
+    0xff534d42, // CIFS
+    0xfe534d42, // SMB2
+    0x65735546, // FUSE (sshfs and friends)
+    0x73757245, // Coda
+    0x5346414f, // AFS
+    0x01021997, // 9P
+    0x786f4256, // VirtualBox shared folders
+    0x00c36400, // Ceph
+];
+
+/// How a Watcher detects changes.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WatcherBackend {
+    /// Changes are detected with FsEventHandles (inotify, FSEvents, kqueue, ...), one per
+    /// directory.
+    FsEvent,
+
+    /// Changes are detected with FsPollHandles, one per file and directory, which stat() their
+    /// path every poll_interval milliseconds. This works on any filesystem, but is much more
+    /// expensive.
+    FsPoll,
+}
+
+/// A change reported by a Watcher.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum WatchEvent {
+    /// A file or directory was created.
+    Created(String),
+
+    /// The contents or metadata of a file changed, or the file was replaced by another file.
+    Modified(String),
+
+    /// A file or directory was removed.
+    Removed(String),
+
+    /// A file or directory was renamed within the watched tree.
+    Renamed { from: String, to: String },
+}
+
+callbacks! {
+    pub WatcherCB(watcher: Watcher, events: Result<Vec<WatchEvent>, Box<dyn std::error::Error>>);
+}
+
+/// Options for a Watcher.
+#[derive(Clone, Copy, Debug)]
+pub struct WatcherOptions {
+    /// How long to wait, in milliseconds, after the last filesystem event before reporting a batch
+    /// of changes. Defaults to 50.
+    pub debounce: u64,
+
+    /// Whether subdirectories are watched too. Defaults to true.
+    pub recursive: bool,
+
+    /// Which backend to use. If None (the default), the backend is chosen when the watcher is
+    /// started: FsPoll is used for paths on network filesystems (NFS, SMB, FUSE, ...) where
+    /// filesystem events are not delivered for changes made by other machines, and FsEvent is
+    /// used everywhere else. Detection is currently only done on Linux; set this explicitly to
+    /// poll, for example, a container volume whose events do not arrive.
+    pub backend: Option<WatcherBackend>,
+
+    /// How often, in milliseconds, the FsPoll backend checks each path. Defaults to 2000.
+    pub poll_interval: u32,
+}
+
+impl Default for WatcherOptions {
+    fn default() -> WatcherOptions {
+        WatcherOptions {
+            debounce: 50,
+            recursive: true,
+            backend: None,
+            poll_interval: 2000,
+        }
+    }
+}
+
+/// What the watcher last saw at a path.
+#[derive(Clone, Copy, Eq, PartialEq)]
+struct Entry {
+    dev: u64,
+    ino: u64,
+    is_dir: bool,
+    size: u64,
+    mtime: crate::TimeSpec,
+}
+
+impl Entry {
+    fn new(stat: &crate::Stat) -> Entry {
+        Entry {
+            dev: stat.dev,
+            ino: stat.ino,
+            is_dir: stat.is_dir(),
+            size: stat.size,
+            mtime: stat.mtim,
+        }
+    }
+
+    fn same_file(&self, other: &Entry) -> bool {
+        self.dev == other.dev && self.ino == other.ino
+    }
+}
+
+struct WatcherState {
+    r#loop: crate::Loop,
+    options: WatcherOptions,
+    backend: Option<WatcherBackend>,
+    timer: TimerHandle,
+    watches: BTreeMap<String, crate::Handle>,
+    known: BTreeMap<String, Entry>,
+    pending: BTreeSet<String>,
+    flushing: bool,
+    closed: bool,
+}
+
+impl WatcherState {
+    /// Forget everything at or below path, and close the watches on any paths there.
+    fn forget(&mut self, path: &str) {
+        let prefix = Path::new(path);
+        self.known.retain(|k, _| !Path::new(k).starts_with(prefix));
+        let closing: Vec<String> = self
+            .watches
+            .keys()
+            .filter(|k| Path::new(k).starts_with(prefix))
+            .cloned()
+            .collect();
+        for k in closing {
+            if let Some(mut handle) = self.watches.remove(&k) {
+                handle.close(());
+            }
+        }
+    }
+}
+
+/// Results of the lstat() calls made while flushing a batch of pending paths.
+struct Batch {
+    remaining: usize,
+    results: Vec<(String, crate::Result<Entry>)>,
+}
+
+/// A Watcher watches a file or a directory tree for changes. It is built on FsEventHandle, with
+/// one handle per watched directory, but rather than passing on raw events it waits until events
+/// have stopped arriving for the debounce interval, then lstat()s every path that was touched and
+/// reports what actually changed as a batch of WatchEvents. Bursts of events - such as an editor
+/// saving a file - are therefore coalesced, and a rename within the tree is reported as a single
+/// Renamed event.
+///
+/// Because FsEventHandle only supports the RECURSIVE flag on OSX and Windows, recursion is
+/// implemented by the watcher itself: new directories are watched as they are created.
+/// Symlinks are never followed.
+///
+/// On filesystems where filesystem events are not delivered, the watcher falls back to polling
+/// every path with an FsPollHandle instead. The same events are reported either way. See
+/// WatcherOptions::backend.
+///
+/// Watchers are cheap to clone: clones share the same state. The watcher keeps the loop alive
+/// until close() is called.
+#[derive(Clone)]
+pub struct Watcher {
+    state: Rc<RefCell<WatcherState>>,
+    cb: Rc<RefCell<WatcherCB<'static>>>,
+}
+
+impl Watcher {
+    /// Create a new watcher.
+    pub fn new(r#loop: &crate::Loop, options: WatcherOptions) -> crate::Result<Watcher> {
+        let timer = TimerHandle::new(r#loop)?;
+        Ok(Watcher {
+            state: Rc::new(RefCell::new(WatcherState {
+                r#loop: r#loop.clone(),
+                options,
+                backend: options.backend,
+                timer,
+                watches: BTreeMap::new(),
+                known: BTreeMap::new(),
+                pending: BTreeSet::new(),
+                flushing: false,
+                closed: false,
+            })),
+            cb: Rc::new(RefCell::new(WatcherCB::Nil)),
+        })
+    }
+
+    /// Start watching the given path, calling cb with each batch of changes. The path is
+    /// lstat()ed asynchronously; if that fails, or if the watcher fails to watch a directory after
+    /// it has started, the error is passed to cb.
+    ///
+    /// start() may be called again to watch additional paths; each call replaces the callback.
+    /// The backend is chosen by the first call to start(), and is used for every path.
+    pub fn start<CB: Into<WatcherCB<'static>>>(
+        &mut self,
+        path: &str,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        *self.cb.borrow_mut() = cb.into();
+
+        let r#loop = self.state.borrow().r#loop.clone();
+        let watcher = self.clone();
+        let root = path.to_owned();
+        r#loop.fs_lstat(path, move |req: FsReq| match req.result() {
+            Ok(_) => watcher.begin(root.clone(), Entry::new(&req.stat())),
+            Err(e) => watcher.report(Err(Box::new(e))),
+        })?;
+        Ok(())
+    }
+
+    /// Called once a path passed to start() has been lstat()ed. The first call chooses the
+    /// backend before anything is watched.
+    fn begin(&self, path: String, entry: Entry) {
+        let backend = {
+            let state = self.state.borrow();
+            if state.closed {
+                return;
+            }
+            state.backend
+        };
+        match backend {
+            Some(_) => self.watch_root(path, entry),
+            None => self.detect_backend(path, entry),
+        }
+    }
+
+    /// Choose a backend for the given path, based on the type of filesystem it is on, then watch
+    /// it.
+    #[cfg(target_os = "linux")]
+    fn detect_backend(&self, path: String, entry: Entry) {
+        let r#loop = self.state.borrow().r#loop.clone();
+        let watcher = self.clone();
+        let root = path.clone();
+        let result = r#loop.fs_statfs(&path, move |req: FsReq| {
+            let polled = req.result().is_ok()
+                && req
+                    .statfs()
+                    .is_some_and(|statfs| POLLED_FILESYSTEMS.contains(&statfs.r#type));
+            watcher.set_backend(if polled {
+                WatcherBackend::FsPoll
+            } else {
+                WatcherBackend::FsEvent
+            });
+            watcher.watch_root(root.clone(), entry);
+        });
+        if result.is_err() {
+            self.set_backend(WatcherBackend::FsEvent);
+            self.watch_root(path, entry);
+        }
+    }
+
+    /// Choose a backend for the given path, then watch it. Filesystem types are only detected on
+    /// Linux.
+    #[cfg(not(target_os = "linux"))]
+    fn detect_backend(&self, path: String, entry: Entry) {
+        self.set_backend(WatcherBackend::FsEvent);
+        self.watch_root(path, entry);
+    }
+
+    /// Set the backend, unless another call to start() already chose one.
+    fn set_backend(&self, backend: WatcherBackend) {
+        let mut state = self.state.borrow_mut();
+        if state.backend.is_none() {
+            state.backend = Some(backend);
+        }
+    }
+
+    /// Watch a path passed to start(), and scan it if it is a directory.
+    fn watch_root(&self, path: String, entry: Entry) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.closed {
+                return;
+            }
+            state.known.insert(path.clone(), entry);
+        }
+        let result = self.watch(&path, entry.is_dir).and_then(|_| {
+            if entry.is_dir {
+                self.scan(path, false)
+            } else {
+                Ok(())
+            }
+        });
+        if let Err(e) = result {
+            self.report(Err(e));
+        }
+    }
+
+    /// Stop watching and close every handle owned by the watcher. No further events are reported.
+    pub fn close(&mut self) {
+        {
+            let mut state = self.state.borrow_mut();
+            if state.closed {
+                return;
+            }
+            state.closed = true;
+            state.pending.clear();
+            state.known.clear();
+            for (_, mut handle) in std::mem::take(&mut state.watches) {
+                handle.close(());
+            }
+            state.timer.close(());
+        }
+        self.cb.replace(WatcherCB::Nil);
+    }
+
+    /// Returns the backend the watcher is using, or None if it has not chosen one yet. The backend
+    /// is chosen once the path passed to the first call to start() has been examined.
+    pub fn backend(&self) -> Option<WatcherBackend> {
+        self.state.borrow().backend
+    }
+
+    /// Watch a single path with a new FsEventHandle or FsPollHandle, depending on the backend.
+    fn watch(&self, path: &str, is_dir: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let mut state = self.state.borrow_mut();
+        if state.closed || state.watches.contains_key(path) {
+            return Ok(());
+        }
+
+        let handle = match state.backend {
+            Some(WatcherBackend::FsPoll) => {
+                self.watch_poll(&state.r#loop, path, is_dir, state.options.poll_interval)?
+            }
+            _ => self.watch_event(&state.r#loop, path, is_dir)?,
+        };
+        state.watches.insert(path.to_owned(), handle);
+        Ok(())
+    }
+
+    /// Watch a path with an FsEventHandle. Events on a directory are for the entry named by
+    /// filename.
+    fn watch_event(
+        &self,
+        r#loop: &crate::Loop,
+        path: &str,
+        is_dir: bool,
+    ) -> Result<crate::Handle, Box<dyn std::error::Error>> {
+        let mut handle = FsEventHandle::new(r#loop)?;
+        let watcher = self.clone();
+        let dir = path.to_owned();
+        let result = handle.start(
+            path,
+            FsEventFlags::empty(),
+            move |_: FsEventHandle,
+                  filename: Option<Cow<str>>,
+                  _: FsEvent,
+                  status: crate::Result<u32>| match status {
+                Ok(_) => watcher.mark_pending(match filename {
+                    Some(name) if is_dir => join(&dir, &name),
+                    _ => dir.clone(),
+                }),
+                Err(e) => watcher.report(Err(Box::new(e))),
+            },
+        );
+        if let Err(e) = result {
+            handle.close(());
+            return Err(e);
+        }
+        Ok(handle.to_handle())
+    }
+
+    /// Watch a path with an FsPollHandle. Polling a directory only tells us that its entries
+    /// changed, not which ones, so the directory is rescanned.
+    fn watch_poll(
+        &self,
+        r#loop: &crate::Loop,
+        path: &str,
+        is_dir: bool,
+        interval: u32,
+    ) -> Result<crate::Handle, Box<dyn std::error::Error>> {
+        let mut handle = FsPollHandle::new(r#loop)?;
+        let watcher = self.clone();
+        let target = path.to_owned();
+        let result = handle.start(
+            path,
+            interval,
+            move |_: FsPollHandle, status: crate::Result<u32>, _: crate::Stat, _: crate::Stat| {
+                match status {
+                    Ok(_) if is_dir => watcher.rescan(&target),
+                    Ok(_) | Err(crate::Error::ENOENT) | Err(crate::Error::ENOTDIR) => {
+                        watcher.mark_pending(target.clone())
+                    }
+                    Err(e) => watcher.report(Err(Box::new(e))),
+                }
+            },
+        );
+        if let Err(e) = result {
+            handle.close(());
+            return Err(e);
+        }
+        Ok(handle.to_handle())
+    }
+
+    /// Mark every entry that is, or was, in a directory as pending.
+    fn rescan(&self, dir: &str) {
+        let children: Vec<String> = {
+            let state = self.state.borrow();
+            state
+                .known
+                .keys()
+                .filter(|k| Path::new(k).parent() == Some(Path::new(dir)))
+                .cloned()
+                .collect()
+        };
+        for child in children {
+            self.mark_pending(child);
+        }
+        if let Err(e) = self.scan(dir.to_owned(), true) {
+            self.report_watch_error(e);
+        }
+    }
+
+    /// List a directory. If report is true, each entry is marked pending so that it will be
+    /// reported as created; otherwise each entry is learned silently.
+    fn scan(&self, dir: String, report: bool) -> Result<(), Box<dyn std::error::Error>> {
+        let r#loop = self.state.borrow().r#loop.clone();
+        let watcher = self.clone();
+        let path = dir.clone();
+        r#loop.fs_scandir(
+            &path,
+            FsOpenFlags::empty(),
+            move |iter: crate::ScandirIter| {
+                for dirent in iter.filter_map(Result::ok) {
+                    let path = join(&dir, &dirent.name);
+                    if report {
+                        watcher.mark_pending(path);
+                    } else {
+                        watcher.discover(path);
+                    }
+                }
+            },
+        )?;
+        Ok(())
+    }
+
+    /// lstat() a path and add it to the known paths without reporting it.
+    fn discover(&self, path: String) {
+        let r#loop = self.state.borrow().r#loop.clone();
+        let watcher = self.clone();
+        let _ = r#loop.fs_lstat(&path.clone(), move |req: FsReq| {
+            if req.result().is_ok() {
+                watcher.add(path.clone(), Entry::new(&req.stat()), false);
+            }
+        });
+    }
+
+    /// Add a path to the known paths. Directories are watched and scanned if the watcher is
+    /// recursive. With the FsPoll backend, every other path is watched as well.
+    fn add(&self, path: String, entry: Entry, report: bool) {
+        let (recursive, backend) = {
+            let mut state = self.state.borrow_mut();
+            if state.closed {
+                return;
+            }
+            state.known.insert(path.clone(), entry);
+            (state.options.recursive, state.backend)
+        };
+
+        let result = if entry.is_dir && recursive {
+            self.watch(&path, true)
+                .and_then(|_| self.scan(path, report))
+        } else if backend == Some(WatcherBackend::FsPoll) {
+            self.watch(&path, false)
+        } else {
+            Ok(())
+        };
+        if let Err(e) = result {
+            self.report_watch_error(e);
+        }
+    }
+
+    /// Report an error from watching or scanning a path after the watcher was started.
+    fn report_watch_error(&self, e: Box<dyn std::error::Error>) {
+        // the path may already be gone again, in which case it will be reported as removed by
+        // the watch on its parent
+        match e.downcast_ref::<crate::Error>() {
+            Some(crate::Error::ENOENT) | Some(crate::Error::ENOTDIR) => (),
+            _ => self.report(Err(e)),
+        }
+    }
+
+    /// Mark a path as possibly changed, and (re)start the debounce timer.
+    fn mark_pending(&self, path: String) {
+        let mut state = self.state.borrow_mut();
+        if state.closed {
+            return;
+        }
+        state.pending.insert(path);
+        if !state.flushing {
+            let result = self.schedule_flush(&mut state);
+            drop(state);
+            if let Err(e) = result {
+                self.report(Err(Box::new(e)));
+            }
+        }
+    }
+
+    fn schedule_flush(&self, state: &mut WatcherState) -> crate::Result<()> {
+        let watcher = self.clone();
+        let debounce = state.options.debounce;
+        state
+            .timer
+            .start(debounce, 0, move |_: TimerHandle| watcher.flush())
+    }
+
+    /// Called when the debounce timer fires: lstat() every pending path.
+    fn flush(&self) {
+        let (r#loop, paths) = {
+            let mut state = self.state.borrow_mut();
+            if state.closed || state.pending.is_empty() {
+                return;
+            }
+            state.flushing = true;
+            (state.r#loop.clone(), std::mem::take(&mut state.pending))
+        };
+
+        let batch = Rc::new(RefCell::new(Batch {
+            remaining: paths.len(),
+            results: Vec::with_capacity(paths.len()),
+        }));
+        for path in paths {
+            let watcher = self.clone();
+            let req_batch = batch.clone();
+            let req_path = path.clone();
+            let result = r#loop.fs_lstat(&path, move |req: FsReq| {
+                let result = req.result().map(|_| Entry::new(&req.stat()));
+                watcher.complete(&req_batch, req_path.clone(), result);
+            });
+            if let Err(e) = result {
+                self.complete(&batch, path, Err(crate::Error::from_uv_boxed(e)));
+            }
+        }
+    }
+
+    /// Record the result of one lstat() in the batch, and finish the batch once every result is
+    /// in.
+    fn complete(&self, batch: &Rc<RefCell<Batch>>, path: String, result: crate::Result<Entry>) {
+        let results = {
+            let mut batch = batch.borrow_mut();
+            batch.results.push((path, result));
+            batch.remaining -= 1;
+            if batch.remaining > 0 {
+                return;
+            }
+            std::mem::take(&mut batch.results)
+        };
+        self.finish_flush(results);
+    }
+
+    /// Compare the results of a batch against what the watcher knew, and report the differences.
+    fn finish_flush(&self, results: Vec<(String, crate::Result<Entry>)>) {
+        let mut events = Vec::new();
+        let mut created: Vec<(String, Entry)> = Vec::new();
+        let mut renamed: Vec<(String, Entry)> = Vec::new();
+        {
+            let mut state = self.state.borrow_mut();
+            if state.closed {
+                return;
+            }
+
+            let mut gone: Vec<(String, Entry)> = Vec::new();
+            for (path, result) in results {
+                let old = state.known.get(&path).copied();
+                match (old, result) {
+                    (None, Ok(entry)) => created.push((path, entry)),
+                    (Some(old), Ok(entry))
+                        if old.is_dir != entry.is_dir
+                            || (entry.is_dir && !old.same_file(&entry)) =>
+                    {
+                        gone.push((path.clone(), old));
+                        created.push((path, entry));
+                    }
+                    (Some(old), Ok(entry)) if !entry.is_dir && old != entry => {
+                        state.known.insert(path.clone(), entry);
+                        events.push(WatchEvent::Modified(path));
+                    }
+                    (Some(old), Err(crate::Error::ENOENT))
+                    | (Some(old), Err(crate::Error::ENOTDIR)) => gone.push((path, old)),
+                    _ => (),
+                }
+            }
+
+            // a path that disappeared and a path that appeared with the same inode is a rename
+            for (from, old) in gone {
+                match created.iter().position(|(_, entry)| entry.same_file(&old)) {
+                    Some(i) => {
+                        let (to, entry) = created.remove(i);
+                        events.push(WatchEvent::Renamed {
+                            from: from.clone(),
+                            to: to.clone(),
+                        });
+                        renamed.push((to, entry));
+                    }
+                    None => {
+                        events.push(WatchEvent::Removed(from.clone()));
+                    }
+                }
+                state.forget(&from);
+            }
+            for (path, _) in created.iter() {
+                events.push(WatchEvent::Created(path.clone()));
+            }
+        }
+
+        // the contents of a renamed directory were already known, so they are learned silently;
+        // the contents of a new directory are reported as created
+        for (path, entry) in renamed {
+            self.add(path, entry, false);
+        }
+        for (path, entry) in created {
+            self.add(path, entry, true);
+        }
+
+        let result = {
+            let mut state = self.state.borrow_mut();
+            state.flushing = false;
+            if !state.closed && !state.pending.is_empty() {
+                self.schedule_flush(&mut state)
+            } else {
+                Ok(())
+            }
+        };
+        if let Err(e) = result {
+            self.report(Err(Box::new(e)));
+        }
+        if !events.is_empty() {
+            self.report(Ok(events));
+        }
+    }
+
+    /// Pass events to the callback. The callback is taken out of the watcher while it runs, so
+    /// that it may call start() or close().
+    fn report(&self, events: Result<Vec<WatchEvent>, Box<dyn std::error::Error>>) {
+        if self.state.borrow().closed {
+            return;
+        }
+
+        let mut cb = self.cb.replace(WatcherCB::Nil);
+        cb.call(self.clone(), events);
+
+        let mut current = self.cb.borrow_mut();
+        if current.is_nil() && !self.state.borrow().closed {
+            *current = cb;
+        }
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    Path::new(dir).join(name).to_string_lossy().into_owned()
+}
+
+impl crate::Loop {
+    /// Create a new Watcher with the default options. See Watcher.
+    pub fn watcher(&self) -> crate::Result<Watcher> {
+        Watcher::new(self, WatcherOptions::default())
+    }
+}
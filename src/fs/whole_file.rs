@@ -0,0 +1,388 @@
+use crate::{AsyncFile, Buf, File, FsModeFlags, FsOpenFlags, FsReq, MAX_READ, MIN_READ};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+callbacks! {
+    pub FsReadFileCB(result: crate::Result<Vec<u8>>);
+    pub FsWriteFileCB(result: crate::Result<usize>);
+}
+
+/// Call a shared callback. It can only be called once; later calls do nothing.
+fn finish<T>(cb: &Rc<RefCell<T>>, call: impl FnOnce(&mut T))
+where
+    T: Default,
+{
+    let mut cb = std::mem::take(&mut *cb.borrow_mut());
+    call(&mut cb);
+}
+
+/// Close file, then pass result to cb. If the write succeeded but closing the file fails (which
+/// can happen on network filesystems), the error from close is passed instead.
+fn close_then_finish(
+    file: AsyncFile,
+    result: crate::Result<usize>,
+    cb: Rc<RefCell<FsWriteFileCB<'static>>>,
+) {
+    if let Err(e) = result {
+        // the file is closed when the last clone is dropped
+        return finish(&cb, |cb| cb.call(Err(e)));
+    }
+
+    let close_cb = cb.clone();
+    let closed = file.close(move |_: AsyncFile, closed: crate::Result<u64>| {
+        finish(&close_cb, |cb| cb.call(closed.and(result)))
+    });
+    if let Err(e) = closed {
+        finish(&cb, |cb| cb.call(Err(e)));
+    }
+}
+
+/// State for fs_write_file_atomic(), which takes several requests to complete.
+struct AtomicWrite {
+    r#loop: crate::Loop,
+    file: AsyncFile,
+    tmp: String,
+    target: String,
+    mode: FsModeFlags,
+    data: Vec<u8>,
+    cb: RefCell<FsWriteFileCB<'static>>,
+}
+
+impl AtomicWrite {
+    fn chmod(self: Rc<Self>) {
+        let op = self.clone();
+        let result =
+            self.r#loop
+                .fs_fchmod(self.file.file(), self.mode, move |req: FsReq| {
+                    match req.result() {
+                        Ok(_) => op.clone().write(),
+                        Err(e) => op.clone().fail(e),
+                    }
+                });
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    fn write(self: Rc<Self>) {
+        let op = self.clone();
+        let result = self.file.write_all(
+            &self.data,
+            move |_: AsyncFile, result: crate::Result<u64>| match result {
+                Ok(_) => op.clone().sync(),
+                Err(e) => op.clone().fail(e),
+            },
+        );
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    fn sync(self: Rc<Self>) {
+        let op = self.clone();
+        let result = self.file.sync_all(
+            move |_: AsyncFile, result: crate::Result<u64>| match result {
+                Ok(_) => op.clone().close(),
+                Err(e) => op.clone().fail(e),
+            },
+        );
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    fn close(self: Rc<Self>) {
+        let op = self.clone();
+        let result = self.file.close(
+            move |_: AsyncFile, result: crate::Result<u64>| match result {
+                Ok(_) => op.clone().rename(),
+                Err(e) => op.clone().fail(e),
+            },
+        );
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    fn rename(self: Rc<Self>) {
+        let op = self.clone();
+        let result = self
+            .r#loop
+            .fs_rename(&self.tmp, &self.target, move |req: FsReq| {
+                match req.result() {
+                    Ok(_) => op.finish(Ok(op.data.len())),
+                    Err(e) => op.clone().fail(e),
+                }
+            });
+        // the target was checked by fs_mkstemp(), so only libuv can have failed
+        if let Err(e) = result {
+            self.fail(crate::Error::from_uv_boxed(e));
+        }
+    }
+
+    /// Close and remove the temporary file, then pass the error to the callback.
+    fn fail(self: Rc<Self>, e: crate::Error) {
+        let op = self.clone();
+        let closed = self
+            .file
+            .close(move |_: AsyncFile, _: crate::Result<u64>| op.unlink());
+        if closed.is_err() {
+            // the file was already closed
+            self.unlink();
+        }
+        self.finish(Err(e));
+    }
+
+    fn unlink(&self) {
+        let _ = self.r#loop.fs_unlink(&self.tmp, |_: FsReq| ());
+    }
+
+    fn finish(&self, result: crate::Result<usize>) {
+        let mut cb = std::mem::take(&mut *self.cb.borrow_mut());
+        cb.call(result);
+    }
+}
+
+impl crate::Loop {
+    /// Read the whole file at path. The file's size is used to size the buffer up front.
+    pub fn fs_read_file<CB: Into<FsReadFileCB<'static>>>(
+        &self,
+        path: &str,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let cb = Rc::new(RefCell::new(cb.into()));
+        AsyncFile::open(
+            self,
+            path,
+            FsOpenFlags::RDONLY,
+            FsModeFlags::empty(),
+            move |file: crate::Result<AsyncFile>| {
+                let read_cb = cb.clone();
+                let result = file.and_then(|file| {
+                    file.read_to_end(move |file: AsyncFile, result: crate::Result<Vec<u8>>| {
+                        let _ = file.close(());
+                        finish(&read_cb, |cb| cb.call(result))
+                    })
+                });
+                if let Err(e) = result {
+                    finish(&cb, |cb| cb.call(Err(e)));
+                }
+            },
+        )
+    }
+
+    /// Read the whole file at path.
+    pub fn fs_read_file_sync(&self, path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let file = self.fs_open_sync(path, FsOpenFlags::RDONLY, FsModeFlags::empty())?;
+        let result = self.read_all_sync(file);
+        let closed = self.fs_close_sync(file);
+        let data = result?;
+        closed?;
+        Ok(data)
+    }
+
+    /// Private implementation for fs_write_file() and fs_append_file()
+    fn _fs_write_file<CB: Into<FsWriteFileCB<'static>>>(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+        append: bool,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let flags = FsOpenFlags::WRONLY
+            | FsOpenFlags::CREAT
+            | if append {
+                FsOpenFlags::APPEND
+            } else {
+                FsOpenFlags::TRUNC
+            };
+        let cb = Rc::new(RefCell::new(cb.into()));
+        let data = data.to_vec();
+        AsyncFile::open(
+            self,
+            path,
+            flags,
+            mode,
+            move |file: crate::Result<AsyncFile>| {
+                let write_cb = cb.clone();
+                let result = file.and_then(|file| {
+                    let write_cb = write_cb.clone();
+                    file.write_all(&data, move |file: AsyncFile, result: crate::Result<u64>| {
+                        close_then_finish(file, result.map(|n| n as _), write_cb.clone())
+                    })
+                });
+                if let Err(e) = result {
+                    finish(&cb, |cb| cb.call(Err(e)));
+                }
+            },
+        )
+    }
+
+    /// Write data to the file at path, creating it with the given mode if it does not exist, and
+    /// truncating it if it does. The callback is passed the number of bytes written.
+    pub fn fs_write_file<CB: Into<FsWriteFileCB<'static>>>(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self._fs_write_file(path, data, mode, false, cb)
+    }
+
+    /// Write data to the file at path, creating it with the given mode if it does not exist, and
+    /// truncating it if it does. Returns the number of bytes written.
+    pub fn fs_write_file_sync(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let flags = FsOpenFlags::WRONLY | FsOpenFlags::CREAT | FsOpenFlags::TRUNC;
+        let file = self.fs_open_sync(path, flags, mode)?;
+        let result = self.write_all_sync(file, data, 0);
+        let closed = self.fs_close_sync(file);
+        let written = result?;
+        closed?;
+        Ok(written)
+    }
+
+    /// Append data to the file at path, creating it with the given mode if it does not exist.
+    /// The callback is passed the number of bytes written.
+    pub fn fs_append_file<CB: Into<FsWriteFileCB<'static>>>(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self._fs_write_file(path, data, mode, true, cb)
+    }
+
+    /// Append data to the file at path, creating it with the given mode if it does not exist.
+    /// Returns the number of bytes written.
+    pub fn fs_append_file_sync(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let flags = FsOpenFlags::WRONLY | FsOpenFlags::CREAT | FsOpenFlags::APPEND;
+        let file = self.fs_open_sync(path, flags, mode)?;
+        let result = self.write_all_sync(file, data, -1);
+        let closed = self.fs_close_sync(file);
+        let written = result?;
+        closed?;
+        Ok(written)
+    }
+
+    /// Atomically replace the file at path with data: the data is written to a temporary file
+    /// next to path (created with fs_mkstemp()), which is given the requested mode, flushed to
+    /// disk with fs_fsync(), and then renamed over path. Readers see either the old contents or
+    /// the new contents, never a partially written file. If any step fails, the temporary file is
+    /// removed. The callback is passed the number of bytes written.
+    ///
+    /// Note: the directory containing path is not flushed, so after a system crash the rename
+    /// itself may be lost - in which case path still has its old contents.
+    pub fn fs_write_file_atomic<CB: Into<FsWriteFileCB<'static>>>(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cb = Some(cb.into());
+        let r#loop = self.clone();
+        let target = path.to_owned();
+        let mut data = data.to_vec();
+        self.fs_mkstemp(&format!("{}.XXXXXX", path), move |req: FsReq| {
+            let mut cb = match cb.take() {
+                Some(cb) => cb,
+                None => return,
+            };
+            let file = match req.result() {
+                Ok(file) => AsyncFile::from_file(&r#loop, file as _),
+                Err(e) => return cb.call(Err(e)),
+            };
+            Rc::new(AtomicWrite {
+                r#loop: r#loop.clone(),
+                file,
+                tmp: req.path(),
+                target: target.clone(),
+                mode,
+                data: std::mem::take(&mut data),
+                cb: RefCell::new(cb),
+            })
+            .chmod();
+        })?;
+        Ok(())
+    }
+
+    /// Atomically replace the file at path with data. See fs_write_file_atomic(). Returns the
+    /// number of bytes written.
+    pub fn fs_write_file_atomic_sync(
+        &self,
+        path: &str,
+        data: &[u8],
+        mode: FsModeFlags,
+    ) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut req = self._fs_mkstemp(&format!("{}.XXXXXX", path), ())?;
+        let result = req.result();
+        let tmp = req.path();
+        req.destroy();
+        let file = result? as File;
+
+        let result = self
+            .fs_fchmod_sync(file, mode)
+            .and_then(|_| self.write_all_sync(file, data, 0))
+            .and_then(|written| self.fs_fsync_sync(file).map(|_| written));
+        let closed = self.fs_close_sync(file);
+        let result = result
+            .and_then(|written| closed.map(|_| written))
+            .map_err(|e| Box::new(e) as Box<dyn std::error::Error>)
+            .and_then(|written| self.fs_rename_sync(&tmp, path).map(|_| written));
+        if result.is_err() {
+            let _ = self.fs_unlink_sync(&tmp);
+        }
+        result
+    }
+
+    /// Read from offset 0 to the end of file.
+    fn read_all_sync(&self, file: File) -> crate::Result<Vec<u8>> {
+        let size = self.fs_fstat_sync(file).map(|stat| stat.size).unwrap_or(0);
+        let mut data = Vec::with_capacity(size as _);
+        loop {
+            let want = (data.capacity() - data.len()).max(MIN_READ).min(MAX_READ);
+            let mut buf = Buf::with_capacity(want)?;
+            let result = self.fs_read_sync(file, &[buf], data.len() as _);
+            if let Ok(n) = result {
+                data.extend_from_slice(buf.as_bytes(n));
+            }
+            buf.destroy();
+            if result? == 0 {
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Write all of data to file at offset, or at the current position if offset is -1.
+    fn write_all_sync(&self, file: File, data: &[u8], offset: i64) -> crate::Result<usize> {
+        let mut written = 0;
+        while written < data.len() {
+            let mut buf = Buf::new_from_bytes_exact(&data[written..])?;
+            let at = if offset < 0 {
+                offset
+            } else {
+                offset + written as i64
+            };
+            let result = self.fs_write_sync(file, &[buf], at);
+            buf.destroy();
+            match result? {
+                0 => return Err(crate::Error::EIO),
+                n => written += n,
+            }
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,509 @@
+use crate::{File, WorkReq};
+use std::ffi::{CStr, CString};
+
+callbacks! {
+    pub FsGetxattrCB(result: crate::Result<Vec<u8>>);
+    pub FsListxattrCB(result: crate::Result<Vec<String>>);
+    pub FsXattrCB(result: crate::Result<()>);
+}
+
+bitflags! {
+    /// Flags for fs_setxattr().
+    pub struct XattrFlags: i32 {
+        /// Fail with EEXIST if the attribute already exists.
+        const CREATE = libc::XATTR_CREATE;
+
+        /// Fail if the attribute does not already exist.
+        const REPLACE = libc::XATTR_REPLACE;
+    }
+}
+
+/// The file whose attributes are operated on.
+enum Target {
+    /// A path, following symlinks.
+    Path(CString),
+
+    /// A path, operating on a symlink itself rather than the file it points to.
+    Link(CString),
+
+    /// An open file.
+    File(File),
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use super::Target;
+    use libc::{c_char, c_int, c_void, size_t, ssize_t};
+    use std::ffi::CStr;
+
+    pub(super) fn get(target: &Target, name: &CStr, value: *mut c_void, size: size_t) -> ssize_t {
+        unsafe {
+            match target {
+                Target::Path(path) => libc::getxattr(path.as_ptr(), name.as_ptr(), value, size),
+                Target::Link(path) => libc::lgetxattr(path.as_ptr(), name.as_ptr(), value, size),
+                Target::File(file) => libc::fgetxattr(*file, name.as_ptr(), value, size),
+            }
+        }
+    }
+
+    pub(super) fn set(target: &Target, name: &CStr, value: &[u8], flags: c_int) -> c_int {
+        let (ptr, size) = (value.as_ptr() as *const c_void, value.len());
+        unsafe {
+            match target {
+                Target::Path(path) => {
+                    libc::setxattr(path.as_ptr(), name.as_ptr(), ptr, size, flags)
+                }
+                Target::Link(path) => {
+                    libc::lsetxattr(path.as_ptr(), name.as_ptr(), ptr, size, flags)
+                }
+                Target::File(file) => libc::fsetxattr(*file, name.as_ptr(), ptr, size, flags),
+            }
+        }
+    }
+
+    pub(super) fn list(target: &Target, list: *mut c_char, size: size_t) -> ssize_t {
+        unsafe {
+            match target {
+                Target::Path(path) => libc::listxattr(path.as_ptr(), list, size),
+                Target::Link(path) => libc::llistxattr(path.as_ptr(), list, size),
+                Target::File(file) => libc::flistxattr(*file, list, size),
+            }
+        }
+    }
+
+    pub(super) fn remove(target: &Target, name: &CStr) -> c_int {
+        unsafe {
+            match target {
+                Target::Path(path) => libc::removexattr(path.as_ptr(), name.as_ptr()),
+                Target::Link(path) => libc::lremovexattr(path.as_ptr(), name.as_ptr()),
+                Target::File(file) => libc::fremovexattr(*file, name.as_ptr()),
+            }
+        }
+    }
+}
+
+// macOS has no l* variants; instead, each function takes XATTR_NOFOLLOW
+#[cfg(target_os = "macos")]
+mod sys {
+    use super::Target;
+    use libc::{c_char, c_int, c_void, size_t, ssize_t};
+    use std::ffi::CStr;
+
+    pub(super) fn get(target: &Target, name: &CStr, value: *mut c_void, size: size_t) -> ssize_t {
+        unsafe {
+            match target {
+                Target::Path(path) => {
+                    libc::getxattr(path.as_ptr(), name.as_ptr(), value, size, 0, 0)
+                }
+                Target::Link(path) => libc::getxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    value,
+                    size,
+                    0,
+                    libc::XATTR_NOFOLLOW,
+                ),
+                Target::File(file) => libc::fgetxattr(*file, name.as_ptr(), value, size, 0, 0),
+            }
+        }
+    }
+
+    pub(super) fn set(target: &Target, name: &CStr, value: &[u8], flags: c_int) -> c_int {
+        let (ptr, size) = (value.as_ptr() as *const c_void, value.len());
+        unsafe {
+            match target {
+                Target::Path(path) => {
+                    libc::setxattr(path.as_ptr(), name.as_ptr(), ptr, size, 0, flags)
+                }
+                Target::Link(path) => libc::setxattr(
+                    path.as_ptr(),
+                    name.as_ptr(),
+                    ptr,
+                    size,
+                    0,
+                    flags | libc::XATTR_NOFOLLOW,
+                ),
+                Target::File(file) => libc::fsetxattr(*file, name.as_ptr(), ptr, size, 0, flags),
+            }
+        }
+    }
+
+    pub(super) fn list(target: &Target, list: *mut c_char, size: size_t) -> ssize_t {
+        unsafe {
+            match target {
+                Target::Path(path) => libc::listxattr(path.as_ptr(), list, size, 0),
+                Target::Link(path) => {
+                    libc::listxattr(path.as_ptr(), list, size, libc::XATTR_NOFOLLOW)
+                }
+                Target::File(file) => libc::flistxattr(*file, list, size, 0),
+            }
+        }
+    }
+
+    pub(super) fn remove(target: &Target, name: &CStr) -> c_int {
+        unsafe {
+            match target {
+                Target::Path(path) => libc::removexattr(path.as_ptr(), name.as_ptr(), 0),
+                Target::Link(path) => {
+                    libc::removexattr(path.as_ptr(), name.as_ptr(), libc::XATTR_NOFOLLOW)
+                }
+                Target::File(file) => libc::fremovexattr(*file, name.as_ptr(), 0),
+            }
+        }
+    }
+}
+
+/// Turn the return value of an xattr function into a Result.
+fn check(ret: isize) -> crate::Result<usize> {
+    if ret < 0 {
+        Err(crate::Error::last_os_error())
+    } else {
+        Ok(ret as _)
+    }
+}
+
+fn getxattr(target: &Target, name: &CStr) -> crate::Result<Vec<u8>> {
+    loop {
+        let size = check(sys::get(target, name, std::ptr::null_mut(), 0))?;
+        let mut value = vec![0u8; size];
+        match check(sys::get(target, name, value.as_mut_ptr() as _, value.len())) {
+            Ok(len) => {
+                value.truncate(len);
+                return Ok(value);
+            }
+            // the attribute grew after its size was read
+            Err(crate::Error::ERANGE) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn setxattr(target: &Target, name: &CStr, value: &[u8], flags: XattrFlags) -> crate::Result<()> {
+    check(sys::set(target, name, value, flags.bits()) as _).map(|_| ())
+}
+
+fn listxattr(target: &Target) -> crate::Result<Vec<String>> {
+    loop {
+        let size = check(sys::list(target, std::ptr::null_mut(), 0))?;
+        let mut list = vec![0u8; size];
+        match check(sys::list(target, list.as_mut_ptr() as _, list.len())) {
+            Ok(len) => {
+                // the names are NUL terminated, one after another
+                return Ok(list[..len]
+                    .split(|&b| b == 0)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| String::from_utf8_lossy(name).into_owned())
+                    .collect());
+            }
+            Err(crate::Error::ERANGE) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn removexattr(target: &Target, name: &CStr) -> crate::Result<()> {
+    check(sys::remove(target, name) as _).map(|_| ())
+}
+
+impl crate::Loop {
+    fn _fs_getxattr<CB: Into<FsGetxattrCB<'static>>>(
+        &self,
+        target: Target,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        let name = CString::new(name)?;
+        let req = self.queue_blocking(
+            move || getxattr(&target, &name),
+            move |result: crate::Result<Vec<u8>>| cb.call(result),
+        )?;
+        Ok(req)
+    }
+
+    fn _fs_setxattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        target: Target,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        let name = CString::new(name)?;
+        let value = value.to_vec();
+        let req = self.queue_blocking(
+            move || setxattr(&target, &name, &value, flags),
+            move |result: crate::Result<()>| cb.call(result),
+        )?;
+        Ok(req)
+    }
+
+    fn _fs_listxattr<CB: Into<FsListxattrCB<'static>>>(
+        &self,
+        target: Target,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        let mut cb = cb.into();
+        self.queue_blocking(
+            move || listxattr(&target),
+            move |result: crate::Result<Vec<String>>| cb.call(result),
+        )
+    }
+
+    fn _fs_removexattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        target: Target,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        let name = CString::new(name)?;
+        let req = self.queue_blocking(
+            move || removexattr(&target, &name),
+            move |result: crate::Result<()>| cb.call(result),
+        )?;
+        Ok(req)
+    }
+
+    /// Equivalent to getxattr(2). The callback is passed the attribute's value. Extended
+    /// attribute operations are not supported by libuv itself, so they run on the threadpool as
+    /// work requests.
+    pub fn fs_getxattr<CB: Into<FsGetxattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_getxattr(Target::Path(CString::new(path)?), name, cb)
+    }
+
+    /// Equivalent to getxattr(2).
+    pub fn fs_getxattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let target = Target::Path(CString::new(path)?);
+        Ok(getxattr(&target, &CString::new(name)?)?)
+    }
+
+    /// Equivalent to lgetxattr(2).
+    pub fn fs_lgetxattr<CB: Into<FsGetxattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_getxattr(Target::Link(CString::new(path)?), name, cb)
+    }
+
+    /// Equivalent to lgetxattr(2).
+    pub fn fs_lgetxattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        let target = Target::Link(CString::new(path)?);
+        Ok(getxattr(&target, &CString::new(name)?)?)
+    }
+
+    /// Equivalent to fgetxattr(2).
+    pub fn fs_fgetxattr<CB: Into<FsGetxattrCB<'static>>>(
+        &self,
+        file: File,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_getxattr(Target::File(file), name, cb)
+    }
+
+    /// Equivalent to fgetxattr(2).
+    pub fn fs_fgetxattr_sync(
+        &self,
+        file: File,
+        name: &str,
+    ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+        Ok(getxattr(&Target::File(file), &CString::new(name)?)?)
+    }
+
+    /// Equivalent to setxattr(2).
+    pub fn fs_setxattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_setxattr(Target::Path(CString::new(path)?), name, value, flags, cb)
+    }
+
+    /// Equivalent to setxattr(2).
+    pub fn fs_setxattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target::Path(CString::new(path)?);
+        Ok(setxattr(&target, &CString::new(name)?, value, flags)?)
+    }
+
+    /// Equivalent to lsetxattr(2).
+    pub fn fs_lsetxattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_setxattr(Target::Link(CString::new(path)?), name, value, flags, cb)
+    }
+
+    /// Equivalent to lsetxattr(2).
+    pub fn fs_lsetxattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target::Link(CString::new(path)?);
+        Ok(setxattr(&target, &CString::new(name)?, value, flags)?)
+    }
+
+    /// Equivalent to fsetxattr(2).
+    pub fn fs_fsetxattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        file: File,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_setxattr(Target::File(file), name, value, flags, cb)
+    }
+
+    /// Equivalent to fsetxattr(2).
+    pub fn fs_fsetxattr_sync(
+        &self,
+        file: File,
+        name: &str,
+        value: &[u8],
+        flags: XattrFlags,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(setxattr(
+            &Target::File(file),
+            &CString::new(name)?,
+            value,
+            flags,
+        )?)
+    }
+
+    /// Equivalent to listxattr(2). The callback is passed the attribute names.
+    pub fn fs_listxattr<CB: Into<FsListxattrCB<'static>>>(
+        &self,
+        path: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        Ok(self._fs_listxattr(Target::Path(CString::new(path)?), cb)?)
+    }
+
+    /// Equivalent to listxattr(2).
+    pub fn fs_listxattr_sync(&self, path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(listxattr(&Target::Path(CString::new(path)?))?)
+    }
+
+    /// Equivalent to llistxattr(2).
+    pub fn fs_llistxattr<CB: Into<FsListxattrCB<'static>>>(
+        &self,
+        path: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        Ok(self._fs_listxattr(Target::Link(CString::new(path)?), cb)?)
+    }
+
+    /// Equivalent to llistxattr(2).
+    pub fn fs_llistxattr_sync(
+        &self,
+        path: &str,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        Ok(listxattr(&Target::Link(CString::new(path)?))?)
+    }
+
+    /// Equivalent to flistxattr(2).
+    pub fn fs_flistxattr<CB: Into<FsListxattrCB<'static>>>(
+        &self,
+        file: File,
+        cb: CB,
+    ) -> crate::Result<WorkReq> {
+        self._fs_listxattr(Target::File(file), cb)
+    }
+
+    /// Equivalent to flistxattr(2).
+    pub fn fs_flistxattr_sync(&self, file: File) -> crate::Result<Vec<String>> {
+        listxattr(&Target::File(file))
+    }
+
+    /// Equivalent to removexattr(2).
+    pub fn fs_removexattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_removexattr(Target::Path(CString::new(path)?), name, cb)
+    }
+
+    /// Equivalent to removexattr(2).
+    pub fn fs_removexattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target::Path(CString::new(path)?);
+        Ok(removexattr(&target, &CString::new(name)?)?)
+    }
+
+    /// Equivalent to lremovexattr(2).
+    pub fn fs_lremovexattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        path: &str,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_removexattr(Target::Link(CString::new(path)?), name, cb)
+    }
+
+    /// Equivalent to lremovexattr(2).
+    pub fn fs_lremovexattr_sync(
+        &self,
+        path: &str,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let target = Target::Link(CString::new(path)?);
+        Ok(removexattr(&target, &CString::new(name)?)?)
+    }
+
+    /// Equivalent to fremovexattr(2).
+    pub fn fs_fremovexattr<CB: Into<FsXattrCB<'static>>>(
+        &self,
+        file: File,
+        name: &str,
+        cb: CB,
+    ) -> Result<WorkReq, Box<dyn std::error::Error>> {
+        self._fs_removexattr(Target::File(file), name, cb)
+    }
+
+    /// Equivalent to fremovexattr(2).
+    pub fn fs_fremovexattr_sync(
+        &self,
+        file: File,
+        name: &str,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(removexattr(&Target::File(file), &CString::new(name)?)?)
+    }
+}
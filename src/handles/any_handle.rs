@@ -0,0 +1,161 @@
+use crate::{HandleTrait, HandleType, Inner, IntoInner, ToHandle};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A handle of any type, converted to its concrete handle type. See Loop::handles().
+#[derive(Clone, Copy)]
+pub enum AnyHandle {
+    Async(crate::AsyncHandle),
+    Check(crate::CheckHandle),
+    FsEvent(crate::FsEventHandle),
+    FsPoll(crate::FsPollHandle),
+    Idle(crate::IdleHandle),
+    Pipe(crate::PipeHandle),
+    Poll(crate::PollHandle),
+    Prepare(crate::PrepareHandle),
+    Process(crate::ProcessHandle),
+    Signal(crate::SignalHandle),
+    Tcp(crate::TcpHandle),
+    Timer(crate::TimerHandle),
+    Tty(crate::TtyHandle),
+    Udp(crate::UdpHandle),
+
+    /// A handle of a type that libuv-rs does not wrap.
+    Unknown(crate::Handle),
+}
+
+impl From<crate::Handle> for AnyHandle {
+    fn from(handle: crate::Handle) -> AnyHandle {
+        let ptr = handle.inner();
+        match handle.get_type() {
+            HandleType::ASYNC => AnyHandle::Async((ptr as *mut uv::uv_async_t).into_inner()),
+            HandleType::CHECK => AnyHandle::Check((ptr as *mut uv::uv_check_t).into_inner()),
+            HandleType::FS_EVENT => {
+                AnyHandle::FsEvent((ptr as *mut uv::uv_fs_event_t).into_inner())
+            }
+            HandleType::FS_POLL => AnyHandle::FsPoll((ptr as *mut uv::uv_fs_poll_t).into_inner()),
+            HandleType::IDLE => AnyHandle::Idle((ptr as *mut uv::uv_idle_t).into_inner()),
+            HandleType::NAMED_PIPE => AnyHandle::Pipe((ptr as *mut uv::uv_pipe_t).into_inner()),
+            HandleType::POLL => AnyHandle::Poll((ptr as *mut uv::uv_poll_t).into_inner()),
+            HandleType::PREPARE => AnyHandle::Prepare((ptr as *mut uv::uv_prepare_t).into_inner()),
+            HandleType::PROCESS => AnyHandle::Process((ptr as *mut uv::uv_process_t).into_inner()),
+            HandleType::SIGNAL => AnyHandle::Signal((ptr as *mut uv::uv_signal_t).into_inner()),
+            HandleType::TCP => AnyHandle::Tcp((ptr as *mut uv::uv_tcp_t).into_inner()),
+            HandleType::TIMER => AnyHandle::Timer((ptr as *mut uv::uv_timer_t).into_inner()),
+            HandleType::TTY => AnyHandle::Tty((ptr as *mut uv::uv_tty_t).into_inner()),
+            HandleType::UDP => AnyHandle::Udp((ptr as *mut uv::uv_udp_t).into_inner()),
+            _ => AnyHandle::Unknown(handle),
+        }
+    }
+}
+
+impl From<AnyHandle> for crate::Handle {
+    fn from(handle: AnyHandle) -> crate::Handle {
+        handle.to_handle()
+    }
+}
+
+impl ToHandle for AnyHandle {
+    fn to_handle(&self) -> crate::Handle {
+        match self {
+            AnyHandle::Async(h) => h.to_handle(),
+            AnyHandle::Check(h) => h.to_handle(),
+            AnyHandle::FsEvent(h) => h.to_handle(),
+            AnyHandle::FsPoll(h) => h.to_handle(),
+            AnyHandle::Idle(h) => h.to_handle(),
+            AnyHandle::Pipe(h) => h.to_handle(),
+            AnyHandle::Poll(h) => h.to_handle(),
+            AnyHandle::Prepare(h) => h.to_handle(),
+            AnyHandle::Process(h) => h.to_handle(),
+            AnyHandle::Signal(h) => h.to_handle(),
+            AnyHandle::Tcp(h) => h.to_handle(),
+            AnyHandle::Timer(h) => h.to_handle(),
+            AnyHandle::Tty(h) => h.to_handle(),
+            AnyHandle::Udp(h) => h.to_handle(),
+            AnyHandle::Unknown(h) => h.to_handle(),
+        }
+    }
+}
+
+impl HandleTrait for AnyHandle {}
+
+/// Writes a handle in the same format as uv_print_all_handles(): flags, type and address. The
+/// flags are R if the handle is referenced, A if it is active, and I if it is internal (internal
+/// handles are never returned by walk(), so that flag is always -).
+fn print_handle<W: std::io::Write>(w: &mut W, handle: &crate::Handle) -> std::io::Result<()> {
+    writeln!(
+        w,
+        "[{}{}-] {:<8} {:p}",
+        if handle.has_ref() { 'R' } else { '-' },
+        if handle.is_active() { 'A' } else { '-' },
+        handle.get_type().name(),
+        handle.inner(),
+    )
+}
+
+impl crate::Loop {
+    /// Returns all of the handles in the loop, converted to their concrete types.
+    pub fn handles(&self) -> Vec<AnyHandle> {
+        self.raw_handles()
+            .into_iter()
+            .map(AnyHandle::from)
+            .collect()
+    }
+
+    /// Returns all of the handles in the loop.
+    fn raw_handles(&self) -> Vec<crate::Handle> {
+        let handles: Rc<RefCell<Vec<crate::Handle>>> = Default::default();
+        let walk_handles = handles.clone();
+        self.walk(move |handle| walk_handles.borrow_mut().push(handle));
+        handles.replace(Vec::new())
+    }
+
+    /// Close all of the handles in the loop that are not already closing. The loop must then be
+    /// run so that the close callbacks are called.
+    pub fn close_all_handles(&self) {
+        for mut handle in self.raw_handles() {
+            if !handle.is_closing() {
+                handle.close(());
+            }
+        }
+    }
+
+    /// Prints all handles associated with the loop to the given writer. This is the equivalent of
+    /// uv_print_all_handles(), which only supports writing to a C FILE*.
+    ///
+    /// Example output:
+    ///
+    /// ```text
+    /// [R--] signal   0x604000000510
+    /// [-A-] async    0x604000000590
+    /// [RA-] timer    0x604000000610
+    /// ```
+    ///
+    /// The format is [flags] handle-type handle-address. For flags:
+    ///   * R is printed for a handle that is referenced
+    ///   * A is printed for a handle that is active
+    ///   * I is printed for a handle that is internal
+    ///
+    /// Warning: This function is meant for ad hoc debugging, there is no API/ABI stability
+    /// guarantees.
+    pub fn print_all_handles<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for handle in self.raw_handles() {
+            print_handle(w, &handle)?;
+        }
+        Ok(())
+    }
+
+    /// This is the same as print_all_handles() except only active handles are printed. This is
+    /// the equivalent of uv_print_active_handles().
+    ///
+    /// Warning: This function is meant for ad hoc debugging, there is no API/ABI stability
+    /// guarantees.
+    pub fn print_active_handles<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for handle in self.raw_handles() {
+            if handle.is_active() {
+                print_handle(w, &handle)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -10,6 +10,14 @@ callbacks! {
         prev: crate::Stat,
         curr: crate::Stat
     );
+
+    pub FsPollDiffCB(
+        handle: FsPollHandle,
+        status: crate::Result<u32>,
+        diff: crate::StatDiff,
+        prev: crate::Stat,
+        curr: crate::Stat
+    );
 }
 
 /// Additional data stored on the handle
@@ -102,6 +110,28 @@ impl FsPollHandle {
             .map_err(|e| Box::new(e) as _)
     }
 
+    /// The same as start(), except that the callback is also passed a StatDiff describing what
+    /// changed between prev and curr.
+    pub fn start_with_diff<CB: Into<FsPollDiffCB<'static>>>(
+        &mut self,
+        path: &str,
+        interval: u32,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        self.start(
+            path,
+            interval,
+            move |handle: FsPollHandle,
+                  status: crate::Result<u32>,
+                  prev: crate::Stat,
+                  curr: crate::Stat| {
+                let diff = prev.diff(&curr);
+                cb.call(handle, status, diff, prev, curr)
+            },
+        )
+    }
+
     /// Stop the handle, the callback will no longer be called.
     pub fn stop(&mut self) -> crate::Result<()> {
         crate::uvret(unsafe { uv_fs_poll_stop(self.handle) })
@@ -5,6 +5,9 @@ use addl_handle_data::*;
 pub mod handle;
 pub use handle::*;
 
+pub mod any_handle;
+pub use any_handle::*;
+
 pub mod r#async;
 pub use r#async::*;
 
@@ -29,11 +32,20 @@ pub use prepare::*;
 pub mod process;
 pub use process::*;
 
+pub mod scheduler;
+pub use scheduler::*;
+
 pub mod signal;
 pub use signal::*;
 
+pub mod signal_set;
+pub use signal_set::*;
+
 pub mod timer;
 pub use timer::*;
 
+pub mod timer_wheel;
+pub use timer_wheel::*;
+
 pub mod streams;
 pub use streams::*;
@@ -349,13 +349,15 @@ impl ProcessHandle {
 
     /// Sends the specified signal to the given process handle. Check the documentation on
     /// SignalHandle for signal support, specially on Windows.
-    pub fn kill(&mut self, signum: i32) -> crate::Result<()> {
+    pub fn kill<S: Into<crate::Signal>>(&mut self, signum: S) -> crate::Result<()> {
+        let signum = signum.into().signum();
         crate::uvret(unsafe { uv_process_kill(self.handle, signum) })
     }
 
     /// Sends the specified signal to the given PID. Check the documentation on SignalHandle for
     /// signal support, specially on Windows.
-    pub fn kill_pid(pid: i32, signum: i32) -> crate::Result<()> {
+    pub fn kill_pid<S: Into<crate::Signal>>(pid: i32, signum: S) -> crate::Result<()> {
+        let signum = signum.into().signum();
         crate::uvret(unsafe { uv_kill(pid, signum) })
     }
 }
@@ -0,0 +1,588 @@
+use crate::{HandleTrait, TimerHandle};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+callbacks! {
+    pub ScheduleCB(job: ScheduledJob, tick: ScheduleTick);
+}
+
+const MINUTE: i64 = 60_000;
+const DAY_MINUTES: i64 = 24 * 60;
+
+/// How far ahead to look for a day matching a cron expression. The rarest day, the 29th of
+/// February, can be eight years away, as 2100 is not a leap year.
+const MAX_DAYS: i64 = 8 * 366;
+
+/// The most ticks one timer callback will deliver with MissedTicks::CatchUp.
+const MAX_CATCH_UP: u64 = 1024;
+
+/// A cron expression in the five field format of crontab(5): minute, hour, day of month, month,
+/// and day of week. Each field is a comma separated list of values, ranges (1-5) or "*", each
+/// optionally followed by a step (*/15); months and days of the week can also be given by their
+/// first three letters (jan, mon). Sunday is 0 or 7. As in cron, if both the day of month and the
+/// day of week are restricted, a day matches if either does.
+///
+/// The shorthands @yearly (or @annually), @monthly, @weekly, @daily (or @midnight) and @hourly are
+/// also accepted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CronExpr {
+    /// Bitmasks of the matching values of each field
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+
+    /// Whether the day of month and day of week fields were "*"
+    any_day: bool,
+    any_weekday: bool,
+}
+
+const MONTH_NAMES: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+/// Parse one field of a cron expression into a bitmask of the values between min and max.
+fn parse_field(field: &str, min: u32, max: u32, names: &[&str]) -> crate::Result<u64> {
+    let value = |s: &str| -> crate::Result<u32> {
+        let lower = s.to_ascii_lowercase();
+        if let Some(i) = names.iter().position(|name| *name == lower) {
+            return Ok(min + i as u32);
+        }
+        match s.parse::<u32>() {
+            Ok(v) if (min..=max).contains(&v) => Ok(v),
+            _ => Err(crate::Error::EINVAL),
+        }
+    };
+
+    let mut mask = 0u64;
+    for item in field.split(',') {
+        let mut parts = item.splitn(2, '/');
+        let range = parts.next().unwrap_or("");
+        let step = match parts.next() {
+            Some(step) => match step.parse::<u32>() {
+                Ok(step) if step > 0 => step,
+                _ => return Err(crate::Error::EINVAL),
+            },
+            None => 1,
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else {
+            let mut bounds = range.splitn(2, '-');
+            let start = value(bounds.next().unwrap_or(""))?;
+            match bounds.next() {
+                Some(end) => (start, value(end)?),
+                // "5/10" means from 5 to the maximum, in steps of 10
+                None if step > 1 => (start, max),
+                None => (start, start),
+            }
+        };
+        if start > end {
+            return Err(crate::Error::EINVAL);
+        }
+        for v in (start..=end).step_by(step as usize) {
+            mask |= 1 << v;
+        }
+    }
+    Ok(mask)
+}
+
+impl CronExpr {
+    /// Parse a cron expression. Fails with EINVAL if it is malformed.
+    pub fn parse(expr: &str) -> crate::Result<CronExpr> {
+        let expr = match expr.trim() {
+            "@yearly" | "@annually" => "0 0 1 1 *",
+            "@monthly" => "0 0 1 * *",
+            "@weekly" => "0 0 * * 0",
+            "@daily" | "@midnight" => "0 0 * * *",
+            "@hourly" => "0 * * * *",
+            expr => expr,
+        };
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err(crate::Error::EINVAL);
+        }
+
+        // day of week 7 is another name for Sunday
+        let mut weekdays = parse_field(fields[4], 0, 7, &WEEKDAY_NAMES)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronExpr {
+            minutes: parse_field(fields[0], 0, 59, &[])?,
+            hours: parse_field(fields[1], 0, 23, &[])?,
+            days: parse_field(fields[2], 1, 31, &[])?,
+            months: parse_field(fields[3], 1, 12, &MONTH_NAMES)?,
+            weekdays,
+            any_day: fields[2].starts_with('*'),
+            any_weekday: fields[4].starts_with('*'),
+        })
+    }
+
+    fn matches_day(&self, days: i64) -> bool {
+        let (_, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 4).rem_euclid(7);
+        if self.months & (1 << month) == 0 {
+            return false;
+        }
+        let day_matches = self.days & (1 << day) != 0;
+        let weekday_matches = self.weekdays & (1 << weekday) != 0;
+        if self.any_day || self.any_weekday {
+            day_matches && weekday_matches
+        } else {
+            day_matches || weekday_matches
+        }
+    }
+
+    /// Returns the first matching minute of the day at or after the given minute, if there is one.
+    fn first_minute(&self, from: i64) -> Option<i64> {
+        for hour in from / 60..24 {
+            if self.hours & (1 << hour) == 0 {
+                continue;
+            }
+            let first = if hour == from / 60 { from % 60 } else { 0 };
+            for minute in first..60 {
+                if self.minutes & (1 << minute) != 0 {
+                    return Some(hour * 60 + minute);
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the first matching minute after time, both in milliseconds since the Unix epoch,
+    /// or None if there isn't one in the next eight years. Times are taken to be in UTC.
+    pub fn next_after(&self, time: i64) -> Option<i64> {
+        let next = time.div_euclid(MINUTE) + 1;
+        let first_day = next.div_euclid(DAY_MINUTES);
+        for days in first_day..first_day + MAX_DAYS {
+            let from = if days == first_day {
+                next.rem_euclid(DAY_MINUTES)
+            } else {
+                0
+            };
+            if self.matches_day(days) {
+                if let Some(minute) = self.first_minute(from) {
+                    return Some((days * DAY_MINUTES + minute) * MINUTE);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// Convert days since the Unix epoch to a (year, month, day) date in the proleptic Gregorian
+/// calendar, using Howard Hinnant's civil_from_days algorithm.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month as u32, day as u32)
+}
+
+/// When a scheduled job runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Schedule {
+    /// Every interval milliseconds, aligned to the wall clock: the job runs when the time since
+    /// the Unix epoch, less offset, is a multiple of interval. For example, an interval of 60000
+    /// runs the job on every minute, and an offset of 5000 runs it five seconds past.
+    Every { interval: u64, offset: u64 },
+
+    /// Whenever a cron expression matches.
+    Cron(CronExpr),
+}
+
+impl Schedule {
+    /// Every interval milliseconds, aligned to the wall clock, with no offset.
+    pub fn every(interval: u64) -> Schedule {
+        Schedule::Every {
+            interval,
+            offset: 0,
+        }
+    }
+
+    /// Whenever the cron expression matches. Fails with EINVAL if it is malformed.
+    pub fn cron(expr: &str) -> crate::Result<Schedule> {
+        CronExpr::parse(expr).map(Schedule::Cron)
+    }
+
+    /// Returns the first time the schedule matches after time, both in milliseconds since the
+    /// Unix epoch. utc_offset is the offset of local time from UTC, in seconds: the schedule is
+    /// aligned to local time.
+    pub fn next_after(&self, time: u64, utc_offset: i64) -> Option<u64> {
+        let local = time as i64 + utc_offset * 1000;
+        let next = match *self {
+            Schedule::Every { interval, offset } => {
+                let interval = interval.max(1) as i64;
+                let offset = offset as i64;
+                Some(((local - offset).div_euclid(interval) + 1) * interval + offset)
+            }
+            Schedule::Cron(expr) => expr.next_after(local),
+        };
+        next.map(|next| (next - utc_offset * 1000).max(0) as u64)
+    }
+}
+
+/// What to do about ticks which were missed because the loop was blocked, or the system was
+/// suspended.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MissedTicks {
+    /// Run the job once, for the latest tick; ScheduleTick::missed says how many were skipped.
+    Skip,
+
+    /// Run the job once for each missed tick, one after another, up to 1024 at a time.
+    CatchUp,
+}
+
+/// Options for Loop::schedule().
+#[derive(Clone, Copy, Debug)]
+pub struct ScheduleOptions {
+    /// Delay each run by a random amount of up to this many milliseconds, so that jobs on many
+    /// machines don't all run at the same moment. Defaults to 0.
+    pub jitter: u64,
+
+    /// What to do about missed ticks. Defaults to Skip.
+    pub missed: MissedTicks,
+
+    /// The offset of local time from UTC, in seconds, which the schedule is aligned to. Daylight
+    /// saving time is not accounted for. Defaults to 0, for UTC.
+    pub utc_offset: i64,
+}
+
+impl Default for ScheduleOptions {
+    fn default() -> ScheduleOptions {
+        ScheduleOptions {
+            jitter: 0,
+            missed: MissedTicks::Skip,
+            utc_offset: 0,
+        }
+    }
+}
+
+/// Passed to a scheduled job each time it runs.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ScheduleTick {
+    /// When the job was due to run, in milliseconds since the Unix epoch, before jitter.
+    pub scheduled: u64,
+
+    /// The number of earlier ticks which were skipped, with MissedTicks::Skip.
+    pub missed: u64,
+}
+
+struct JobState {
+    schedule: Schedule,
+    options: ScheduleOptions,
+    timer: TimerHandle,
+
+    /// The tick the timer is set for, and the time it is set for, including jitter
+    next: Option<(u64, u64)>,
+    active: bool,
+    cb: ScheduleCB<'static>,
+}
+
+/// Returns the wall clock time in milliseconds since the Unix epoch.
+fn wall_clock() -> crate::Result<u64> {
+    let tv = crate::gettimeofday()?;
+    Ok((tv.sec * 1000 + tv.usec / 1000).max(0) as u64)
+}
+
+/// A job scheduled with Loop::schedule(). It runs until stop() is called.
+#[derive(Clone)]
+pub struct ScheduledJob {
+    state: Rc<RefCell<JobState>>,
+}
+
+impl ScheduledJob {
+    /// Returns when the job will next run, in milliseconds since the Unix epoch, including
+    /// jitter; or None if it has been stopped, or its schedule never matches again.
+    pub fn next_run(&self) -> Option<u64> {
+        let state = self.state.borrow();
+        if state.active {
+            state.next.map(|(_, at)| at)
+        } else {
+            None
+        }
+    }
+
+    /// Returns true until the job is stopped.
+    pub fn is_active(&self) -> bool {
+        self.state.borrow().active
+    }
+
+    /// Stop the job and close its timer. This may be called from the job's callback.
+    pub fn stop(&self) {
+        let cb = {
+            let mut state = self.state.borrow_mut();
+            if !state.active {
+                return;
+            }
+            state.active = false;
+            state.timer.close(());
+            std::mem::take(&mut state.cb)
+        };
+
+        // drop the callback after the borrow ends, in case it holds the last clone of this job
+        drop(cb);
+    }
+
+    /// Work out the first tick after `after`, and set the timer for it. The timer repeats, so it
+    /// is re-armed with set_repeat() and again(), which are safe to call from its own callback.
+    fn arm(&self, after: u64, now: u64) -> crate::Result<()> {
+        let mut state = self.state.borrow_mut();
+        let tick = match state.schedule.next_after(after, state.options.utc_offset) {
+            Some(tick) => tick,
+            None => {
+                state.next = None;
+                return state.timer.stop();
+            }
+        };
+        let jitter = match state.options.jitter {
+            0 => 0,
+            jitter => crate::random_u64() % (jitter + 1),
+        };
+        let at = tick.saturating_add(jitter);
+        state.next = Some((tick, at));
+        let delay = at.saturating_sub(now).max(1);
+        state.timer.set_repeat(delay);
+        state.timer.again()
+    }
+
+    fn on_timer(&self) {
+        let now = match wall_clock() {
+            Ok(now) => now,
+            Err(_) => return,
+        };
+        let (tick, at) = match self.state.borrow().next {
+            Some(next) => next,
+            None => return,
+        };
+
+        // the timer runs on the monotonic clock, so if the wall clock was set back, it can fire
+        // early
+        if now < at {
+            let _ = self.arm(tick.saturating_sub(1), now);
+            return;
+        }
+
+        // find the ticks which were missed, if the loop was blocked
+        let (schedule, options) = {
+            let state = self.state.borrow();
+            (state.schedule, state.options)
+        };
+        let mut ticks = vec![tick];
+        let mut last = tick;
+        let mut missed = 0;
+        match (schedule, options.missed) {
+            (Schedule::Every { interval, .. }, MissedTicks::Skip) => {
+                // the ticks are evenly spaced, so there's no need to step through them
+                let interval = interval.max(1);
+                missed = (now - tick) / interval;
+                last = tick + missed * interval;
+            }
+            _ => {
+                while let Some(next) = schedule.next_after(last, options.utc_offset) {
+                    if next > now || ticks.len() as u64 >= MAX_CATCH_UP {
+                        break;
+                    }
+                    last = next;
+                    missed += 1;
+                    if options.missed == MissedTicks::CatchUp {
+                        ticks.push(next);
+                    }
+                }
+            }
+        }
+        if options.missed == MissedTicks::Skip {
+            ticks = vec![last];
+        }
+        if self.arm(last, now).is_err() {
+            self.state.borrow_mut().next = None;
+        }
+
+        for scheduled in ticks {
+            let mut cb = {
+                let mut state = self.state.borrow_mut();
+                if !state.active {
+                    return;
+                }
+                std::mem::take(&mut state.cb)
+            };
+            let tick = ScheduleTick {
+                scheduled,
+                missed: if options.missed == MissedTicks::Skip {
+                    missed
+                } else {
+                    0
+                },
+            };
+            cb.call(self.clone(), tick);
+
+            // put the callback back, unless the job was stopped while it ran
+            let mut state = self.state.borrow_mut();
+            if state.active {
+                state.cb = cb;
+            }
+        }
+    }
+}
+
+impl crate::watchdog::WatchdogSource for ScheduledJob {
+    fn watchdog_source(&self) -> crate::CallbackSource {
+        crate::CallbackSource::Handle(crate::HandleType::TIMER)
+    }
+}
+
+impl crate::Loop {
+    /// Run cb on a wall clock schedule, such as every minute on the minute, or a cron expression.
+    /// Unlike TimerHandle, whose times are relative to the loop's monotonic clock, the schedule
+    /// is followed with gettimeofday(), so a job doesn't drift from the wall clock; if the clock
+    /// is set back, the job waits for the wall clock to catch up.
+    ///
+    /// The callback is passed the ScheduledJob, so that it can stop itself, and the ScheduleTick
+    /// it is running for. The job runs until it is stopped.
+    pub fn schedule<CB: Into<ScheduleCB<'static>>>(
+        &self,
+        schedule: Schedule,
+        options: ScheduleOptions,
+        cb: CB,
+    ) -> crate::Result<ScheduledJob> {
+        let mut timer = self.timer()?;
+        let job = ScheduledJob {
+            state: Rc::new(RefCell::new(JobState {
+                schedule,
+                options,
+                timer,
+                next: None,
+                active: true,
+                cb: cb.into(),
+            })),
+        };
+
+        // the timer's callback holds the job until it is stopped
+        let timer_job = job.clone();
+        let result = timer
+            .start(1, 1, move |_: TimerHandle| timer_job.on_timer())
+            .and_then(|_| wall_clock())
+            .and_then(|now| job.arm(now, now));
+        if let Err(e) = result {
+            job.stop();
+            return Err(e);
+        }
+        Ok(job)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{civil_from_days, CronExpr};
+
+    const DAY: i64 = 86_400_000;
+
+    /// 2024-01-01T00:00:00Z, a Monday
+    const JAN_1_2024: i64 = 1_704_067_200_000;
+
+    fn next(expr: &str, time: i64) -> Option<i64> {
+        CronExpr::parse(expr).unwrap().next_after(time)
+    }
+
+    #[test]
+    fn parse_rejects_malformed_expressions() {
+        for expr in &[
+            "",
+            "* * * *",
+            "* * * * * *",
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+            "* * * foo *",
+        ] {
+            assert!(CronExpr::parse(expr).is_err(), "{:?} was accepted", expr);
+        }
+    }
+
+    #[test]
+    fn parse_accepts_sunday_as_7() {
+        let sunday = CronExpr::parse("0 0 * * 0").unwrap();
+        assert_eq!(CronExpr::parse("0 0 * * 7").unwrap(), sunday);
+        assert_eq!(CronExpr::parse("0 0 * * SUN").unwrap(), sunday);
+        assert_eq!(CronExpr::parse("@weekly").unwrap(), sunday);
+        assert_eq!(
+            CronExpr::parse("0 0 * * 5-7").unwrap(),
+            CronExpr::parse("0 0 * * 0,5,6").unwrap()
+        );
+
+        // 2024-01-07 was a Sunday
+        assert_eq!(next("0 0 * * 7", JAN_1_2024), Some(JAN_1_2024 + 6 * DAY));
+    }
+
+    #[test]
+    fn next_after_matches_day_of_month_or_day_of_week() {
+        // both restricted: the 13th, or any Friday
+        let expr = "0 0 13 * 5";
+        assert_eq!(next(expr, JAN_1_2024), Some(JAN_1_2024 + 4 * DAY));
+        assert_eq!(
+            next(expr, JAN_1_2024 + 4 * DAY),
+            Some(JAN_1_2024 + 11 * DAY)
+        );
+        assert_eq!(
+            next(expr, JAN_1_2024 + 11 * DAY),
+            Some(JAN_1_2024 + 12 * DAY)
+        );
+
+        // only one restricted
+        assert_eq!(next("0 0 13 * *", JAN_1_2024), Some(JAN_1_2024 + 12 * DAY));
+        assert_eq!(next("0 0 * * 5", JAN_1_2024), Some(JAN_1_2024 + 4 * DAY));
+        assert_eq!(next("0 0 */2 * 5", JAN_1_2024), Some(JAN_1_2024 + 4 * DAY));
+    }
+
+    #[test]
+    fn next_after_is_strictly_after() {
+        assert_eq!(next("* * * * *", JAN_1_2024), Some(JAN_1_2024 + 60_000));
+        assert_eq!(next("* * * * *", JAN_1_2024 + 1), Some(JAN_1_2024 + 60_000));
+        assert_eq!(
+            next("30 9 * * *", JAN_1_2024),
+            Some(JAN_1_2024 + 570 * 60_000)
+        );
+    }
+
+    #[test]
+    fn next_after_finds_february_29() {
+        // from 2024-03-01 to 2028-02-29T12:00:00Z
+        assert_eq!(
+            next("0 12 29 2 *", 1_709_251_200_000),
+            Some(1_835_438_400_000)
+        );
+
+        // 2100 isn't a leap year, so from 2096-03-01 to 2104-02-29T12:00:00Z
+        assert_eq!(
+            next("0 12 29 2 *", 3_981_398_400_000),
+            Some(4_233_729_600_000)
+        );
+
+        // and February 30 never comes
+        assert_eq!(next("0 0 30 2 *", JAN_1_2024), None);
+    }
+
+    #[test]
+    fn civil_from_days_converts_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(19_723), (2024, 1, 1));
+        assert_eq!(civil_from_days(11_016), (2000, 2, 29));
+        assert_eq!(civil_from_days(47_540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47_541), (2100, 3, 1));
+    }
+}
@@ -2,6 +2,288 @@ use crate::{FromInner, HandleTrait, Inner, IntoInner};
 use std::convert::TryFrom;
 use uv::{uv_signal_init, uv_signal_start, uv_signal_start_oneshot, uv_signal_stop, uv_signal_t};
 
+/// Signals that can be watched with a SignalHandle, or sent with ProcessHandle::kill(). See the
+/// documentation on SignalHandle for the caveats around each signal, especially on Windows.
+///
+/// Signals are compared and hashed by their signal number, so a signal given as OTHER or SIGRT is
+/// equal to the named variant with the same number.
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug)]
+pub enum Signal {
+    /// Hangup detected on controlling terminal. On Windows, this is generated when the user
+    /// closes the console window.
+    SIGHUP,
+
+    /// Interrupt from keyboard (CTRL+C).
+    SIGINT,
+
+    /// Quit from keyboard.
+    SIGQUIT,
+
+    /// Illegal instruction.
+    SIGILL,
+
+    /// Abort signal from abort().
+    SIGABRT,
+
+    /// Floating-point exception.
+    SIGFPE,
+
+    /// Kill signal. Cannot be caught.
+    SIGKILL,
+
+    /// Invalid memory reference.
+    SIGSEGV,
+
+    /// Termination signal.
+    SIGTERM,
+
+    /// Window resize signal.
+    SIGWINCH,
+
+    /// Delivered when the user presses CTRL+BREAK (Windows only).
+    #[cfg(windows)]
+    SIGBREAK,
+
+    /// Trace/breakpoint trap.
+    #[cfg(not(windows))]
+    SIGTRAP,
+
+    /// Bus error (bad memory access).
+    #[cfg(not(windows))]
+    SIGBUS,
+
+    /// User-defined signal 1.
+    #[cfg(not(windows))]
+    SIGUSR1,
+
+    /// User-defined signal 2.
+    #[cfg(not(windows))]
+    SIGUSR2,
+
+    /// Broken pipe: write to pipe with no readers.
+    #[cfg(not(windows))]
+    SIGPIPE,
+
+    /// Timer signal from alarm(2).
+    #[cfg(not(windows))]
+    SIGALRM,
+
+    /// Child stopped or terminated.
+    #[cfg(not(windows))]
+    SIGCHLD,
+
+    /// Continue if stopped.
+    #[cfg(not(windows))]
+    SIGCONT,
+
+    /// Stop process. Cannot be caught.
+    #[cfg(not(windows))]
+    SIGSTOP,
+
+    /// Stop typed at terminal.
+    #[cfg(not(windows))]
+    SIGTSTP,
+
+    /// Terminal input for background process.
+    #[cfg(not(windows))]
+    SIGTTIN,
+
+    /// Terminal output for background process.
+    #[cfg(not(windows))]
+    SIGTTOU,
+
+    /// Urgent condition on socket.
+    #[cfg(not(windows))]
+    SIGURG,
+
+    /// CPU time limit exceeded.
+    #[cfg(not(windows))]
+    SIGXCPU,
+
+    /// File size limit exceeded.
+    #[cfg(not(windows))]
+    SIGXFSZ,
+
+    /// Virtual alarm clock.
+    #[cfg(not(windows))]
+    SIGVTALRM,
+
+    /// Profiling timer expired.
+    #[cfg(not(windows))]
+    SIGPROF,
+
+    /// Bad system call.
+    #[cfg(not(windows))]
+    SIGSYS,
+
+    /// Real-time signal SIGRTMIN+n (Linux only). Note that SIGRTMIN is determined at runtime by
+    /// the C library, which reserves a few of the lowest real-time signals for itself.
+    #[cfg(target_os = "linux")]
+    SIGRT(i32),
+
+    /// Any other signal number.
+    OTHER(i32),
+}
+
+impl Signal {
+    /// Returns the signal number.
+    pub fn signum(&self) -> i32 {
+        match self {
+            Signal::SIGHUP => uv::SIGHUP as _,
+            Signal::SIGINT => uv::SIGINT as _,
+            Signal::SIGQUIT => uv::SIGQUIT as _,
+            Signal::SIGILL => uv::SIGILL as _,
+            Signal::SIGABRT => uv::SIGABRT as _,
+            Signal::SIGFPE => uv::SIGFPE as _,
+            Signal::SIGKILL => uv::SIGKILL as _,
+            Signal::SIGSEGV => uv::SIGSEGV as _,
+            Signal::SIGTERM => uv::SIGTERM as _,
+            Signal::SIGWINCH => uv::SIGWINCH as _,
+            #[cfg(windows)]
+            Signal::SIGBREAK => uv::SIGBREAK as _,
+            #[cfg(not(windows))]
+            Signal::SIGTRAP => uv::SIGTRAP as _,
+            #[cfg(not(windows))]
+            Signal::SIGBUS => uv::SIGBUS as _,
+            #[cfg(not(windows))]
+            Signal::SIGUSR1 => uv::SIGUSR1 as _,
+            #[cfg(not(windows))]
+            Signal::SIGUSR2 => uv::SIGUSR2 as _,
+            #[cfg(not(windows))]
+            Signal::SIGPIPE => uv::SIGPIPE as _,
+            #[cfg(not(windows))]
+            Signal::SIGALRM => uv::SIGALRM as _,
+            #[cfg(not(windows))]
+            Signal::SIGCHLD => uv::SIGCHLD as _,
+            #[cfg(not(windows))]
+            Signal::SIGCONT => uv::SIGCONT as _,
+            #[cfg(not(windows))]
+            Signal::SIGSTOP => uv::SIGSTOP as _,
+            #[cfg(not(windows))]
+            Signal::SIGTSTP => uv::SIGTSTP as _,
+            #[cfg(not(windows))]
+            Signal::SIGTTIN => uv::SIGTTIN as _,
+            #[cfg(not(windows))]
+            Signal::SIGTTOU => uv::SIGTTOU as _,
+            #[cfg(not(windows))]
+            Signal::SIGURG => uv::SIGURG as _,
+            #[cfg(not(windows))]
+            Signal::SIGXCPU => uv::SIGXCPU as _,
+            #[cfg(not(windows))]
+            Signal::SIGXFSZ => uv::SIGXFSZ as _,
+            #[cfg(not(windows))]
+            Signal::SIGVTALRM => uv::SIGVTALRM as _,
+            #[cfg(not(windows))]
+            Signal::SIGPROF => uv::SIGPROF as _,
+            #[cfg(not(windows))]
+            Signal::SIGSYS => uv::SIGSYS as _,
+            #[cfg(target_os = "linux")]
+            Signal::SIGRT(n) => libc::SIGRTMIN() + n,
+            Signal::OTHER(signum) => *signum,
+        }
+    }
+
+    /// Returns the signal for the given signal number.
+    pub fn from_signum(signum: i32) -> Signal {
+        #[cfg(target_os = "linux")]
+        {
+            if (libc::SIGRTMIN()..=libc::SIGRTMAX()).contains(&signum) {
+                return Signal::SIGRT(signum - libc::SIGRTMIN());
+            }
+        }
+
+        match signum as u32 {
+            uv::SIGHUP => Signal::SIGHUP,
+            uv::SIGINT => Signal::SIGINT,
+            uv::SIGQUIT => Signal::SIGQUIT,
+            uv::SIGILL => Signal::SIGILL,
+            uv::SIGABRT => Signal::SIGABRT,
+            uv::SIGFPE => Signal::SIGFPE,
+            uv::SIGKILL => Signal::SIGKILL,
+            uv::SIGSEGV => Signal::SIGSEGV,
+            uv::SIGTERM => Signal::SIGTERM,
+            uv::SIGWINCH => Signal::SIGWINCH,
+            #[cfg(windows)]
+            uv::SIGBREAK => Signal::SIGBREAK,
+            #[cfg(not(windows))]
+            uv::SIGTRAP => Signal::SIGTRAP,
+            #[cfg(not(windows))]
+            uv::SIGBUS => Signal::SIGBUS,
+            #[cfg(not(windows))]
+            uv::SIGUSR1 => Signal::SIGUSR1,
+            #[cfg(not(windows))]
+            uv::SIGUSR2 => Signal::SIGUSR2,
+            #[cfg(not(windows))]
+            uv::SIGPIPE => Signal::SIGPIPE,
+            #[cfg(not(windows))]
+            uv::SIGALRM => Signal::SIGALRM,
+            #[cfg(not(windows))]
+            uv::SIGCHLD => Signal::SIGCHLD,
+            #[cfg(not(windows))]
+            uv::SIGCONT => Signal::SIGCONT,
+            #[cfg(not(windows))]
+            uv::SIGSTOP => Signal::SIGSTOP,
+            #[cfg(not(windows))]
+            uv::SIGTSTP => Signal::SIGTSTP,
+            #[cfg(not(windows))]
+            uv::SIGTTIN => Signal::SIGTTIN,
+            #[cfg(not(windows))]
+            uv::SIGTTOU => Signal::SIGTTOU,
+            #[cfg(not(windows))]
+            uv::SIGURG => Signal::SIGURG,
+            #[cfg(not(windows))]
+            uv::SIGXCPU => Signal::SIGXCPU,
+            #[cfg(not(windows))]
+            uv::SIGXFSZ => Signal::SIGXFSZ,
+            #[cfg(not(windows))]
+            uv::SIGVTALRM => Signal::SIGVTALRM,
+            #[cfg(not(windows))]
+            uv::SIGPROF => Signal::SIGPROF,
+            #[cfg(not(windows))]
+            uv::SIGSYS => Signal::SIGSYS,
+            _ => Signal::OTHER(signum),
+        }
+    }
+}
+
+impl From<i32> for Signal {
+    fn from(signum: i32) -> Signal {
+        Signal::from_signum(signum)
+    }
+}
+
+impl From<Signal> for i32 {
+    fn from(signal: Signal) -> i32 {
+        signal.signum()
+    }
+}
+
+impl PartialEq for Signal {
+    fn eq(&self, other: &Signal) -> bool {
+        self.signum() == other.signum()
+    }
+}
+
+impl Eq for Signal {}
+
+impl std::hash::Hash for Signal {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.signum().hash(state);
+    }
+}
+
+impl std::fmt::Display for Signal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            #[cfg(target_os = "linux")]
+            Signal::SIGRT(n) => write!(f, "SIGRTMIN+{}", n),
+            Signal::OTHER(signum) => write!(f, "signal {}", signum),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
 callbacks! {
     pub SignalCB(handle: SignalHandle, signum: i32);
 }
@@ -76,8 +358,13 @@ impl SignalHandle {
         Ok(SignalHandle { handle })
     }
 
-    /// Start the handle with the given callback, watching for the given signal.
-    pub fn start<CB: Into<SignalCB<'static>>>(&mut self, cb: CB, signum: i32) -> crate::Result<()> {
+    /// Start the handle with the given callback, watching for the given signal. signum may be a
+    /// Signal or a raw signal number.
+    pub fn start<CB: Into<SignalCB<'static>>, S: Into<Signal>>(
+        &mut self,
+        cb: CB,
+        signum: S,
+    ) -> crate::Result<()> {
         // uv_cb is either Some(uv_signal_cb) or None
         let cb = cb.into();
         let uv_cb = use_c_callback!(uv_signal_cb, cb);
@@ -90,15 +377,16 @@ impl SignalHandle {
             }
         }
 
+        let signum = signum.into().signum();
         crate::uvret(unsafe { uv_signal_start(self.handle, uv_cb, signum as _) })
     }
 
     /// Same functionality as start() but the signal handler is reset the moment the signal is
     /// received.
-    pub fn start_oneshot<CB: Into<SignalCB<'static>>>(
+    pub fn start_oneshot<CB: Into<SignalCB<'static>>, S: Into<Signal>>(
         &mut self,
         cb: CB,
-        signum: i32,
+        signum: S,
     ) -> crate::Result<()> {
         // uv_cb is either Some(uv_signal_cb) or None
         let cb = cb.into();
@@ -112,6 +400,7 @@ impl SignalHandle {
             }
         }
 
+        let signum = signum.into().signum();
         crate::uvret(unsafe { uv_signal_start_oneshot(self.handle, uv_cb, signum as _) })
     }
 
@@ -124,6 +413,11 @@ impl SignalHandle {
     pub fn signum(&self) -> i32 {
         unsafe { (*self.handle).signum }
     }
+
+    /// Signal being monitored by this handle, as a Signal.
+    pub fn signal(&self) -> Signal {
+        Signal::from_signum(self.signum())
+    }
 }
 
 impl FromInner<*mut uv_signal_t> for SignalHandle {
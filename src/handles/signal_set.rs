@@ -0,0 +1,151 @@
+use crate::{HandleTrait, Signal, SignalHandle};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+callbacks! {
+    pub SignalSetCB(handle: SignalHandle, signal: Signal);
+}
+
+/// A SignalSet watches several signals with a single callback. It creates and manages one
+/// SignalHandle per signal.
+///
+/// SignalSets are cheap to clone: clones share the same underlying handles, so a clone can be
+/// moved into a callback in order to close() the set from within it.
+#[derive(Clone)]
+pub struct SignalSet {
+    r#loop: crate::Loop,
+    handles: Rc<RefCell<Vec<SignalHandle>>>,
+}
+
+impl SignalSet {
+    /// Create a new, empty, signal set.
+    pub fn new(r#loop: &crate::Loop) -> SignalSet {
+        SignalSet {
+            r#loop: r#loop.clone(),
+            handles: Rc::new(RefCell::new(Vec::new())),
+        }
+    }
+
+    /// Create a signal set for the classic daemon pattern: shutdown is called when SIGINT or
+    /// SIGTERM is received, and reload is called when SIGHUP is received. After shutdown returns,
+    /// every handle in the set is closed so that the set no longer keeps the loop alive.
+    pub fn graceful_shutdown<S, R>(
+        r#loop: &crate::Loop,
+        shutdown: S,
+        reload: R,
+    ) -> crate::Result<SignalSet>
+    where
+        S: Into<SignalSetCB<'static>>,
+        R: Into<SignalSetCB<'static>>,
+    {
+        let mut set = SignalSet::new(r#loop);
+
+        let mut shutdown = shutdown.into();
+        let mut closer = set.clone();
+        let result = set
+            .start(
+                &[Signal::SIGINT, Signal::SIGTERM],
+                move |handle: SignalHandle, signal: Signal| {
+                    shutdown.call(handle, signal);
+                    closer.close();
+                },
+            )
+            .and_then(|_| set.start(&[Signal::SIGHUP], reload));
+        if let Err(e) = result {
+            set.close();
+            return Err(e);
+        }
+
+        Ok(set)
+    }
+
+    /// Start watching each of the given signals, calling cb whenever any of them is received. A
+    /// new SignalHandle is created for each signal. If any of the handles fail to start, the
+    /// handles created by this call are closed and the error is returned.
+    pub fn start<CB: Into<SignalSetCB<'static>>>(
+        &mut self,
+        signals: &[Signal],
+        cb: CB,
+    ) -> crate::Result<()> {
+        let cb = Rc::new(RefCell::new(cb.into()));
+        let mut started = Vec::with_capacity(signals.len());
+        for signal in signals {
+            let result = SignalHandle::new(&self.r#loop).and_then(|mut handle| {
+                let cb = cb.clone();
+                let result = handle.start(
+                    move |handle: SignalHandle, signum: i32| {
+                        cb.borrow_mut().call(handle, Signal::from_signum(signum))
+                    },
+                    *signal,
+                );
+                if result.is_err() {
+                    handle.close(());
+                }
+                result.map(|_| handle)
+            });
+
+            match result {
+                Ok(handle) => started.push(handle),
+                Err(e) => {
+                    for mut handle in started {
+                        handle.close(());
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        self.handles.borrow_mut().extend(started);
+        Ok(())
+    }
+
+    /// Stop all of the handles in the set. The callbacks will no longer be called, but the
+    /// handles remain open. Returns the first error encountered, if any.
+    pub fn stop(&mut self) -> crate::Result<()> {
+        let mut result = Ok(());
+        for handle in self.handles.borrow_mut().iter_mut() {
+            let ret = handle.stop();
+            if result.is_ok() {
+                result = ret;
+            }
+        }
+        result
+    }
+
+    /// Close all of the handles in the set, and remove them from the set.
+    pub fn close(&mut self) {
+        let handles: Vec<SignalHandle> = self.handles.borrow_mut().drain(..).collect();
+        for mut handle in handles {
+            if !handle.is_closing() {
+                handle.close(());
+            }
+        }
+    }
+
+    /// Reference all of the handles in the set. See HandleTrait::r#ref().
+    pub fn r#ref(&mut self) {
+        for handle in self.handles.borrow_mut().iter_mut() {
+            handle.r#ref();
+        }
+    }
+
+    /// Un-reference all of the handles in the set, so that the set does not keep the loop alive
+    /// on its own. See HandleTrait::unref().
+    pub fn unref(&mut self) {
+        for handle in self.handles.borrow_mut().iter_mut() {
+            handle.unref();
+        }
+    }
+
+    /// The signals currently being watched by this set.
+    pub fn signals(&self) -> Vec<Signal> {
+        self.handles.borrow().iter().map(|h| h.signal()).collect()
+    }
+}
+
+impl crate::Loop {
+    /// Create a new, empty, signal set
+    pub fn signal_set(&self) -> SignalSet {
+        SignalSet::new(self)
+    }
+}
@@ -173,9 +173,9 @@ impl UdpHandle {
         addr: &SocketAddr,
         flags: UdpBindFlags,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let mut sockaddr: uv::sockaddr = unsafe { std::mem::zeroed() };
+        let mut sockaddr: uv::sockaddr_storage = unsafe { std::mem::zeroed() };
         crate::fill_sockaddr(&mut sockaddr, addr)?;
-        crate::uvret(unsafe { uv_udp_bind(self.handle, &sockaddr as _, flags.bits()) })
+        crate::uvret(unsafe { uv_udp_bind(self.handle, uv_handle!(&sockaddr), flags.bits()) })
             .map_err(|e| Box::new(e) as _)
     }
 
@@ -186,9 +186,9 @@ impl UdpHandle {
     /// ENOTCONN error.
     pub fn connect(&mut self, addr: Option<&SocketAddr>) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(addr) = addr {
-            let mut sockaddr: uv::sockaddr = unsafe { std::mem::zeroed() };
+            let mut sockaddr: uv::sockaddr_storage = unsafe { std::mem::zeroed() };
             crate::fill_sockaddr(&mut sockaddr, addr)?;
-            crate::uvret(unsafe { uv_udp_connect(self.handle, &sockaddr as _) })
+            crate::uvret(unsafe { uv_udp_connect(self.handle, uv_handle!(&sockaddr)) })
         } else {
             crate::uvret(unsafe { uv_udp_connect(self.handle, std::ptr::null()) })
         }
@@ -321,11 +321,11 @@ impl UdpHandle {
         cb: CB,
     ) -> Result<crate::UdpSendReq, Box<dyn std::error::Error>> {
         let mut req = crate::UdpSendReq::new(bufs, cb)?;
-        let mut sockaddr: uv::sockaddr = unsafe { std::mem::zeroed() };
+        let mut sockaddr: uv::sockaddr_storage = unsafe { std::mem::zeroed() };
         let mut sockaddr_ptr: *const uv::sockaddr = std::ptr::null();
         if let Some(addr) = addr {
             crate::fill_sockaddr(&mut sockaddr, addr)?;
-            sockaddr_ptr = &sockaddr as _;
+            sockaddr_ptr = uv_handle!(&sockaddr);
         }
 
         let result = crate::uvret(unsafe {
@@ -357,11 +357,11 @@ impl UdpHandle {
         bufs: &[impl crate::BufTrait],
     ) -> Result<i32, Box<dyn std::error::Error>> {
         let (bufs_ptr, bufs_len, bufs_capacity) = bufs.into_inner();
-        let mut sockaddr: uv::sockaddr = unsafe { std::mem::zeroed() };
+        let mut sockaddr: uv::sockaddr_storage = unsafe { std::mem::zeroed() };
         let mut sockaddr_ptr: *const uv::sockaddr = std::ptr::null();
         if let Some(addr) = addr {
             crate::fill_sockaddr(&mut sockaddr, addr)?;
-            sockaddr_ptr = &sockaddr as _;
+            sockaddr_ptr = uv_handle!(&sockaddr);
         }
 
         let result = unsafe { uv_udp_try_send(self.handle, bufs_ptr, bufs_len as _, sockaddr_ptr) };
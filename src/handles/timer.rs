@@ -1,5 +1,6 @@
 use crate::{FromInner, HandleTrait, Inner, IntoInner};
 use std::convert::TryFrom;
+use std::time::Duration;
 use uv::{
     uv_timer_again, uv_timer_get_due_in, uv_timer_get_repeat, uv_timer_init, uv_timer_set_repeat,
     uv_timer_start, uv_timer_stop, uv_timer_t,
@@ -84,6 +85,22 @@ impl TimerHandle {
         crate::uvret(unsafe { uv_timer_start(self.handle, uv_cb, timeout, repeat) })
     }
 
+    /// Start the timer, like start(), but with the timeout and repeat given as Durations. A repeat
+    /// of None means the timer fires once.
+    ///
+    /// Timers have millisecond resolution, so any fraction of a millisecond is rounded up: the
+    /// timer never fires before timeout has elapsed. A timeout of zero fires on the next event loop
+    /// iteration, and a repeat of Some(Duration) shorter than a millisecond, including zero,
+    /// repeats every millisecond. Durations too long to represent are treated as "never".
+    pub fn start_after<CB: Into<TimerCB<'static>>>(
+        &mut self,
+        timeout: Duration,
+        repeat: Option<Duration>,
+        cb: CB,
+    ) -> crate::Result<()> {
+        self.start(crate::millis_ceil(timeout), repeat_millis(repeat), cb)
+    }
+
     /// Stop the timer, the callback will not be called anymore.
     pub fn stop(&mut self) -> crate::Result<()> {
         crate::uvret(unsafe { uv_timer_stop(self.handle) })
@@ -119,6 +136,31 @@ impl TimerHandle {
     pub fn due_in(&self) -> u64 {
         unsafe { uv_timer_get_due_in(self.handle) }
     }
+
+    /// Set the repeat interval, like set_repeat(), with the same rounding as start_after(). None
+    /// stops the timer from repeating.
+    pub fn set_repeat_duration(&mut self, repeat: Option<Duration>) {
+        self.set_repeat(repeat_millis(repeat));
+    }
+
+    /// Get the timer repeat interval, or None if the timer doesn't repeat.
+    pub fn repeat_duration(&self) -> Option<Duration> {
+        match self.get_repeat() {
+            0 => None,
+            repeat => Some(Duration::from_millis(repeat)),
+        }
+    }
+
+    /// Get the time until the timer is due, or zero if it has expired. Like due_in(), this is
+    /// relative to the loop's cached now(), not the current time.
+    pub fn due_in_duration(&self) -> Duration {
+        Duration::from_millis(self.due_in())
+    }
+}
+
+/// Convert a repeat interval to milliseconds, where 0 means the timer doesn't repeat.
+fn repeat_millis(repeat: Option<Duration>) -> u64 {
+    repeat.map_or(0, |repeat| crate::millis_ceil(repeat).max(1))
 }
 
 impl FromInner<*mut uv_timer_t> for TimerHandle {
@@ -0,0 +1,444 @@
+use crate::{HandleTrait, TimerHandle};
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+callbacks! {
+    pub TimerWheelCB(token: TimerToken, drift: u64);
+}
+
+/// Identifies a timer scheduled on a TimerWheel. Tokens are never reused: once a timer has fired
+/// or been cancelled, its token no longer refers to anything.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TimerToken {
+    index: usize,
+    generation: u64,
+}
+
+/// Options for TimerWheel.
+#[derive(Clone, Copy, Debug)]
+pub struct TimerWheelOptions {
+    /// The resolution of the wheel, in milliseconds: timers fire on the first tick at or after
+    /// their deadline, so up to this late. Defaults to 10; values below 1 are treated as 1.
+    pub tick: u64,
+
+    /// The number of slots in the wheel. Timers further than slots * tick in the future share
+    /// slots with nearer ones, and are skipped over on each turn of the wheel until they are due.
+    /// Defaults to 1024; values below 1 are treated as 1.
+    pub slots: usize,
+}
+
+impl Default for TimerWheelOptions {
+    fn default() -> TimerWheelOptions {
+        TimerWheelOptions {
+            tick: 10,
+            slots: 1024,
+        }
+    }
+}
+
+/// Statistics about a TimerWheel.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TimerWheelStats {
+    /// The number of timers which are scheduled.
+    pub scheduled: usize,
+
+    /// The number of timers which have fired.
+    pub fired: u64,
+
+    /// How late the latest timer to fire was, in milliseconds.
+    pub last_drift: u64,
+
+    /// The latest any timer has fired, in milliseconds.
+    pub max_drift: u64,
+}
+
+struct Scheduled {
+    /// When the timer is due, in loop time
+    deadline: u64,
+
+    /// The tick the timer fires on
+    deadline_tick: u64,
+
+    /// Orders timers with the same deadline by when they were scheduled
+    seq: u64,
+
+    /// Where the timer is in the slots
+    slot: usize,
+    pos: usize,
+    cb: TimerWheelCB<'static>,
+}
+
+struct Entry {
+    generation: u64,
+    timer: Option<Scheduled>,
+}
+
+struct WheelState {
+    r#loop: crate::Loop,
+    tick: u64,
+    timer: TimerHandle,
+
+    /// Whether the TimerHandle has been started; after that, it is resumed with again()
+    started: bool,
+
+    /// Loop time at tick 0
+    base: u64,
+
+    /// The last tick which has been processed
+    current_tick: u64,
+
+    /// The timers, indexed by TimerToken::index, and the indexes which are free
+    entries: Vec<Entry>,
+    free: Vec<usize>,
+
+    /// The indexes of the timers in each slot
+    slots: Vec<Vec<usize>>,
+    next_seq: u64,
+    stats: TimerWheelStats,
+    closed: bool,
+}
+
+impl WheelState {
+    fn get(&self, token: TimerToken) -> Option<&Scheduled> {
+        self.entries
+            .get(token.index)
+            .filter(|entry| entry.generation == token.generation)
+            .and_then(|entry| entry.timer.as_ref())
+    }
+
+    /// The tick which processes the given loop time: the first at or after it, and after the
+    /// current tick.
+    fn tick_for(&self, deadline: u64) -> u64 {
+        let elapsed = deadline.saturating_sub(self.base);
+        let ticks = elapsed / self.tick + if elapsed % self.tick == 0 { 0 } else { 1 };
+        ticks.max(self.current_tick + 1)
+    }
+
+    /// Put the timer at index into the slot for its deadline.
+    fn link(&mut self, index: usize) {
+        let slots = self.slots.len();
+        let deadline_tick = match &self.entries[index].timer {
+            Some(timer) => self.tick_for(timer.deadline),
+            None => return,
+        };
+        let slot = (deadline_tick % slots as u64) as usize;
+        let pos = self.slots[slot].len();
+        self.slots[slot].push(index);
+        if let Some(timer) = &mut self.entries[index].timer {
+            timer.deadline_tick = deadline_tick;
+            timer.slot = slot;
+            timer.pos = pos;
+        }
+    }
+
+    /// Take the timer at index out of its slot.
+    fn unlink(&mut self, index: usize) {
+        let (slot, pos) = match &self.entries[index].timer {
+            Some(timer) => (timer.slot, timer.pos),
+            None => return,
+        };
+        self.slots[slot].swap_remove(pos);
+        if let Some(&moved) = self.slots[slot].get(pos) {
+            if let Some(timer) = &mut self.entries[moved].timer {
+                timer.pos = pos;
+            }
+        }
+    }
+
+    /// Remove the timer at index, freeing its token.
+    fn remove(&mut self, index: usize) -> Option<Scheduled> {
+        self.unlink(index);
+        let entry = &mut self.entries[index];
+        let timer = entry.timer.take()?;
+        entry.generation += 1;
+        self.free.push(index);
+        self.stats.scheduled -= 1;
+        Some(timer)
+    }
+
+    /// Start or resume the TimerHandle, if it isn't running.
+    fn resume(&mut self, weak: Weak<RefCell<WheelState>>) -> crate::Result<()> {
+        if self.stats.scheduled > 0 {
+            return Ok(());
+        }
+
+        // catch up with the time spent idle, so that the wheel doesn't scan the slots for it
+        let now = self.r#loop.now();
+        self.current_tick = now.saturating_sub(self.base) / self.tick;
+        if self.started {
+            return self.timer.again();
+        }
+        let tick = self.tick;
+        self.timer.start(tick, tick, move |_: TimerHandle| {
+            if let Some(state) = weak.upgrade() {
+                advance(&state);
+            }
+        })?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Stop the TimerHandle if no timers are scheduled, so that the wheel doesn't keep the loop
+    /// alive.
+    fn pause(&mut self) {
+        if self.stats.scheduled == 0 && !self.closed {
+            let _ = self.timer.stop();
+        }
+    }
+}
+
+impl Drop for WheelState {
+    fn drop(&mut self) {
+        if !self.closed {
+            self.timer.close(());
+        }
+    }
+}
+
+/// Process the ticks up to the loop's current time, firing the timers which are due.
+fn advance(state: &Rc<RefCell<WheelState>>) {
+    let (due, to_tick) = {
+        let mut wheel = state.borrow_mut();
+        let to_tick = wheel.r#loop.now().saturating_sub(wheel.base) / wheel.tick;
+        if to_tick <= wheel.current_tick {
+            return;
+        }
+
+        // if the loop was blocked for a whole turn of the wheel, every slot needs scanning
+        let slots = wheel.slots.len() as u64;
+        let first = if to_tick - wheel.current_tick >= slots {
+            to_tick + 1 - slots
+        } else {
+            wheel.current_tick + 1
+        };
+        let mut due: Vec<(u64, u64, TimerToken)> = Vec::new();
+        for tick in first..=to_tick {
+            let slot = (tick % slots) as usize;
+            for &index in &wheel.slots[slot] {
+                let entry = &wheel.entries[index];
+                if let Some(timer) = &entry.timer {
+                    if timer.deadline_tick <= to_tick {
+                        let token = TimerToken {
+                            index,
+                            generation: entry.generation,
+                        };
+                        due.push((timer.deadline, timer.seq, token));
+                    }
+                }
+            }
+        }
+        wheel.current_tick = to_tick;
+        due.sort_unstable_by_key(|&(deadline, seq, _)| (deadline, seq));
+        (due, to_tick)
+    };
+
+    for (_, _, token) in due {
+        // an earlier callback may have cancelled or rescheduled this timer
+        let fired = {
+            let mut wheel = state.borrow_mut();
+            match wheel.get(token) {
+                Some(timer) if timer.deadline_tick <= to_tick => {}
+                _ => continue,
+            }
+            let now = wheel.r#loop.now();
+            wheel.remove(token.index).map(|timer| {
+                let drift = now.saturating_sub(timer.deadline);
+                wheel.stats.fired += 1;
+                wheel.stats.last_drift = drift;
+                wheel.stats.max_drift = wheel.stats.max_drift.max(drift);
+                (timer.cb, drift)
+            })
+        };
+        if let Some((mut cb, drift)) = fired {
+            cb.call(token, drift);
+        }
+    }
+    state.borrow_mut().pause();
+}
+
+/// A hashed timing wheel: many timers, driven by a single TimerHandle. Scheduling, rescheduling
+/// and cancelling a timer are O(1), which makes the wheel suited to timeouts for very many
+/// connections, where creating a TimerHandle for each would be expensive. The price is
+/// resolution: timers fire on the wheel's ticks, up to TimerWheelOptions::tick milliseconds late,
+/// and never early.
+///
+/// Callbacks are passed the token of the timer which fired, and its drift: how many milliseconds
+/// after its deadline it fired. Timers which are due on the same tick fire in order of their
+/// deadlines.
+///
+/// The TimerHandle only runs while timers are scheduled, so an idle wheel doesn't keep the loop
+/// alive. It is closed by close(), or when the last clone of the wheel is dropped. TimerWheels
+/// are cheap to clone: clones share the same timers.
+#[derive(Clone)]
+pub struct TimerWheel {
+    state: Rc<RefCell<WheelState>>,
+}
+
+impl TimerWheel {
+    /// Create a new, empty wheel.
+    pub fn new(r#loop: &crate::Loop, options: TimerWheelOptions) -> crate::Result<TimerWheel> {
+        let timer = r#loop.timer()?;
+        let slots = options.slots.max(1);
+        Ok(TimerWheel {
+            state: Rc::new(RefCell::new(WheelState {
+                r#loop: r#loop.clone(),
+                tick: options.tick.max(1),
+                timer,
+                started: false,
+                base: r#loop.now(),
+                current_tick: 0,
+                entries: Vec::new(),
+                free: Vec::new(),
+                slots: (0..slots).map(|_| Vec::new()).collect(),
+                next_seq: 0,
+                stats: TimerWheelStats::default(),
+                closed: false,
+            })),
+        })
+    }
+
+    /// Schedule cb to be called after the given number of milliseconds, measured from the loop's
+    /// now(). Returns a token which can be used to cancel or reschedule the timer. Fails with
+    /// EBADF once the wheel has been closed.
+    pub fn schedule<CB: Into<TimerWheelCB<'static>>>(
+        &self,
+        after: u64,
+        cb: CB,
+    ) -> crate::Result<TimerToken> {
+        let weak = Rc::downgrade(&self.state);
+        let mut wheel = self.state.borrow_mut();
+        if wheel.closed {
+            return Err(crate::Error::EBADF);
+        }
+        wheel.resume(weak)?;
+
+        let index = match wheel.free.pop() {
+            Some(index) => index,
+            None => {
+                wheel.entries.push(Entry {
+                    generation: 0,
+                    timer: None,
+                });
+                wheel.entries.len() - 1
+            }
+        };
+        let deadline = wheel.r#loop.now().saturating_add(after);
+        let seq = wheel.next_seq;
+        wheel.next_seq += 1;
+        wheel.entries[index].timer = Some(Scheduled {
+            deadline,
+            deadline_tick: 0,
+            seq,
+            slot: 0,
+            pos: 0,
+            cb: cb.into(),
+        });
+        wheel.link(index);
+        wheel.stats.scheduled += 1;
+        Ok(TimerToken {
+            index,
+            generation: wheel.entries[index].generation,
+        })
+    }
+
+    /// Cancel a timer. Returns false if it had already fired or been cancelled.
+    pub fn cancel(&self, token: TimerToken) -> bool {
+        let timer = {
+            let mut wheel = self.state.borrow_mut();
+            if wheel.get(token).is_none() {
+                return false;
+            }
+            let timer = wheel.remove(token.index);
+            wheel.pause();
+            timer
+        };
+
+        // drop the callback after the borrow ends, in case dropping it drops this wheel
+        drop(timer);
+        true
+    }
+
+    /// Move a timer's deadline to the given number of milliseconds after the loop's now(),
+    /// keeping its token and callback; this is how an idle timeout is pushed back when there is
+    /// activity. Fails with ENOENT if the timer has already fired or been cancelled.
+    pub fn reschedule(&self, token: TimerToken, after: u64) -> crate::Result<()> {
+        let mut wheel = self.state.borrow_mut();
+        if wheel.get(token).is_none() {
+            return Err(crate::Error::ENOENT);
+        }
+        let deadline = wheel.r#loop.now().saturating_add(after);
+        let seq = wheel.next_seq;
+        wheel.next_seq += 1;
+        wheel.unlink(token.index);
+        if let Some(timer) = &mut wheel.entries[token.index].timer {
+            timer.deadline = deadline;
+            timer.seq = seq;
+        }
+        wheel.link(token.index);
+        Ok(())
+    }
+
+    /// Returns true if the timer is scheduled: it hasn't fired or been cancelled.
+    pub fn is_scheduled(&self, token: TimerToken) -> bool {
+        self.state.borrow().get(token).is_some()
+    }
+
+    /// Returns how long until the timer is due, in milliseconds relative to the loop's now(), or
+    /// None if it isn't scheduled.
+    pub fn due_in(&self, token: TimerToken) -> Option<u64> {
+        let wheel = self.state.borrow();
+        let now = wheel.r#loop.now();
+        wheel
+            .get(token)
+            .map(|timer| timer.deadline.saturating_sub(now))
+    }
+
+    /// Returns the number of scheduled timers.
+    pub fn len(&self) -> usize {
+        self.state.borrow().stats.scheduled
+    }
+
+    /// Returns true if no timers are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns statistics about the wheel, including how late its timers have fired.
+    pub fn stats(&self) -> TimerWheelStats {
+        self.state.borrow().stats
+    }
+
+    /// Cancel every timer, and close the wheel's TimerHandle.
+    pub fn close(&self) {
+        let entries = {
+            let mut wheel = self.state.borrow_mut();
+            if wheel.closed {
+                return;
+            }
+            wheel.closed = true;
+            wheel.timer.close(());
+            wheel.free.clear();
+            wheel.stats.scheduled = 0;
+            for slot in wheel.slots.iter_mut() {
+                slot.clear();
+            }
+            std::mem::take(&mut wheel.entries)
+        };
+
+        // drop the callbacks after the borrow ends, in case dropping one drops this wheel
+        drop(entries);
+    }
+}
+
+impl crate::watchdog::WatchdogSource for TimerToken {
+    fn watchdog_source(&self) -> crate::CallbackSource {
+        crate::CallbackSource::Handle(crate::HandleType::TIMER)
+    }
+}
+
+impl crate::Loop {
+    /// Create a TimerWheel with the default options. See TimerWheel.
+    pub fn timer_wheel(&self) -> crate::Result<TimerWheel> {
+        TimerWheel::new(self, TimerWheelOptions::default())
+    }
+}
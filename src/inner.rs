@@ -102,9 +102,10 @@ where
     }
 }
 
-/// Fill a uv::sockaddr from a SocketAddr
+/// Fill a uv::sockaddr_storage from a SocketAddr. A plain uv::sockaddr is too small to hold an
+/// IPv6 address.
 pub(crate) fn fill_sockaddr(
-    sockaddr: *mut uv::sockaddr,
+    sockaddr: *mut uv::sockaddr_storage,
     addr: &SocketAddr,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let s = addr.ip().to_string();
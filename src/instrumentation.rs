@@ -0,0 +1,423 @@
+//! Opt-in instrumentation for an event loop. See Loop::instrument().
+use crate::{CheckHandle, HandleTrait, HandleType, Inner, IntoInner, PrepareHandle};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Upper bounds of the latency histogram buckets, in nanoseconds. There is an implicit +Inf
+/// bucket after the last one.
+const LATENCY_BUCKETS: [u64; 12] = [
+    10_000,
+    50_000,
+    100_000,
+    500_000,
+    1_000_000,
+    5_000_000,
+    10_000_000,
+    50_000_000,
+    100_000_000,
+    500_000_000,
+    1_000_000_000,
+    5_000_000_000,
+];
+
+/// A latency histogram with fixed, exponentially sized buckets. All values are in nanoseconds.
+#[derive(Clone, Debug, Default)]
+pub struct Histogram {
+    counts: [u64; LATENCY_BUCKETS.len() + 1],
+    count: u64,
+    sum: u64,
+    max: u64,
+}
+
+impl Histogram {
+    /// Record a value, in nanoseconds.
+    pub fn record(&mut self, value: u64) {
+        let idx = LATENCY_BUCKETS
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[idx] += 1;
+        self.count += 1;
+        self.sum = self.sum.saturating_add(value);
+        self.max = self.max.max(value);
+    }
+
+    /// Number of recorded values.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Sum of all recorded values, in nanoseconds.
+    pub fn sum(&self) -> u64 {
+        self.sum
+    }
+
+    /// Largest recorded value, in nanoseconds.
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    /// Mean of the recorded values, in nanoseconds, or 0 if nothing has been recorded.
+    pub fn mean(&self) -> u64 {
+        if self.count == 0 {
+            0
+        } else {
+            self.sum / self.count
+        }
+    }
+
+    /// Returns the cumulative count for each bucket, as (upper bound in nanoseconds, count). The
+    /// last bucket has an upper bound of None, meaning +Inf, and its count is equal to count().
+    pub fn buckets(&self) -> Vec<(Option<u64>, u64)> {
+        let mut cumulative = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(idx, count)| {
+                cumulative += count;
+                (LATENCY_BUCKETS.get(idx).copied(), cumulative)
+            })
+            .collect()
+    }
+
+    /// Writes the histogram in Prometheus text format. Values are converted to seconds.
+    fn write_prometheus(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        for (bound, count) in self.buckets() {
+            match bound {
+                Some(bound) => {
+                    let _ = writeln!(
+                        out,
+                        "{}_bucket{{le=\"{}\"}} {}",
+                        name,
+                        bound as f64 / 1e9,
+                        count
+                    );
+                }
+                None => {
+                    let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, count);
+                }
+            }
+        }
+        let _ = writeln!(out, "{}_sum {}", name, self.sum as f64 / 1e9);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+/// The number of handles of a given type in the loop.
+#[derive(Clone, Copy, Debug)]
+pub struct HandleCount {
+    /// The type of the handles.
+    pub handle_type: HandleType,
+
+    /// Number of handles of this type, including inactive and closing handles.
+    pub total: u64,
+
+    /// Number of active handles of this type. See HandleTrait::is_active().
+    pub active: u64,
+}
+
+/// A point-in-time snapshot of the statistics gathered by a LoopInstrumentation.
+#[derive(Clone, Debug)]
+pub struct LoopSnapshot {
+    /// Number of loop iterations observed since instrumentation started (or was reset).
+    pub iterations: u64,
+
+    /// Wall-clock duration of each full loop iteration.
+    pub iteration_time: Histogram,
+
+    /// Time spent blocked in the kernel's event provider (e.g. epoll_wait) each iteration.
+    pub poll_time: Histogram,
+
+    /// Time spent running callbacks each iteration; that is, the iteration time minus the poll
+    /// time. This is the number to watch for loop stalls.
+    pub callback_time: Histogram,
+
+    /// Number of loop iterations, as reported by libuv.
+    pub loop_count: u64,
+
+    /// Number of events that have been processed by the event handler, as reported by libuv.
+    pub events: u64,
+
+    /// Number of events that were waiting to be processed when the event provider was called, as
+    /// reported by libuv.
+    pub events_waiting: u64,
+
+    /// Number of active handles, as tracked by libuv's reference counting.
+    pub active_handles: u64,
+
+    /// Number of active requests. libuv does not provide a way to enumerate requests, so they
+    /// cannot be broken down by ReqType.
+    pub active_reqs: u64,
+
+    /// Handle census, by type. The handles used by the instrumentation itself are not included.
+    pub handles: Vec<HandleCount>,
+}
+
+impl LoopSnapshot {
+    /// Formats the snapshot in the Prometheus text exposition format. All metric names are
+    /// prefixed with the given prefix (for example, "uv").
+    pub fn to_prometheus(&self, prefix: &str) -> String {
+        let mut out = String::new();
+
+        let counters = [
+            (
+                "loop_iterations_total",
+                "Loop iterations observed by the instrumentation.",
+                self.iterations,
+            ),
+            (
+                "loop_count_total",
+                "Loop iterations reported by libuv.",
+                self.loop_count,
+            ),
+            (
+                "loop_events_total",
+                "Events processed by the event handler.",
+                self.events,
+            ),
+        ];
+        for (name, help, value) in counters.iter() {
+            let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} counter", prefix, name);
+            let _ = writeln!(out, "{}_{} {}", prefix, name, value);
+        }
+
+        let gauges = [
+            (
+                "loop_events_waiting",
+                "Events waiting when the event provider was called.",
+                self.events_waiting,
+            ),
+            (
+                "loop_active_handles",
+                "Active handles in the loop.",
+                self.active_handles,
+            ),
+            (
+                "loop_active_reqs",
+                "Active requests in the loop.",
+                self.active_reqs,
+            ),
+        ];
+        for (name, help, value) in gauges.iter() {
+            let _ = writeln!(out, "# HELP {}_{} {}", prefix, name, help);
+            let _ = writeln!(out, "# TYPE {}_{} gauge", prefix, name);
+            let _ = writeln!(out, "{}_{} {}", prefix, name, value);
+        }
+
+        let name = format!("{}_loop_handles", prefix);
+        let _ = writeln!(out, "# HELP {} Handles in the loop, by type.", name);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for count in self.handles.iter() {
+            let t = count.handle_type.name();
+            let _ = writeln!(
+                out,
+                "{}{{type=\"{}\",state=\"all\"}} {}",
+                name, t, count.total
+            );
+            let _ = writeln!(
+                out,
+                "{}{{type=\"{}\",state=\"active\"}} {}",
+                name, t, count.active
+            );
+        }
+
+        self.iteration_time.write_prometheus(
+            &mut out,
+            &format!("{}_loop_iteration_seconds", prefix),
+            "Duration of each loop iteration.",
+        );
+        self.poll_time.write_prometheus(
+            &mut out,
+            &format!("{}_loop_poll_seconds", prefix),
+            "Time spent blocked in the event provider each iteration.",
+        );
+        self.callback_time.write_prometheus(
+            &mut out,
+            &format!("{}_loop_callback_seconds", prefix),
+            "Time spent running callbacks each iteration.",
+        );
+
+        out
+    }
+}
+
+/// Statistics gathered by the prepare and check callbacks.
+#[derive(Default)]
+struct InstrumentationState {
+    /// hrtime() and idle_time() when the prepare callback last ran
+    prepare_at: u64,
+    idle_at_prepare: u64,
+
+    /// hrtime() when the check callback last ran, or 0 if it hasn't run yet
+    last_check: u64,
+
+    iterations: u64,
+    iteration_time: Histogram,
+    poll_time: Histogram,
+    callback_time: Histogram,
+}
+
+/// Instruments an event loop with a PrepareHandle/CheckHandle pair in order to measure how long
+/// each loop iteration takes, and how much of that time was spent blocked for i/o versus running
+/// callbacks. See Loop::instrument().
+///
+/// The prepare callback runs right before the loop blocks for i/o and the check callback runs
+/// right after, so the time in between includes both the time spent blocked in the kernel and the
+/// time spent in i/o callbacks. The two are separated using the loop's idle time metric, which is
+/// why instrumenting a loop enables accumulate_idle_time().
+///
+/// Both handles are unref'd, so they will not keep the loop alive on their own.
+///
+/// Note: iterations are measured from one check callback to the next. If run() returns and is
+/// later called again, the first iteration will include the time spent outside of the loop. Call
+/// reset() before calling run() again to avoid this.
+pub struct LoopInstrumentation {
+    r#loop: crate::Loop,
+    prepare: PrepareHandle,
+    check: CheckHandle,
+    state: Rc<RefCell<InstrumentationState>>,
+}
+
+impl LoopInstrumentation {
+    /// Start instrumenting the given loop.
+    pub fn new(r#loop: &mut crate::Loop) -> crate::Result<LoopInstrumentation> {
+        r#loop.accumulate_idle_time()?;
+
+        let state: Rc<RefCell<InstrumentationState>> = Default::default();
+
+        let mut prepare = PrepareHandle::new(r#loop)?;
+        let mut check = match CheckHandle::new(r#loop) {
+            Ok(check) => check,
+            Err(e) => {
+                prepare.close(());
+                return Err(e);
+            }
+        };
+
+        let prepare_state = state.clone();
+        let prepare_loop = r#loop.clone();
+        let check_state = state.clone();
+        let check_loop = r#loop.clone();
+        let result = prepare
+            .start(move |_: PrepareHandle| {
+                let mut state = prepare_state.borrow_mut();
+                state.prepare_at = crate::hrtime();
+                state.idle_at_prepare = prepare_loop.idle_time();
+            })
+            .and_then(|_| {
+                check.start(move |_: CheckHandle| {
+                    let now = crate::hrtime();
+                    let mut state = check_state.borrow_mut();
+
+                    // idle_time() is the accumulated time spent in the event provider, so the
+                    // difference since the prepare callback is the time spent blocked this
+                    // iteration.
+                    let poll = now.saturating_sub(state.prepare_at);
+                    let blocked = check_loop
+                        .idle_time()
+                        .saturating_sub(state.idle_at_prepare)
+                        .min(poll);
+
+                    if state.last_check != 0 {
+                        let iteration = now.saturating_sub(state.last_check);
+                        state.iterations += 1;
+                        state.iteration_time.record(iteration);
+                        state.poll_time.record(blocked);
+                        state
+                            .callback_time
+                            .record(iteration.saturating_sub(blocked));
+                    }
+                    state.last_check = now;
+                })
+            });
+        if let Err(e) = result {
+            prepare.close(());
+            check.close(());
+            return Err(e);
+        }
+
+        prepare.unref();
+        check.unref();
+
+        Ok(LoopInstrumentation {
+            r#loop: r#loop.clone(),
+            prepare,
+            check,
+            state,
+        })
+    }
+
+    /// Takes a snapshot of the statistics gathered so far, along with a census of the handles in
+    /// the loop (using Loop::walk()) and libuv's own metrics.
+    pub fn snapshot(&self) -> crate::Result<LoopSnapshot> {
+        let metrics = self.r#loop.metrics_info()?;
+
+        let census: Rc<RefCell<Vec<HandleCount>>> = Default::default();
+        let walk_census = census.clone();
+        let prepare: *mut uv::uv_handle_t = self.prepare.inner();
+        let check: *mut uv::uv_handle_t = self.check.inner();
+        self.r#loop.walk(move |handle: crate::Handle| {
+            let ptr = handle.inner();
+            if ptr == prepare || ptr == check {
+                return;
+            }
+
+            let handle_type = handle.get_type();
+            let active = handle.is_active() as u64;
+            let mut census = walk_census.borrow_mut();
+            match census.iter_mut().find(|c| c.handle_type == handle_type) {
+                Some(count) => {
+                    count.total += 1;
+                    count.active += active;
+                }
+                None => census.push(HandleCount {
+                    handle_type,
+                    total: 1,
+                    active,
+                }),
+            }
+        });
+        let handles = census.replace(Vec::new());
+
+        let uv_loop: *mut uv::uv_loop_t = (&self.r#loop).into_inner();
+        let (active_handles, active_reqs) =
+            unsafe { ((*uv_loop).active_handles, (*uv_loop).active_reqs.count) };
+
+        let state = self.state.borrow();
+        Ok(LoopSnapshot {
+            iterations: state.iterations,
+            iteration_time: state.iteration_time.clone(),
+            poll_time: state.poll_time.clone(),
+            callback_time: state.callback_time.clone(),
+            loop_count: metrics.loop_count,
+            events: metrics.events,
+            events_waiting: metrics.events_waiting,
+            // the instrumentation's own handles are unref'd, so they're not counted here
+            active_handles: active_handles as _,
+            active_reqs: active_reqs as _,
+            handles,
+        })
+    }
+
+    /// Discard all of the statistics gathered so far.
+    pub fn reset(&mut self) {
+        *self.state.borrow_mut() = Default::default();
+    }
+
+    /// Stop instrumenting the loop, closing the prepare and check handles.
+    pub fn close(&mut self) {
+        self.prepare.close(());
+        self.check.close(());
+    }
+}
+
+impl crate::Loop {
+    /// Start instrumenting the loop. See LoopInstrumentation.
+    pub fn instrument(&mut self) -> crate::Result<LoopInstrumentation> {
+        LoopInstrumentation::new(self)
+    }
+}
@@ -81,7 +81,10 @@ macro_rules! __callback_callfn {
     ($Name:ident($($($a:ident: $T:ty),+)?) -> $TReturn:ty) => {
         pub(crate) fn call(&mut self$(,$($a: $T),+)?) -> $TReturn {
             match self {
-                $Name::CB(ref mut f) => f($($($a),+)?),
+                $Name::CB(ref mut f) => {
+                    let _guard = __callback_guard!($Name$(,$($a),+)?);
+                    f($($($a),+)?)
+                }
                 $Name::Nil => Default::default(),
             }
         }
@@ -89,13 +92,36 @@ macro_rules! __callback_callfn {
     ($Name:ident($($($a:ident: $T:ty),+)?)) => {
         pub(crate) fn call(&mut self$(,$($a: $T),+)?) {
             match self {
-                $Name::CB(ref mut f) => f($($($a),+)?),
+                $Name::CB(ref mut f) => {
+                    let _guard = __callback_guard!($Name$(,$($a),+)?);
+                    f($($($a),+)?)
+                }
                 $Name::Nil => (),
             }
         }
     }
 }
 
+// The first argument to most callbacks is the handle or request that generated it, which the
+// watchdog uses to report where a slow callback came from. Arguments which don't implement
+// WatchdogSource are reported without a source. WorkCB runs on the threadpool, where blocking is
+// expected and there is no loop running, so it isn't timed at all.
+macro_rules! __callback_guard {
+    (WorkCB$(, $a:ident)*) => {
+        crate::watchdog::CallbackGuard::untimed("WorkCB")
+    };
+    ($Name:ident) => {
+        crate::watchdog::CallbackGuard::new(stringify!($Name), || None)
+    };
+    ($Name:ident, $first:ident$(, $rest:ident)*) => {
+        crate::watchdog::CallbackGuard::new(stringify!($Name), || {
+            #[allow(unused_imports)]
+            use crate::watchdog::{KnownCallbackSource, NoCallbackSource};
+            (&$first).callback_source()
+        })
+    };
+}
+
 macro_rules! use_c_callback {
     ($ccb:expr, $cb:expr) => {
         if ($cb).is_nil() {
@@ -132,6 +158,12 @@ pub use version::*;
 pub mod r#loop;
 pub use r#loop::*;
 
+pub mod instrumentation;
+pub use instrumentation::*;
+
+pub mod watchdog;
+pub use watchdog::*;
+
 pub mod buf;
 pub use buf::*;
 
@@ -153,6 +185,8 @@ pub use shared_libs::*;
 pub mod misc;
 pub use misc::*;
 
+pub mod threadpool;
+
 /// Imports some things that most every program will need.
 pub mod prelude {
     pub use super::{
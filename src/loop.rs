@@ -1,4 +1,5 @@
 use crate::{FromInner, HandleTrait, IntoInner};
+use std::rc::Rc;
 use uv::{
     uv_backend_fd, uv_backend_timeout, uv_default_loop, uv_handle_t, uv_loop_alive, uv_loop_close,
     uv_loop_configure, uv_loop_delete, uv_loop_fork, uv_loop_get_data, uv_loop_init, uv_loop_new,
@@ -59,10 +60,51 @@ impl FromInner<uv_metrics_t> for Metrics {
     }
 }
 
+/// Options used to configure a new loop. See Loop::with_options().
+///
+/// There is no option for UV_LOOP_USE_IO_URING_SQPOLL: it was added in libuv 1.49, and this crate
+/// is built against libuv 1.48 (libuv-sys2 ~1.48), which doesn't have it. libuv 1.48 needs no
+/// option, because on Linux it already submits file operations through an io_uring with a
+/// kernel polling thread (SQPOLL) whenever the kernel is new enough (5.10.186 or later); setting
+/// the environment variable UV_USE_IO_URING=0 turns that off. libuv 1.49 made SQPOLL opt-in,
+/// which is what the option controls, so it can be added here once the dependency is updated.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LoopOptions {
+    /// Block a signal when polling for new events. This is currently only implemented for SIGPROF
+    /// signals, to suppress unnecessary wakeups when using a sampling profiler. Requesting other
+    /// signals will fail with EINVAL.
+    pub block_signal: Option<crate::Signal>,
+
+    /// Accumulate the amount of idle time the event loop spends in the event provider. This
+    /// option is necessary to use idle_time().
+    pub metrics_idle_time: bool,
+}
+
+impl LoopOptions {
+    /// Constructs a new LoopOptions object with no options set.
+    pub fn new() -> LoopOptions {
+        Default::default()
+    }
+
+    /// Block the given signal when polling for new events.
+    pub fn block_signal(mut self, signal: crate::Signal) -> LoopOptions {
+        self.block_signal = Some(signal);
+        self
+    }
+
+    /// Accumulate the amount of idle time the event loop spends in the event provider.
+    pub fn metrics_idle_time(mut self) -> LoopOptions {
+        self.metrics_idle_time = true;
+        self
+    }
+}
+
 /// Data that we need to track with the loop.
 #[derive(Default)]
 pub(crate) struct LoopData {
     walk_cb: Option<Box<dyn FnMut(crate::Handle)>>,
+    resolver_cache: Option<crate::ResolverCache>,
+    watchdog: Rc<crate::watchdog::LoopWatchdog>,
 }
 
 /// Callback for uv_walk
@@ -109,6 +151,14 @@ impl Loop {
         Ok(r#loop)
     }
 
+    /// Creates a new Loop, configured with the given options. If any of the options cannot be
+    /// applied, the loop is destroyed and the error is returned.
+    pub fn with_options(options: &LoopOptions) -> crate::Result<Loop> {
+        let mut r#loop = Loop::new()?;
+        r#loop.configure(options)?;
+        Ok(r#loop)
+    }
+
     /// Returns the initialized default loop.
     ///
     /// This function is just a convenient way for having a global loop throughout an application,
@@ -171,6 +221,20 @@ impl Loop {
         crate::uvret(unsafe { uv_loop_configure(self.handle, uv_loop_option_UV_METRICS_IDLE_TIME) })
     }
 
+    /// Apply all of the given options to the loop. Options are applied in the order they are
+    /// declared in LoopOptions, stopping at the first error.
+    ///
+    /// Options should be applied immediately after the loop is created, before it is run.
+    pub fn configure(&mut self, options: &LoopOptions) -> crate::Result<()> {
+        if let Some(signal) = options.block_signal {
+            self.block_signal(signal.signum())?;
+        }
+        if options.metrics_idle_time {
+            self.accumulate_idle_time()?;
+        }
+        Ok(())
+    }
+
     /// Releases all internal loop resources. Call this function only when the loop has finished
     /// executing and all open handles and requests have been closed, or it will return
     /// Error::EBUSY.  After this function returns, the user can free the memory allocated for the
@@ -182,6 +246,7 @@ impl Loop {
     /// This function runs the event loop. It will act differently depending on the specified mode.
     /// run() is not reentrant. It must not be called from a callback.
     pub fn run(&mut self, mode: RunMode) -> crate::Result<i32> {
+        let _watchdog = crate::watchdog::RunGuard::enter(self.watchdog());
         let ret = unsafe { uv_run(self.handle, mode.into_inner()) };
         if ret < 0 {
             Err(crate::Error::from_inner(ret as uv::uv_errno_t))
@@ -238,6 +303,14 @@ impl Loop {
         unsafe { uv_backend_timeout(self.handle) as _ }
     }
 
+    /// Get the poll timeout as a Duration, or None for no timeout.
+    pub fn backend_timeout_duration(&self) -> Option<std::time::Duration> {
+        match self.backend_timeout() {
+            timeout if timeout < 0 => None,
+            timeout => Some(std::time::Duration::from_millis(timeout as u64)),
+        }
+    }
+
     /// Return the current timestamp in milliseconds. The timestamp is cached at the start of the
     /// event loop tick, see update_time() for details and rationale.
     ///
@@ -247,6 +320,13 @@ impl Loop {
         unsafe { uv_now(self.handle) }
     }
 
+    /// Return the current timestamp as a LoopInstant, which can be compared with other
+    /// LoopInstants and offset by Durations without mixing up units. It is the same cached,
+    /// millisecond timestamp as now().
+    pub fn now_instant(&self) -> crate::LoopInstant {
+        crate::LoopInstant::from_millis(self.now())
+    }
+
     /// Update the event loop’s concept of “now”. Libuv caches the current time at the start of the
     /// event loop tick in order to reduce the number of time-related system calls.
     ///
@@ -268,6 +348,35 @@ impl Loop {
         unsafe { uv_walk(self.handle, Some(uv_walk_cb), std::ptr::null_mut()) };
     }
 
+    /// Install a ResolverCache on the loop, which is used for lookups made with ResolverBuilder,
+    /// or remove it with None.
+    pub fn set_resolver_cache(&self, cache: Option<crate::ResolverCache>) {
+        let dataptr = self.get_data();
+        if !dataptr.is_null() {
+            unsafe { (*dataptr).resolver_cache = cache };
+        }
+    }
+
+    /// Returns the loop's ResolverCache, if it has one.
+    pub fn resolver_cache(&self) -> Option<crate::ResolverCache> {
+        let dataptr = self.get_data();
+        if dataptr.is_null() {
+            None
+        } else {
+            unsafe { (*dataptr).resolver_cache.clone() }
+        }
+    }
+
+    /// Returns the loop's watchdog, which is shared with the thread running the loop.
+    pub(crate) fn watchdog(&self) -> Option<Rc<crate::watchdog::LoopWatchdog>> {
+        let dataptr = self.get_data();
+        if dataptr.is_null() {
+            None
+        } else {
+            unsafe { Some((*dataptr).watchdog.clone()) }
+        }
+    }
+
     /// Reinitialize any kernel state necessary in the child process after a fork(2) system call.
     ///
     /// Previously started watchers will continue to be started in the child process.
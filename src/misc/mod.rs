@@ -11,6 +11,9 @@ use uv::{
 pub mod os;
 pub use os::*;
 
+pub mod time;
+pub use time::*;
+
 /// Data type for storing times.
 pub struct TimeVal {
     pub sec: i64,
@@ -333,7 +336,7 @@ pub fn get_available_memory() -> u64 {
 /// subject to clock drift. The primary use is for measuring performance between intervals.
 ///
 /// Note: Not every platform can support nanosecond resolution; however, this value will always be
-/// in nanoseconds.
+/// in nanoseconds. hrtime_instant() returns the same timestamp as an HrInstant.
 pub fn hrtime() -> u64 {
     unsafe { uv_hrtime() }
 }
@@ -357,7 +360,18 @@ pub fn gettimeofday() -> crate::Result<TimeVal> {
     crate::uvret(unsafe { uv_gettimeofday(&mut tv as _) }).map(|_| tv.into_inner())
 }
 
-/// Causes the calling thread to sleep for msec milliseconds.
+/// Returns a random number, which is good enough for spreading out timers, but is not
+/// unpredictable enough for anything which must resist an attacker. The standard library's
+/// RandomState is randomly keyed, and each one is keyed differently.
+pub(crate) fn random_u64() -> u64 {
+    use std::hash::{BuildHasher, Hasher};
+    std::collections::hash_map::RandomState::new()
+        .build_hasher()
+        .finish()
+}
+
+/// Causes the calling thread to sleep for msec milliseconds. See sleep_for() to sleep for a
+/// Duration.
 pub fn sleep(msec: u32) {
     unsafe { uv_sleep(msec) };
 }
@@ -0,0 +1,222 @@
+use std::convert::TryFrom;
+use std::ops::{Add, AddAssign, Sub, SubAssign};
+use std::time::Duration;
+
+/// Convert a Duration to whole milliseconds, for libuv's millisecond timers. Any fraction of a
+/// millisecond is rounded up, so that a timer never fires before the Duration has elapsed; a
+/// Duration of 1ns waits 1ms, and a Duration of zero stays zero. Durations too long to fit in a
+/// u64 are clamped to u64::MAX milliseconds, which libuv treats as "never".
+pub(crate) fn millis_ceil(duration: Duration) -> u64 {
+    checked_millis_ceil(duration).unwrap_or(u64::MAX)
+}
+
+/// Convert a Duration to whole milliseconds, rounding up, or None if it doesn't fit in a u64.
+fn checked_millis_ceil(duration: Duration) -> Option<u64> {
+    let mut millis = duration.as_millis();
+    if duration.subsec_nanos() % 1_000_000 != 0 {
+        millis += 1;
+    }
+    u64::try_from(millis).ok()
+}
+
+/// A point on the loop's clock, as returned by Loop::now_instant(). Like Loop::now(), it has
+/// millisecond resolution, it is cached at the start of each loop iteration, and it counts from an
+/// arbitrary point in time, so it can only be compared with other LoopInstants from the same loop.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct LoopInstant {
+    millis: u64,
+}
+
+impl LoopInstant {
+    /// Create a LoopInstant from a timestamp returned by Loop::now().
+    pub fn from_millis(millis: u64) -> LoopInstant {
+        LoopInstant { millis }
+    }
+
+    /// Returns the timestamp in milliseconds, as Loop::now() would.
+    pub fn as_millis(&self) -> u64 {
+        self.millis
+    }
+
+    /// Returns the time elapsed from earlier to self, or zero if earlier is later than self.
+    pub fn saturating_duration_since(&self, earlier: LoopInstant) -> Duration {
+        Duration::from_millis(self.millis.saturating_sub(earlier.millis))
+    }
+
+    /// Returns the time elapsed from earlier to self, or None if earlier is later than self.
+    pub fn checked_duration_since(&self, earlier: LoopInstant) -> Option<Duration> {
+        self.millis
+            .checked_sub(earlier.millis)
+            .map(Duration::from_millis)
+    }
+
+    /// Returns self plus duration, or None on overflow. Any fraction of a millisecond in duration
+    /// is rounded up, the same as for timers, so the result is never earlier than the exact sum.
+    pub fn checked_add(&self, duration: Duration) -> Option<LoopInstant> {
+        let millis = checked_millis_ceil(duration)?;
+        self.millis
+            .checked_add(millis)
+            .map(LoopInstant::from_millis)
+    }
+
+    /// Returns self minus duration, or None on underflow. Any fraction of a millisecond in
+    /// duration is rounded up.
+    pub fn checked_sub(&self, duration: Duration) -> Option<LoopInstant> {
+        let millis = checked_millis_ceil(duration)?;
+        self.millis
+            .checked_sub(millis)
+            .map(LoopInstant::from_millis)
+    }
+}
+
+impl Add<Duration> for LoopInstant {
+    type Output = LoopInstant;
+
+    /// Panics on overflow; see checked_add().
+    fn add(self, duration: Duration) -> LoopInstant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to LoopInstant")
+    }
+}
+
+impl AddAssign<Duration> for LoopInstant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for LoopInstant {
+    type Output = LoopInstant;
+
+    /// Panics on underflow; see checked_sub().
+    fn sub(self, duration: Duration) -> LoopInstant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from LoopInstant")
+    }
+}
+
+impl SubAssign<Duration> for LoopInstant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<LoopInstant> for LoopInstant {
+    type Output = Duration;
+
+    /// Returns zero if other is later than self, like std::time::Instant.
+    fn sub(self, other: LoopInstant) -> Duration {
+        self.saturating_duration_since(other)
+    }
+}
+
+/// A point on the high-resolution clock, as returned by hrtime_instant(). It has nanosecond
+/// resolution and counts from an arbitrary point in time; it is not related to the time of day.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct HrInstant {
+    nanos: u64,
+}
+
+impl HrInstant {
+    /// Returns the current time on the high-resolution clock.
+    pub fn now() -> HrInstant {
+        HrInstant::from_nanos(crate::hrtime())
+    }
+
+    /// Create an HrInstant from a timestamp returned by hrtime().
+    pub fn from_nanos(nanos: u64) -> HrInstant {
+        HrInstant { nanos }
+    }
+
+    /// Returns the timestamp in nanoseconds, as hrtime() would.
+    pub fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// Returns the time elapsed since self.
+    pub fn elapsed(&self) -> Duration {
+        HrInstant::now().saturating_duration_since(*self)
+    }
+
+    /// Returns the time elapsed from earlier to self, or zero if earlier is later than self.
+    pub fn saturating_duration_since(&self, earlier: HrInstant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /// Returns the time elapsed from earlier to self, or None if earlier is later than self.
+    pub fn checked_duration_since(&self, earlier: HrInstant) -> Option<Duration> {
+        self.nanos
+            .checked_sub(earlier.nanos)
+            .map(Duration::from_nanos)
+    }
+
+    /// Returns self plus duration, or None on overflow.
+    pub fn checked_add(&self, duration: Duration) -> Option<HrInstant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_add(nanos).map(HrInstant::from_nanos)
+    }
+
+    /// Returns self minus duration, or None on underflow.
+    pub fn checked_sub(&self, duration: Duration) -> Option<HrInstant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        self.nanos.checked_sub(nanos).map(HrInstant::from_nanos)
+    }
+}
+
+impl Add<Duration> for HrInstant {
+    type Output = HrInstant;
+
+    /// Panics on overflow; see checked_add().
+    fn add(self, duration: Duration) -> HrInstant {
+        self.checked_add(duration)
+            .expect("overflow when adding duration to HrInstant")
+    }
+}
+
+impl AddAssign<Duration> for HrInstant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for HrInstant {
+    type Output = HrInstant;
+
+    /// Panics on underflow; see checked_sub().
+    fn sub(self, duration: Duration) -> HrInstant {
+        self.checked_sub(duration)
+            .expect("overflow when subtracting duration from HrInstant")
+    }
+}
+
+impl SubAssign<Duration> for HrInstant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<HrInstant> for HrInstant {
+    type Output = Duration;
+
+    /// Returns zero if other is later than self, like std::time::Instant.
+    fn sub(self, other: HrInstant) -> Duration {
+        self.saturating_duration_since(other)
+    }
+}
+
+/// Returns the current time on the high-resolution clock. This is hrtime(), as an HrInstant.
+pub fn hrtime_instant() -> HrInstant {
+    HrInstant::now()
+}
+
+/// Causes the calling thread to sleep for at least duration. Any fraction of a millisecond is
+/// rounded up, as sleep() has millisecond resolution. Durations longer than sleep() accepts, about
+/// 49 days, are slept in several parts.
+pub fn sleep_for(duration: Duration) {
+    let mut millis = millis_ceil(duration);
+    while millis > 0 {
+        let part = u32::try_from(millis).unwrap_or(u32::MAX);
+        crate::sleep(part);
+        millis -= part as u64;
+    }
+}
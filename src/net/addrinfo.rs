@@ -2,6 +2,7 @@ use crate::{FromInner, IntoInner};
 use std::net::SocketAddr;
 use uv::addrinfo;
 
+#[derive(Clone, Debug)]
 pub struct AddrInfo {
     /// Bitwise OR of uv::AI_* flags
     pub flags: u32,
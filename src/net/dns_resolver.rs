@@ -0,0 +1,1092 @@
+use crate::{
+    AddrInfo, AddressFamily, AiFlags, Buf, ConnectReq, HandleTrait, Inner, IntoInner, Protocol,
+    ReadonlyBuf, ResolveCB, Resolver, SocketType, StreamHandle, StreamTrait, TcpHandle,
+    TimerHandle, UdpHandle, UdpRecvFlags, UdpSendReq, WriteReq,
+};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::rc::Rc;
+
+const DNS_PORT: u16 = 53;
+
+const TYPE_A: u16 = 1;
+const TYPE_CNAME: u16 = 5;
+const TYPE_AAAA: u16 = 28;
+const CLASS_IN: u16 = 1;
+
+const RCODE_NOERROR: u8 = 0;
+const RCODE_SERVFAIL: u8 = 2;
+const RCODE_NXDOMAIN: u8 = 3;
+
+/// Configuration for DnsResolver, with the same meaning as in resolv.conf(5).
+#[derive(Clone, Debug)]
+pub struct DnsConfig {
+    /// The name servers to query, in order. Defaults to 127.0.0.1, port 53.
+    pub nameservers: Vec<SocketAddr>,
+
+    /// Domains to append to names which don't end with a dot. Defaults to none.
+    pub search: Vec<String>,
+
+    /// Names with at least this many dots are tried as they are before the search domains are
+    /// appended; other names are tried after. Defaults to 1.
+    pub ndots: usize,
+
+    /// How long to wait for a reply from a name server, in milliseconds, before trying the next
+    /// one. Defaults to 5 seconds.
+    pub timeout: u64,
+
+    /// How many times to try each name server. Defaults to 2.
+    pub attempts: u32,
+}
+
+impl Default for DnsConfig {
+    fn default() -> DnsConfig {
+        DnsConfig {
+            nameservers: vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), DNS_PORT)],
+            search: Vec::new(),
+            ndots: 1,
+            timeout: 5_000,
+            attempts: 2,
+        }
+    }
+}
+
+impl DnsConfig {
+    /// Parse the contents of a resolv.conf file. The nameserver, domain, search and options
+    /// (ndots, timeout and attempts) keywords are understood; like the C library, anything else
+    /// is ignored. If no name servers are listed, the default is kept.
+    pub fn parse(contents: &str) -> DnsConfig {
+        let mut config = DnsConfig::default();
+        let mut nameservers = Vec::new();
+        for line in contents.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or("");
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("nameserver") => {
+                    if let Some(ip) = words.next().and_then(|word| word.parse::<IpAddr>().ok()) {
+                        nameservers.push(SocketAddr::new(ip, DNS_PORT));
+                    }
+                }
+                Some("domain") => {
+                    config.search = words
+                        .next()
+                        .map(|domain| vec![domain.trim_end_matches('.').to_owned()])
+                        .unwrap_or_default();
+                }
+                Some("search") => {
+                    config.search = words
+                        .map(|domain| domain.trim_end_matches('.').to_owned())
+                        .collect();
+                }
+                Some("options") => {
+                    for option in words {
+                        let mut parts = option.splitn(2, ':');
+                        let name = parts.next().unwrap_or("");
+                        let value = match parts.next().and_then(|value| value.parse::<u32>().ok()) {
+                            Some(value) => value,
+                            None => continue,
+                        };
+                        match name {
+                            "ndots" => config.ndots = value as _,
+                            "timeout" => config.timeout = u64::from(value) * 1000,
+                            "attempts" => config.attempts = value,
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        if !nameservers.is_empty() {
+            config.nameservers = nameservers;
+        }
+        config
+    }
+
+    /// Read a resolv.conf file. The file is read synchronously.
+    pub fn from_file(path: &str) -> Result<DnsConfig, Box<dyn std::error::Error>> {
+        Ok(DnsConfig::parse(&std::fs::read_to_string(path)?))
+    }
+
+    /// Read /etc/resolv.conf, or use the defaults if it cannot be read. The file is read
+    /// synchronously.
+    pub fn system() -> DnsConfig {
+        DnsConfig::from_file("/etc/resolv.conf").unwrap_or_default()
+    }
+}
+
+/// A Resolver which speaks DNS to the configured name servers itself, over UdpHandles on the
+/// loop, instead of calling getaddrinfo() on the threadpool. If a reply is truncated, the query is
+/// repeated over a TcpHandle. Each name server is tried in turn until one replies, up to
+/// DnsConfig::attempts times each.
+///
+/// Only DNS is consulted: /etc/hosts and nsswitch.conf are not, except that "localhost" and its
+/// subdomains always resolve to the loopback addresses (RFC 6761). Services must be numeric
+/// ports. Of the AiFlags, PASSIVE, CANONNAME, NUMERICHOST and NUMERICSERV are supported; the
+/// others are ignored. Lookups fail with EAI_NONAME if the name doesn't exist, or EAI_AGAIN if no
+/// name server replied.
+///
+/// DnsResolvers are cheap to clone: clones share the same configuration.
+#[derive(Clone)]
+pub struct DnsResolver {
+    config: Rc<DnsConfig>,
+}
+
+impl DnsResolver {
+    /// Create a resolver with the given configuration.
+    pub fn new(config: DnsConfig) -> DnsResolver {
+        DnsResolver {
+            config: Rc::new(config),
+        }
+    }
+
+    /// Create a resolver configured by /etc/resolv.conf. See DnsConfig::system().
+    pub fn system() -> DnsResolver {
+        DnsResolver::new(DnsConfig::system())
+    }
+
+    /// Returns the resolver's configuration.
+    pub fn config(&self) -> &DnsConfig {
+        &self.config
+    }
+}
+
+impl Resolver for DnsResolver {
+    fn resolve(
+        &self,
+        r#loop: &crate::Loop,
+        node: Option<&str>,
+        service: Option<&str>,
+        hints: &AddrInfo,
+        cb: ResolveCB<'static>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let flags = hints.ai_flags();
+        let qtypes = match hints.address_family() {
+            AddressFamily::Unspecified => vec![TYPE_AAAA, TYPE_A],
+            AddressFamily::Inet => vec![TYPE_A],
+            AddressFamily::Inet6 => vec![TYPE_AAAA],
+            AddressFamily::Other(_) => return Err(Box::new(crate::Error::EAI_FAMILY)),
+        };
+        let port = match service {
+            None => 0,
+            Some(service) => match service.parse::<u16>() {
+                Ok(port) => port,
+                Err(_) if flags.contains(AiFlags::NUMERICSERV) => {
+                    return Err(Box::new(crate::Error::EAI_NONAME))
+                }
+                Err(_) => return Err(Box::new(crate::Error::EAI_SERVICE)),
+            },
+        };
+        let template = Template {
+            port,
+            socktypes: socket_types(hints.socket_type(), hints.ip_protocol()),
+            canonname: flags.contains(AiFlags::CANONNAME),
+        };
+        let allowed = |ip: &IpAddr| qtypes.contains(&if ip.is_ipv4() { TYPE_A } else { TYPE_AAAA });
+
+        // answer what can be answered without asking a name server
+        let local: Option<Vec<IpAddr>> = match node {
+            None if service.is_none() => return Err(Box::new(crate::Error::EAI_NONAME)),
+            None if flags.contains(AiFlags::PASSIVE) => Some(vec![
+                Ipv6Addr::UNSPECIFIED.into(),
+                Ipv4Addr::UNSPECIFIED.into(),
+            ]),
+            None => Some(vec![Ipv6Addr::LOCALHOST.into(), Ipv4Addr::LOCALHOST.into()]),
+            Some(node) => match node.parse::<IpAddr>() {
+                Ok(ip) => Some(vec![ip]),
+                Err(_) if node.is_empty() || flags.contains(AiFlags::NUMERICHOST) => {
+                    return Err(Box::new(crate::Error::EAI_NONAME))
+                }
+                Err(_) => {
+                    let name = node.trim_end_matches('.').to_ascii_lowercase();
+                    if name == "localhost" || name.ends_with(".localhost") {
+                        Some(vec![Ipv6Addr::LOCALHOST.into(), Ipv4Addr::LOCALHOST.into()])
+                    } else {
+                        None
+                    }
+                }
+            },
+        };
+        if let Some(ips) = local {
+            let ips: Vec<IpAddr> = ips.into_iter().filter(allowed).collect();
+            let result = if ips.is_empty() {
+                Err(crate::Error::EAI_ADDRFAMILY)
+            } else {
+                Ok(template.infos(&ips, node))
+            };
+            return crate::deliver_later(r#loop, result, cb);
+        }
+
+        let lookup = Rc::new(RefCell::new(LookupState {
+            r#loop: r#loop.clone(),
+            config: self.config.clone(),
+            names: search_names(&self.config, node.unwrap_or_default()),
+            qtypes,
+            template,
+            name: String::new(),
+            pending: 0,
+            addrs: Vec::new(),
+            canonical_name: None,
+            error: None,
+            cb,
+        }));
+
+        // start on the next loop iteration, so that the callback is never called before this
+        // returns, even if every query fails immediately
+        let mut timer = r#loop.timer()?;
+        let started = timer.start(0, 0, move |mut timer: TimerHandle| {
+            timer.close(());
+            next_name(&lookup);
+        });
+        if let Err(e) = started {
+            timer.close(());
+            return Err(Box::new(e));
+        }
+        Ok(())
+    }
+}
+
+/// How to build AddrInfos from addresses.
+struct Template {
+    port: u16,
+
+    /// The (socktype, protocol) pairs to return for each address
+    socktypes: Vec<(u32, u32)>,
+    canonname: bool,
+}
+
+impl Template {
+    /// Build an AddrInfo for each address and socket type. With CANONNAME, the first AddrInfo
+    /// gets the canonical name.
+    fn infos(&self, ips: &[IpAddr], canonical_name: Option<&str>) -> Vec<AddrInfo> {
+        let mut infos: Vec<AddrInfo> = ips
+            .iter()
+            .flat_map(|ip| {
+                self.socktypes
+                    .iter()
+                    .map(move |&(socktype, protocol)| AddrInfo {
+                        flags: 0,
+                        family: if ip.is_ipv4() {
+                            AddressFamily::Inet.into_inner()
+                        } else {
+                            AddressFamily::Inet6.into_inner()
+                        },
+                        socktype,
+                        protocol,
+                        canonical_name: None,
+                        addr: Some(SocketAddr::new(*ip, self.port)),
+                    })
+            })
+            .collect();
+        if self.canonname {
+            if let Some(info) = infos.first_mut() {
+                info.canonical_name = canonical_name.map(|name| name.to_owned());
+            }
+        }
+        infos
+    }
+}
+
+/// The socket types to return for each address, as getaddrinfo() does when the hints don't
+/// narrow them down.
+fn socket_types(socktype: SocketType, protocol: Protocol) -> Vec<(u32, u32)> {
+    let pairs = match (socktype, protocol) {
+        (SocketType::Any, Protocol::Any) => vec![
+            (SocketType::Stream, Protocol::Tcp),
+            (SocketType::Datagram, Protocol::Udp),
+        ],
+        (SocketType::Any, Protocol::Tcp) | (SocketType::Stream, Protocol::Any) => {
+            vec![(SocketType::Stream, Protocol::Tcp)]
+        }
+        (SocketType::Any, Protocol::Udp) | (SocketType::Datagram, Protocol::Any) => {
+            vec![(SocketType::Datagram, Protocol::Udp)]
+        }
+        pair => vec![pair],
+    };
+    pairs
+        .into_iter()
+        .map(|(socktype, protocol)| (socktype.into_inner(), protocol.into_inner()))
+        .collect()
+}
+
+/// The names to query for name, in order, applying the search domains as resolv.conf(5)
+/// describes.
+fn search_names(config: &DnsConfig, name: &str) -> VecDeque<String> {
+    if name.ends_with('.') {
+        return std::iter::once(name.trim_end_matches('.').to_owned()).collect();
+    }
+    let searched = config
+        .search
+        .iter()
+        .map(|domain| format!("{}.{}", name, domain));
+    if name.matches('.').count() >= config.ndots {
+        std::iter::once(name.to_owned()).chain(searched).collect()
+    } else {
+        searched.chain(std::iter::once(name.to_owned())).collect()
+    }
+}
+
+/// A random query id, so that forged replies are harder to get accepted (RFC 5452). The id comes
+/// from the system's CSPRNG, via uv_random(); if that fails, it falls back to a randomly keyed
+/// RandomState from the standard library.
+fn random_id() -> u16 {
+    match crate::Loop::random_sync(2, 0) {
+        Ok(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+        Err(_) => std::collections::hash_map::RandomState::new()
+            .build_hasher()
+            .finish() as u16,
+    }
+}
+
+/// Encode a query for name, of type qtype, with recursion desired. The id is filled in for each
+/// try.
+fn build_query(name: &str, qtype: u16) -> crate::Result<Vec<u8>> {
+    let mut message = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+    for label in name.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(crate::Error::EAI_NONAME);
+        }
+        message.push(label.len() as u8);
+        message.extend_from_slice(label.as_bytes());
+    }
+    message.push(0);
+    if message.len() - 12 > 255 {
+        return Err(crate::Error::EAI_NONAME);
+    }
+    message.extend_from_slice(&qtype.to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(message)
+}
+
+/// What a name server said in reply to a query.
+struct Answer {
+    rcode: u8,
+    truncated: bool,
+    addrs: Vec<IpAddr>,
+
+    /// The target of the last CNAME record, if there was one
+    canonical_name: Option<String>,
+}
+
+/// Parse message, if it is a well-formed reply to query. The answers aren't parsed from truncated
+/// replies.
+fn parse_reply(message: &[u8], query: &[u8]) -> Option<Answer> {
+    let header = message.get(0..12)?;
+    let question = &query[12..];
+    let qdcount = u16::from_be_bytes([header[4], header[5]]);
+    let ancount = u16::from_be_bytes([header[6], header[7]]);
+    if header[0..2] != query[0..2]
+        || header[2] & 0x80 == 0
+        || qdcount != 1
+        || !message
+            .get(12..12 + question.len())?
+            .eq_ignore_ascii_case(question)
+    {
+        return None;
+    }
+
+    let mut answer = Answer {
+        rcode: header[3] & 0x0f,
+        truncated: header[2] & 0x02 != 0,
+        addrs: Vec::new(),
+        canonical_name: None,
+    };
+    if answer.truncated {
+        return Some(answer);
+    }
+
+    let qtype = u16::from_be_bytes([question[question.len() - 4], question[question.len() - 3]]);
+    let mut pos = 12 + question.len();
+    for _ in 0..ancount {
+        pos = skip_name(message, pos)?;
+        let record = message.get(pos..pos + 10)?;
+        let rtype = u16::from_be_bytes([record[0], record[1]]);
+        let class = u16::from_be_bytes([record[2], record[3]]);
+        let rdlength = u16::from_be_bytes([record[8], record[9]]) as usize;
+        let rdata = message.get(pos + 10..pos + 10 + rdlength)?;
+        if class == CLASS_IN {
+            match rtype {
+                TYPE_A if qtype == TYPE_A && rdata.len() == 4 => {
+                    answer
+                        .addrs
+                        .push(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]).into());
+                }
+                TYPE_AAAA if qtype == TYPE_AAAA && rdata.len() == 16 => {
+                    let mut octets = [0u8; 16];
+                    octets.copy_from_slice(rdata);
+                    answer.addrs.push(Ipv6Addr::from(octets).into());
+                }
+                TYPE_CNAME => answer.canonical_name = Some(read_name(message, pos + 10)?),
+                _ => {}
+            }
+        }
+        pos += 10 + rdlength;
+    }
+    Some(answer)
+}
+
+/// Returns the position after the (possibly compressed) name at pos.
+fn skip_name(message: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *message.get(pos)? as usize;
+        match len & 0xc0 {
+            0 if len == 0 => return Some(pos + 1),
+            0 => pos += 1 + len,
+            0xc0 => return message.get(pos + 1).map(|_| pos + 2),
+            _ => return None,
+        }
+    }
+}
+
+/// Decode the (possibly compressed) name at pos.
+fn read_name(message: &[u8], mut pos: usize) -> Option<String> {
+    let mut labels = Vec::new();
+
+    // a name has at most 127 labels; the limit also stops pointer loops
+    for _ in 0..256 {
+        let len = *message.get(pos)? as usize;
+        match len & 0xc0 {
+            0 if len == 0 => return Some(labels.join(".")),
+            0 => {
+                let label = message.get(pos + 1..pos + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + len;
+            }
+            0xc0 => pos = ((len & 0x3f) << 8) | *message.get(pos + 1)? as usize,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Copy the first len bytes out of a Buf filled by a read.
+fn buf_bytes(buf: &ReadonlyBuf, len: usize) -> Vec<u8> {
+    if len == 0 || !buf.is_allocated() {
+        return Vec::new();
+    }
+    let uv_buf: *const uv::uv_buf_t = buf.inner();
+    unsafe { std::slice::from_raw_parts((*uv_buf).base as *const u8, len) }.to_vec()
+}
+
+fn alloc_buffer(_: crate::Handle, suggested_size: usize) -> Option<Buf> {
+    Buf::with_capacity(suggested_size).ok()
+}
+
+/// The state of a lookup: the names still to try, and the answers for the current one.
+struct LookupState {
+    r#loop: crate::Loop,
+    config: Rc<DnsConfig>,
+    names: VecDeque<String>,
+    qtypes: Vec<u16>,
+    template: Template,
+
+    /// The name being queried
+    name: String,
+
+    /// Queries in progress for the name
+    pending: usize,
+    addrs: Vec<IpAddr>,
+    canonical_name: Option<String>,
+
+    /// The last error from a query which failed, rather than finding the name doesn't exist
+    error: Option<crate::Error>,
+    cb: ResolveCB<'static>,
+}
+
+/// Query the next name, or report failure if there are no more.
+fn next_name(lookup: &Rc<RefCell<LookupState>>) {
+    let (r#loop, config, name, qtypes) = {
+        let mut state = lookup.borrow_mut();
+        match state.names.pop_front() {
+            Some(name) => {
+                state.name = name.clone();
+                state.pending = state.qtypes.len();
+                state.addrs.clear();
+                state.canonical_name = None;
+                (
+                    state.r#loop.clone(),
+                    state.config.clone(),
+                    name,
+                    state.qtypes.clone(),
+                )
+            }
+            None => {
+                let error = state.error.unwrap_or(crate::Error::EAI_NONAME);
+                let mut cb = std::mem::take(&mut state.cb);
+                drop(state);
+                cb.call(Err(error));
+                return;
+            }
+        }
+    };
+    for qtype in qtypes {
+        let query_lookup = lookup.clone();
+        let result = query(
+            &r#loop,
+            config.clone(),
+            &name,
+            qtype,
+            Box::new(move |result: crate::Result<Answer>| on_query(&query_lookup, result)),
+        );
+        if let Err(e) = result {
+            on_query(lookup, Err(e));
+        }
+    }
+}
+
+fn on_query(lookup: &Rc<RefCell<LookupState>>, result: crate::Result<Answer>) {
+    let infos = {
+        let mut state = lookup.borrow_mut();
+        match result {
+            Ok(answer) => {
+                if answer.rcode == RCODE_NOERROR {
+                    state.addrs.extend(answer.addrs);
+                    if answer.canonical_name.is_some() {
+                        state.canonical_name = answer.canonical_name;
+                    }
+                }
+            }
+            Err(e) => state.error = Some(e),
+        }
+        state.pending -= 1;
+        if state.pending > 0 {
+            return;
+        }
+        if state.addrs.is_empty() {
+            None
+        } else {
+            // the AAAA and A queries finish in either order
+            state.addrs.sort_by_key(|ip| ip.is_ipv4());
+            let canonical_name = state.canonical_name.as_deref().unwrap_or(&state.name);
+            Some(state.template.infos(&state.addrs, Some(canonical_name)))
+        }
+    };
+    match infos {
+        Some(infos) => {
+            let mut cb = std::mem::take(&mut lookup.borrow_mut().cb);
+            cb.call(Ok(infos));
+        }
+        None => next_name(lookup),
+    }
+}
+
+/// Called with the outcome of a query: an answer, or the error from the last name server tried.
+type QueryDone = Box<dyn FnOnce(crate::Result<Answer>)>;
+
+/// The state of one query, which is sent to each name server in turn until one answers.
+struct QueryState {
+    r#loop: crate::Loop,
+    config: Rc<DnsConfig>,
+    message: Vec<u8>,
+
+    /// The number of tries made so far. Callbacks from earlier tries are ignored.
+    tries: usize,
+    udp: Option<UdpHandle>,
+    tcp: Option<TcpHandle>,
+    timer: Option<TimerHandle>,
+
+    /// What has been read of a reply over TCP
+    received: Vec<u8>,
+    last_error: crate::Error,
+    done: Option<QueryDone>,
+}
+
+impl QueryState {
+    fn close_handles(&mut self) {
+        if let Some(mut udp) = self.udp.take() {
+            udp.close(());
+        }
+        if let Some(mut tcp) = self.tcp.take() {
+            tcp.close(());
+        }
+        if let Some(mut timer) = self.timer.take() {
+            timer.close(());
+        }
+    }
+}
+
+fn query(
+    r#loop: &crate::Loop,
+    config: Rc<DnsConfig>,
+    name: &str,
+    qtype: u16,
+    done: QueryDone,
+) -> crate::Result<()> {
+    let state = Rc::new(RefCell::new(QueryState {
+        r#loop: r#loop.clone(),
+        config,
+        message: build_query(name, qtype)?,
+        tries: 0,
+        udp: None,
+        tcp: None,
+        timer: None,
+        received: Vec::new(),
+        last_error: crate::Error::EAI_AGAIN,
+        done: Some(done),
+    }));
+    next_try(&state);
+    Ok(())
+}
+
+fn is_stale(state: &Rc<RefCell<QueryState>>, try_id: usize) -> bool {
+    let state = state.borrow();
+    state.done.is_none() || state.tries != try_id
+}
+
+fn finish(state: &Rc<RefCell<QueryState>>, result: crate::Result<Answer>) {
+    let done = {
+        let mut state = state.borrow_mut();
+        state.close_handles();
+        state.done.take()
+    };
+    if let Some(done) = done {
+        done(result);
+    }
+}
+
+/// Send the query over UDP to the next name server, or finish if every try has been made.
+fn next_try(state: &Rc<RefCell<QueryState>>) {
+    loop {
+        let next = {
+            let mut query = state.borrow_mut();
+            query.close_handles();
+            let servers = query.config.nameservers.len();
+            if query.tries >= servers * query.config.attempts.max(1) as usize {
+                None
+            } else {
+                let server = query.config.nameservers[query.tries % servers];
+                query.tries += 1;
+                let id = random_id();
+                query.message[0..2].copy_from_slice(&id.to_be_bytes());
+                Some((query.r#loop.clone(), server, query.tries))
+            }
+        };
+        let (r#loop, server, try_id) = match next {
+            Some(next) => next,
+            None => {
+                let error = state.borrow().last_error;
+                return finish(state, Err(error));
+            }
+        };
+        match send_udp(state, &r#loop, server, try_id) {
+            Ok(_) => return,
+            Err(e) => state.borrow_mut().last_error = e,
+        }
+    }
+}
+
+/// Give up on a try, and move on to the next.
+fn fail_try(state: &Rc<RefCell<QueryState>>, try_id: usize, error: crate::Error) {
+    if is_stale(state, try_id) {
+        return;
+    }
+    state.borrow_mut().last_error = error;
+    next_try(state);
+}
+
+/// Start the timer which limits how long a try may take.
+fn start_timer(
+    state: &Rc<RefCell<QueryState>>,
+    r#loop: &crate::Loop,
+    try_id: usize,
+) -> crate::Result<()> {
+    let mut timer = r#loop.timer()?;
+    let timeout = {
+        let mut query = state.borrow_mut();
+        query.timer = Some(timer);
+        query.config.timeout
+    };
+    let timer_state = state.clone();
+    timer.start(timeout, 0, move |_: TimerHandle| {
+        fail_try(&timer_state, try_id, crate::Error::EAI_AGAIN)
+    })
+}
+
+fn send_udp(
+    state: &Rc<RefCell<QueryState>>,
+    r#loop: &crate::Loop,
+    server: SocketAddr,
+    try_id: usize,
+) -> crate::Result<()> {
+    let mut udp = r#loop.udp()?;
+    state.borrow_mut().udp = Some(udp);
+
+    // connecting binds the socket to the server's address family, and filters out datagrams
+    // from anywhere else
+    udp.connect(Some(&server))
+        .map_err(crate::Error::from_uv_boxed)?;
+    let recv_state = state.clone();
+    udp.recv_start(
+        alloc_buffer,
+        move |_: UdpHandle,
+              nread: crate::Result<usize>,
+              mut buf: ReadonlyBuf,
+              _: SocketAddr,
+              _: UdpRecvFlags| {
+            let reply = nread.map(|n| buf_bytes(&buf, n));
+            buf.dealloc();
+            on_udp_reply(&recv_state, server, try_id, reply);
+        },
+    )?;
+
+    let mut buf = Buf::new_from_bytes_exact(&state.borrow().message)?;
+    let send_state = state.clone();
+    let sent = udp.send(
+        None,
+        &[buf],
+        move |_: UdpSendReq, status: crate::Result<u32>| {
+            buf.destroy();
+            if status.is_err() {
+                fail_try(&send_state, try_id, crate::Error::EAI_AGAIN);
+            }
+        },
+    );
+    if let Err(e) = sent {
+        buf.destroy();
+        return Err(crate::Error::from_uv_boxed(e));
+    }
+    start_timer(state, r#loop, try_id)
+}
+
+fn on_udp_reply(
+    state: &Rc<RefCell<QueryState>>,
+    server: SocketAddr,
+    try_id: usize,
+    reply: crate::Result<Vec<u8>>,
+) {
+    if is_stale(state, try_id) {
+        return;
+    }
+    let answer = match reply {
+        // for example, ECONNREFUSED if nothing is listening
+        Err(_) => return fail_try(state, try_id, crate::Error::EAI_AGAIN),
+        Ok(reply) => parse_reply(&reply, &state.borrow().message),
+    };
+    match answer {
+        // not a reply to this query: keep waiting
+        None => {}
+        Some(answer) if answer.truncated => {
+            if let Err(e) = send_tcp(state, server, try_id) {
+                fail_try(state, try_id, e);
+            }
+        }
+        Some(answer) => on_answer(state, try_id, answer),
+    }
+}
+
+fn on_answer(state: &Rc<RefCell<QueryState>>, try_id: usize, answer: Answer) {
+    match answer.rcode {
+        RCODE_NOERROR | RCODE_NXDOMAIN => finish(state, Ok(answer)),
+        RCODE_SERVFAIL => fail_try(state, try_id, crate::Error::EAI_AGAIN),
+        _ => fail_try(state, try_id, crate::Error::EAI_FAIL),
+    }
+}
+
+/// Repeat the query over TCP to the same name server, after a truncated reply over UDP. The TCP
+/// connection is part of the same try, with its own timeout.
+fn send_tcp(
+    state: &Rc<RefCell<QueryState>>,
+    server: SocketAddr,
+    try_id: usize,
+) -> crate::Result<()> {
+    let r#loop = {
+        let mut query = state.borrow_mut();
+        query.close_handles();
+        query.received.clear();
+        query.r#loop.clone()
+    };
+    let mut tcp = r#loop.tcp()?;
+    state.borrow_mut().tcp = Some(tcp);
+    start_timer(state, &r#loop, try_id)?;
+
+    let connect_state = state.clone();
+    tcp.connect(&server, move |_: ConnectReq, status: crate::Result<u32>| {
+        if is_stale(&connect_state, try_id) {
+            return;
+        }
+        if let Err(e) = status.and_then(|_| write_tcp(&connect_state, tcp, try_id)) {
+            fail_try(&connect_state, try_id, e);
+        }
+    })
+    .map_err(crate::Error::from_uv_boxed)?;
+    Ok(())
+}
+
+fn write_tcp(
+    state: &Rc<RefCell<QueryState>>,
+    mut tcp: TcpHandle,
+    try_id: usize,
+) -> crate::Result<()> {
+    // over TCP, messages are prefixed with their length
+    let mut buf = {
+        let query = state.borrow();
+        let mut framed = (query.message.len() as u16).to_be_bytes().to_vec();
+        framed.extend_from_slice(&query.message);
+        Buf::new_from_bytes_exact(&framed)?
+    };
+    let write_state = state.clone();
+    let written = tcp.write(&[buf], move |_: WriteReq, status: crate::Result<u32>| {
+        buf.destroy();
+        if status.is_err() {
+            fail_try(&write_state, try_id, crate::Error::EAI_AGAIN);
+        }
+    });
+    if let Err(e) = written {
+        buf.destroy();
+        return Err(e);
+    }
+
+    let read_state = state.clone();
+    tcp.read_start(
+        alloc_buffer,
+        move |_: StreamHandle, nread: crate::Result<usize>, mut buf: ReadonlyBuf| {
+            let data = nread.map(|n| buf_bytes(&buf, n));
+            buf.dealloc();
+            on_tcp_read(&read_state, try_id, data);
+        },
+    )
+}
+
+fn on_tcp_read(state: &Rc<RefCell<QueryState>>, try_id: usize, data: crate::Result<Vec<u8>>) {
+    if is_stale(state, try_id) {
+        return;
+    }
+    let data = match data {
+        Ok(data) => data,
+        // including EOF before the whole reply was read
+        Err(_) => return fail_try(state, try_id, crate::Error::EAI_AGAIN),
+    };
+    let answer = {
+        let mut query = state.borrow_mut();
+        query.received.extend_from_slice(&data);
+        if query.received.len() < 2 {
+            return;
+        }
+        let len = u16::from_be_bytes([query.received[0], query.received[1]]) as usize;
+        if query.received.len() < 2 + len {
+            return;
+        }
+        parse_reply(&query.received[2..2 + len], &query.message)
+    };
+    match answer {
+        Some(answer) => on_answer(state, try_id, answer),
+        None => fail_try(state, try_id, crate::Error::EAI_FAIL),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reply to query, with the given flags in the third byte of the header, rcode, and
+    /// answer records.
+    fn reply(query: &[u8], flags: u8, rcode: u8, answers: &[Vec<u8>]) -> Vec<u8> {
+        let mut message = query.to_vec();
+        message[2] = 0x80 | flags;
+        message[3] = rcode;
+        message[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        for answer in answers {
+            message.extend_from_slice(answer);
+        }
+        message
+    }
+
+    /// A resource record of class IN, with name encoded as given.
+    fn record(name: &[u8], rtype: u16, rdata: &[u8]) -> Vec<u8> {
+        let mut record = name.to_vec();
+        record.extend_from_slice(&rtype.to_be_bytes());
+        record.extend_from_slice(&CLASS_IN.to_be_bytes());
+        record.extend_from_slice(&300u32.to_be_bytes());
+        record.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        record.extend_from_slice(rdata);
+        record
+    }
+
+    /// A pointer to the name in the question, which always starts right after the header.
+    const QNAME: [u8; 2] = [0xc0, 12];
+
+    fn query(name: &str, qtype: u16) -> Vec<u8> {
+        let mut query = build_query(name, qtype).unwrap();
+        query[0..2].copy_from_slice(&[0x12, 0x34]);
+        query
+    }
+
+    #[test]
+    fn parse_config() {
+        let config = DnsConfig::parse(
+            "# a comment\n\
+             nameserver 192.0.2.1 ; another comment\n\
+             nameserver not-an-address\n\
+             nameserver 2001:db8::1\n\
+             domain example.com\n\
+             search example.org. example.net\n\
+             options ndots:2 timeout:3 attempts:4 rotate attempts:x\n\
+             sortlist 130.155.160.0/255.255.240.0\n",
+        );
+        assert_eq!(
+            config.nameservers,
+            vec![
+                "192.0.2.1:53".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::1]:53".parse().unwrap()
+            ]
+        );
+        assert_eq!(config.search, vec!["example.org", "example.net"]);
+        assert_eq!(config.ndots, 2);
+        assert_eq!(config.timeout, 3_000);
+        assert_eq!(config.attempts, 4);
+
+        let config = DnsConfig::parse("domain example.com.\n#nameserver 192.0.2.1\n");
+        assert_eq!(config.nameservers, DnsConfig::default().nameservers);
+        assert_eq!(config.search, vec!["example.com"]);
+    }
+
+    #[test]
+    fn build_query_encodes_the_question() {
+        let query = build_query("www.example.com", TYPE_AAAA).unwrap();
+        let mut expected = vec![0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0];
+        expected.extend_from_slice(b"\x03www\x07example\x03com\x00");
+        expected.extend_from_slice(&[0, 28, 0, 1]);
+        assert_eq!(query, expected);
+    }
+
+    #[test]
+    fn build_query_rejects_bad_names() {
+        assert!(build_query("", TYPE_A).is_err());
+        assert!(build_query("a..b", TYPE_A).is_err());
+        assert!(build_query(&"a".repeat(64), TYPE_A).is_err());
+        assert!(build_query(&"a".repeat(63), TYPE_A).is_ok());
+
+        // 4 labels of 63 bytes make a 257 byte name
+        let long = vec!["a".repeat(63); 4].join(".");
+        assert!(build_query(&long, TYPE_A).is_err());
+        assert!(build_query(&long[2..], TYPE_A).is_ok());
+    }
+
+    #[test]
+    fn parse_reply_follows_compression_pointers() {
+        let query = query("www.example.com", TYPE_A);
+
+        // the CNAME's target is "web" followed by a pointer to "example.com" in the question
+        let cname_target = [b"\x03web".as_ref(), &[0xc0, 16]].concat();
+        let message = reply(
+            &query,
+            0,
+            RCODE_NOERROR,
+            &[
+                record(&QNAME, TYPE_CNAME, &cname_target),
+                record(&[0xc0, 16], TYPE_A, &[192, 0, 2, 1]),
+                record(&QNAME, TYPE_AAAA, &[0; 16]),
+                record(&QNAME, TYPE_A, &[192, 0, 2, 2]),
+            ],
+        );
+        let answer = parse_reply(&message, &query).unwrap();
+        assert_eq!(answer.rcode, RCODE_NOERROR);
+        assert!(!answer.truncated);
+        assert_eq!(answer.canonical_name.as_deref(), Some("web.example.com"));
+        assert_eq!(
+            answer.addrs,
+            vec![
+                IpAddr::from(Ipv4Addr::new(192, 0, 2, 1)),
+                IpAddr::from(Ipv4Addr::new(192, 0, 2, 2))
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_reply_ignores_the_answers_of_truncated_replies() {
+        let query = query("example.com", TYPE_A);
+        let mut message = reply(&query, 0x02, RCODE_NOERROR, &[]);
+
+        // a truncated reply may end in the middle of a record
+        message[7] = 1;
+        message.extend_from_slice(&QNAME);
+        let answer = parse_reply(&message, &query).unwrap();
+        assert!(answer.truncated);
+        assert!(answer.addrs.is_empty());
+    }
+
+    #[test]
+    fn parse_reply_rejects_other_messages() {
+        let query = query("example.com", TYPE_A);
+        let answers = [record(&QNAME, TYPE_A, &[192, 0, 2, 1])];
+        let message = reply(&query, 0, RCODE_NOERROR, &answers);
+        assert!(parse_reply(&message, &query).is_some());
+
+        // a different id
+        let mut other = message.clone();
+        other[1] ^= 1;
+        assert!(parse_reply(&other, &query).is_none());
+
+        // a query rather than a reply
+        let mut other = message.clone();
+        other[2] &= 0x7f;
+        assert!(parse_reply(&other, &query).is_none());
+
+        // a different question
+        let other = reply(
+            &self::query("example.org", TYPE_A),
+            0,
+            RCODE_NOERROR,
+            &answers,
+        );
+        assert!(parse_reply(&other, &query).is_none());
+
+        // cut short
+        assert!(parse_reply(&message[..message.len() - 1], &query).is_none());
+        assert!(parse_reply(&message[..11], &query).is_none());
+    }
+
+    #[test]
+    fn parse_reply_rejects_pointer_loops() {
+        let query = query("example.com", TYPE_A);
+
+        // the CNAME's target points at itself
+        let target_at = query.len() + 12;
+        let target = [0xc0 | (target_at >> 8) as u8, target_at as u8];
+        let message = reply(
+            &query,
+            0,
+            RCODE_NOERROR,
+            &[record(&QNAME, TYPE_CNAME, &target)],
+        );
+        assert!(parse_reply(&message, &query).is_none());
+    }
+
+    #[test]
+    fn parse_reply_reports_the_rcode() {
+        let query = query("example.com", TYPE_AAAA);
+        let answer = parse_reply(&reply(&query, 0, RCODE_NXDOMAIN, &[]), &query).unwrap();
+        assert_eq!(answer.rcode, RCODE_NXDOMAIN);
+        assert!(answer.addrs.is_empty());
+    }
+
+    #[test]
+    fn search_names_applies_ndots() {
+        let config = DnsConfig {
+            search: vec!["example.com".to_owned(), "example.net".to_owned()],
+            ndots: 1,
+            ..DnsConfig::default()
+        };
+        assert_eq!(
+            search_names(&config, "www"),
+            vec!["www.example.com", "www.example.net", "www"]
+        );
+        assert_eq!(
+            search_names(&config, "www.example.org"),
+            vec![
+                "www.example.org",
+                "www.example.org.example.com",
+                "www.example.org.example.net"
+            ]
+        );
+        assert_eq!(search_names(&config, "www."), vec!["www"]);
+
+        let config = DnsConfig { ndots: 3, ..config };
+        assert_eq!(
+            search_names(&config, "a.b"),
+            vec!["a.b.example.com", "a.b.example.net", "a.b"]
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use crate::{
+    AddrInfo, AiFlags, ConnectReq, HandleTrait, Protocol, Resolver, SocketType, TcpHandle,
+    TimerHandle,
+};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+callbacks! {
+    pub TcpConnectHostCB(result: crate::Result<TcpHandle>);
+}
+
+/// Options for Loop::tcp_connect_host_with_options().
+#[derive(Clone)]
+pub struct TcpConnectOptions {
+    /// How long to wait for a connection attempt before starting the next one in parallel, in
+    /// milliseconds. Defaults to 250, as recommended by RFC 8305.
+    pub attempt_delay: u64,
+
+    /// The Resolver to look up the host with, or None for SystemResolver. Defaults to None.
+    pub resolver: Option<Rc<dyn Resolver>>,
+}
+
+impl Default for TcpConnectOptions {
+    fn default() -> TcpConnectOptions {
+        TcpConnectOptions {
+            attempt_delay: 250,
+            resolver: None,
+        }
+    }
+}
+
+struct ConnectState {
+    r#loop: crate::Loop,
+    options: TcpConnectOptions,
+
+    /// Addresses which haven't been tried yet, in the order they will be tried
+    candidates: VecDeque<SocketAddr>,
+
+    /// Attempts in progress, by id
+    attempts: HashMap<usize, TcpHandle>,
+    next_id: usize,
+    timer: Option<TimerHandle>,
+    last_error: Option<crate::Error>,
+    done: bool,
+    cb: TcpConnectHostCB<'static>,
+}
+
+impl ConnectState {
+    fn close_timer(&mut self) {
+        if let Some(mut timer) = self.timer.take() {
+            timer.close(());
+        }
+    }
+}
+
+/// Order addresses for connecting: alternate between IPv6 and IPv4 addresses, starting with the
+/// family of the first address, and otherwise keeping their order (RFC 8305, section 4).
+fn interleave(addrs: Vec<SocketAddr>) -> VecDeque<SocketAddr> {
+    let first_v6 = addrs.first().is_some_and(|addr| addr.is_ipv6());
+    let (v6, v4): (VecDeque<SocketAddr>, VecDeque<SocketAddr>) =
+        addrs.into_iter().partition(|addr| addr.is_ipv6());
+    let (mut first, mut second) = if first_v6 { (v6, v4) } else { (v4, v6) };
+    let mut interleaved = VecDeque::with_capacity(first.len() + second.len());
+    while !first.is_empty() || !second.is_empty() {
+        interleaved.extend(first.pop_front());
+        interleaved.extend(second.pop_front());
+    }
+    interleaved
+}
+
+/// Start connecting to the next address, if there is one, then restart the timer for the one
+/// after that.
+fn start_next(state: &Rc<RefCell<ConnectState>>) {
+    loop {
+        let addr = match state.borrow_mut().candidates.pop_front() {
+            Some(addr) => addr,
+            None => break,
+        };
+        match start_attempt(state, addr) {
+            Ok(_) => break,
+            Err(e) => state.borrow_mut().last_error = Some(e),
+        }
+    }
+
+    let (timer, more) = {
+        let state = state.borrow();
+        (state.timer, !state.candidates.is_empty())
+    };
+    if let Some(mut timer) = timer {
+        // the timer repeats every attempt_delay, so again() restarts the delay
+        let result = if more { timer.again() } else { timer.stop() };
+        if let Err(e) = result {
+            state.borrow_mut().last_error = Some(e);
+        }
+    }
+    check_failed(state);
+}
+
+fn start_attempt(state: &Rc<RefCell<ConnectState>>, addr: SocketAddr) -> crate::Result<()> {
+    let (mut handle, id) = {
+        let mut state = state.borrow_mut();
+        let id = state.next_id;
+        state.next_id += 1;
+        (state.r#loop.tcp()?, id)
+    };
+    let connect_state = state.clone();
+    let result = handle.connect(&addr, move |_: ConnectReq, status: crate::Result<u32>| {
+        on_connect(&connect_state, id, handle, status)
+    });
+    match result {
+        Ok(_) => {
+            state.borrow_mut().attempts.insert(id, handle);
+            Ok(())
+        }
+        Err(e) => {
+            handle.close(());
+            Err(crate::Error::from_uv_boxed(e))
+        }
+    }
+}
+
+fn on_connect(
+    state: &Rc<RefCell<ConnectState>>,
+    id: usize,
+    mut handle: TcpHandle,
+    status: crate::Result<u32>,
+) {
+    let done = {
+        let mut state = state.borrow_mut();
+        state.attempts.remove(&id);
+        state.done
+    };
+    if done {
+        // a losing attempt, cancelled or completed after the winner
+        if !handle.is_closing() {
+            handle.close(());
+        }
+        return;
+    }
+
+    match status {
+        Ok(_) => {
+            let mut cb = {
+                let mut state = state.borrow_mut();
+                state.done = true;
+                state.close_timer();
+                for (_, mut loser) in state.attempts.drain() {
+                    loser.close(());
+                }
+                std::mem::take(&mut state.cb)
+            };
+            cb.call(Ok(handle));
+        }
+        Err(e) => {
+            state.borrow_mut().last_error = Some(e);
+            handle.close(());
+
+            // don't wait for the timer after a failure
+            start_next(state);
+        }
+    }
+}
+
+/// Report failure if every address has been tried, and every attempt has failed.
+fn check_failed(state: &Rc<RefCell<ConnectState>>) {
+    let mut cb = {
+        let mut state = state.borrow_mut();
+        if state.done || !state.candidates.is_empty() || !state.attempts.is_empty() {
+            return;
+        }
+        state.done = true;
+        state.close_timer();
+        std::mem::take(&mut state.cb)
+    };
+    let error = state
+        .borrow()
+        .last_error
+        .unwrap_or(crate::Error::EADDRNOTAVAIL);
+    cb.call(Err(error));
+}
+
+impl crate::Loop {
+    /// Resolve host, then connect to port on one of its addresses, using the Happy Eyeballs
+    /// algorithm of RFC 8305 with the default options. See tcp_connect_host_with_options().
+    pub fn tcp_connect_host<CB: Into<TcpConnectHostCB<'static>>>(
+        &self,
+        host: &str,
+        port: u16,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.tcp_connect_host_with_options(host, port, TcpConnectOptions::default(), cb)
+    }
+
+    /// Resolve host with options.resolver, then connect to port on one of its addresses, using the
+    /// Happy Eyeballs algorithm of RFC 8305: the addresses are sorted, then interleaved so that
+    /// IPv6 and IPv4 addresses alternate. A connection to the first address is started, and if it
+    /// hasn't succeeded after options.attempt_delay milliseconds, a connection to the next address
+    /// is started alongside it, and so on; when an attempt fails, the next one is started
+    /// immediately. The first attempt to succeed wins, and the others are closed.
+    ///
+    /// The callback is passed the connected TcpHandle, or the last error if every attempt failed.
+    pub fn tcp_connect_host_with_options<CB: Into<TcpConnectHostCB<'static>>>(
+        &self,
+        host: &str,
+        port: u16,
+        options: TcpConnectOptions,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut builder = self.resolver_builder();
+        if let Some(resolver) = &options.resolver {
+            builder = builder.resolver(resolver.clone());
+        }
+        let state = Rc::new(RefCell::new(ConnectState {
+            r#loop: self.clone(),
+            options,
+            candidates: VecDeque::new(),
+            attempts: HashMap::new(),
+            next_id: 0,
+            timer: None,
+            last_error: None,
+            done: false,
+            cb: cb.into(),
+        }));
+        let resolve_state = state.clone();
+        builder
+            .node(host)
+            .port(port)
+            .socket_type(SocketType::Stream)
+            .protocol(Protocol::Tcp)
+            .flags(AiFlags::ADDRCONFIG)
+            .resolve(move |result: crate::Result<Vec<AddrInfo>>| {
+                let result = result.and_then(|infos| {
+                    let addrs = infos.into_iter().filter_map(|info| info.addr).collect();
+                    let mut state = resolve_state.borrow_mut();
+                    state.candidates = interleave(addrs);
+
+                    let mut timer = state.r#loop.timer()?;
+                    let delay = state.options.attempt_delay.max(1);
+                    let timer_state = resolve_state.clone();
+                    let started =
+                        timer.start(delay, delay, move |_: TimerHandle| start_next(&timer_state));
+                    if let Err(e) = started {
+                        timer.close(());
+                        return Err(e);
+                    }
+                    state.timer = Some(timer);
+                    Ok(())
+                });
+                match result {
+                    Ok(_) => start_next(&resolve_state),
+                    Err(e) => {
+                        let mut cb = {
+                            let mut state = resolve_state.borrow_mut();
+                            state.done = true;
+                            std::mem::take(&mut state.cb)
+                        };
+                        cb.call(Err(e));
+                    }
+                }
+            })?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::interleave;
+    use std::net::SocketAddr;
+
+    fn addrs(addrs: &[&str]) -> Vec<SocketAddr> {
+        addrs.iter().map(|addr| addr.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn interleave_alternates_families_starting_with_the_first() {
+        let interleaved = interleave(addrs(&[
+            "[::1]:80",
+            "[::2]:80",
+            "[::3]:80",
+            "127.0.0.1:80",
+            "127.0.0.2:80",
+        ]));
+        assert_eq!(
+            Vec::from(interleaved),
+            addrs(&[
+                "[::1]:80",
+                "127.0.0.1:80",
+                "[::2]:80",
+                "127.0.0.2:80",
+                "[::3]:80"
+            ])
+        );
+
+        let interleaved = interleave(addrs(&["127.0.0.1:80", "[::1]:80", "[::2]:80"]));
+        assert_eq!(
+            Vec::from(interleaved),
+            addrs(&["127.0.0.1:80", "[::1]:80", "[::2]:80"])
+        );
+    }
+
+    #[test]
+    fn interleave_keeps_the_order_of_a_single_family() {
+        let single = addrs(&["127.0.0.2:80", "127.0.0.1:80", "127.0.0.3:80"]);
+        assert_eq!(Vec::from(interleave(single.clone())), single);
+        assert!(interleave(Vec::new()).is_empty());
+    }
+}
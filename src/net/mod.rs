@@ -1,5 +1,23 @@
 pub mod addrinfo;
 pub use addrinfo::*;
 
+pub mod dns_resolver;
+pub use dns_resolver::*;
+
+pub mod happy_eyeballs;
+pub use happy_eyeballs::*;
+
 pub mod misc;
 pub use misc::*;
+
+pub mod nameinfo;
+pub use nameinfo::*;
+
+pub mod resolve;
+pub use resolve::*;
+
+pub mod resolver;
+pub use resolver::*;
+
+pub mod resolver_cache;
+pub use resolver_cache::*;
@@ -0,0 +1,172 @@
+use crate::{GetNameInfoReq, HandleTrait, TimerHandle};
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::rc::Rc;
+
+bitflags! {
+    /// Flags for getnameinfo(). See getnameinfo(3).
+    pub struct NameInfoFlags: u32 {
+        /// Return the host's address as a string, instead of looking up its name
+        /// (NI_NUMERICHOST).
+        const NUMERICHOST = uv::NI_NUMERICHOST as _;
+
+        /// Return the port as a string, instead of looking up the service name (NI_NUMERICSERV).
+        const NUMERICSERV = uv::NI_NUMERICSERV as _;
+
+        /// Return only the host name part of the fully qualified domain name, for local hosts
+        /// (NI_NOFQDN).
+        const NOFQDN = uv::NI_NOFQDN as _;
+
+        /// Fail if the host's name cannot be found, instead of returning its address as a string
+        /// (NI_NAMEREQD).
+        const NAMEREQD = uv::NI_NAMEREQD as _;
+
+        /// Look up the service name of a datagram service, for services whose UDP and TCP ports
+        /// differ (NI_DGRAM).
+        const DGRAM = uv::NI_DGRAM as _;
+    }
+}
+
+callbacks! {
+    pub GetNameInfoBatchCB(results: HashMap<SocketAddr, crate::Result<NameInfo>>);
+}
+
+/// The result of getnameinfo() for one address.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NameInfo {
+    /// The host name, or the address as a string.
+    pub host: String,
+
+    /// The service name, or the port as a string.
+    pub service: String,
+}
+
+/// Options for Loop::getnameinfo_batch().
+#[derive(Clone, Copy, Debug)]
+pub struct GetNameInfoBatchOptions {
+    /// The flags for each lookup. Defaults to none.
+    pub flags: NameInfoFlags,
+
+    /// The most lookups to have in progress at once. Each lookup occupies a threadpool thread
+    /// while it runs, so this defaults to 4, the size of the default threadpool; values below 1
+    /// are treated as 1.
+    pub concurrency: usize,
+}
+
+impl Default for GetNameInfoBatchOptions {
+    fn default() -> GetNameInfoBatchOptions {
+        GetNameInfoBatchOptions {
+            flags: NameInfoFlags::empty(),
+            concurrency: 4,
+        }
+    }
+}
+
+struct BatchState {
+    r#loop: crate::Loop,
+    flags: NameInfoFlags,
+    concurrency: usize,
+
+    /// Addresses which haven't been looked up yet
+    queue: VecDeque<SocketAddr>,
+    in_flight: usize,
+    results: HashMap<SocketAddr, crate::Result<NameInfo>>,
+    cb: GetNameInfoBatchCB<'static>,
+}
+
+/// Start lookups until the concurrency limit is reached, or report the results if every lookup
+/// has finished.
+fn start_lookups(state: &Rc<RefCell<BatchState>>) {
+    loop {
+        let (r#loop, addr, flags) = {
+            let mut batch = state.borrow_mut();
+            if batch.in_flight >= batch.concurrency {
+                return;
+            }
+            match batch.queue.pop_front() {
+                Some(addr) => {
+                    batch.in_flight += 1;
+                    (batch.r#loop.clone(), addr, batch.flags)
+                }
+                None if batch.in_flight == 0 => break,
+                None => return,
+            }
+        };
+        let lookup_state = state.clone();
+        let result = r#loop.getnameinfo(
+            &addr,
+            flags,
+            move |_: GetNameInfoReq, status: crate::Result<u32>, host: String, service: String| {
+                let result = status.map(|_| NameInfo { host, service });
+                finish_lookup(&lookup_state, addr, result);
+                start_lookups(&lookup_state);
+            },
+        );
+        if let Err(e) = result {
+            finish_lookup(state, addr, Err(crate::Error::from_uv_boxed(e)));
+        }
+    }
+
+    let (mut cb, results) = {
+        let mut batch = state.borrow_mut();
+        (
+            std::mem::take(&mut batch.cb),
+            std::mem::take(&mut batch.results),
+        )
+    };
+    cb.call(results);
+}
+
+fn finish_lookup(
+    state: &Rc<RefCell<BatchState>>,
+    addr: SocketAddr,
+    result: crate::Result<NameInfo>,
+) {
+    let mut batch = state.borrow_mut();
+    batch.in_flight -= 1;
+    batch.results.insert(addr, result);
+}
+
+impl crate::Loop {
+    /// Look up the names of many addresses with getnameinfo(), with at most
+    /// options.concurrency lookups in progress at once. Duplicate addresses are only looked up
+    /// once. When every lookup has finished, the callback is passed a map from each address to
+    /// its result; it is always called on a later loop iteration, even if addrs is empty.
+    pub fn getnameinfo_batch<CB: Into<GetNameInfoBatchCB<'static>>>(
+        &self,
+        addrs: &[SocketAddr],
+        options: GetNameInfoBatchOptions,
+        cb: CB,
+    ) -> crate::Result<()> {
+        let mut queue = VecDeque::with_capacity(addrs.len());
+        let mut seen = std::collections::HashSet::with_capacity(addrs.len());
+        for addr in addrs {
+            if seen.insert(*addr) {
+                queue.push_back(*addr);
+            }
+        }
+        let state = Rc::new(RefCell::new(BatchState {
+            r#loop: self.clone(),
+            flags: options.flags,
+            concurrency: options.concurrency.max(1),
+            queue,
+            in_flight: 0,
+            results: HashMap::with_capacity(seen.len()),
+            cb: cb.into(),
+        }));
+
+        // start on the next loop iteration, so that the callback is never called before this
+        // returns
+        let mut timer = self.timer()?;
+        let started = timer.start(0, 0, move |mut timer: TimerHandle| {
+            timer.close(());
+            start_lookups(&state);
+        });
+        if let Err(e) = started {
+            timer.close(());
+            return Err(e);
+        }
+        Ok(())
+    }
+}
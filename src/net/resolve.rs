@@ -0,0 +1,462 @@
+use crate::{AddrInfo, CacheKey, FromInner, IntoInner, Resolver, SystemResolver};
+use std::collections::HashSet;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::rc::Rc;
+
+callbacks! {
+    pub ResolveCB(result: crate::Result<Vec<AddrInfo>>);
+}
+
+/// An address family, for the family of an AddrInfo.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AddressFamily {
+    /// Any family (AF_UNSPEC).
+    Unspecified,
+
+    /// IPv4 (AF_INET).
+    Inet,
+
+    /// IPv6 (AF_INET6).
+    Inet6,
+
+    /// Some other AF_* constant.
+    Other(u32),
+}
+
+impl FromInner<u32> for AddressFamily {
+    fn from_inner(family: u32) -> AddressFamily {
+        match family {
+            f if f == uv::AF_UNSPEC as u32 => AddressFamily::Unspecified,
+            f if f == uv::AF_INET as u32 => AddressFamily::Inet,
+            f if f == uv::AF_INET6 as u32 => AddressFamily::Inet6,
+            f => AddressFamily::Other(f),
+        }
+    }
+}
+
+impl IntoInner<u32> for AddressFamily {
+    fn into_inner(self) -> u32 {
+        match self {
+            AddressFamily::Unspecified => uv::AF_UNSPEC as _,
+            AddressFamily::Inet => uv::AF_INET as _,
+            AddressFamily::Inet6 => uv::AF_INET6 as _,
+            AddressFamily::Other(f) => f,
+        }
+    }
+}
+
+/// A socket type, for the socktype of an AddrInfo.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SocketType {
+    /// Any socket type (0).
+    Any,
+
+    /// A stream socket, such as TCP (SOCK_STREAM).
+    Stream,
+
+    /// A datagram socket, such as UDP (SOCK_DGRAM).
+    Datagram,
+
+    /// A raw socket (SOCK_RAW).
+    Raw,
+
+    /// Some other SOCK_* constant.
+    Other(u32),
+}
+
+impl FromInner<u32> for SocketType {
+    fn from_inner(socktype: u32) -> SocketType {
+        match socktype {
+            0 => SocketType::Any,
+            t if t == uv::SOCK_STREAM as u32 => SocketType::Stream,
+            t if t == uv::SOCK_DGRAM as u32 => SocketType::Datagram,
+            t if t == uv::SOCK_RAW as u32 => SocketType::Raw,
+            t => SocketType::Other(t),
+        }
+    }
+}
+
+impl IntoInner<u32> for SocketType {
+    fn into_inner(self) -> u32 {
+        match self {
+            SocketType::Any => 0,
+            SocketType::Stream => uv::SOCK_STREAM as _,
+            SocketType::Datagram => uv::SOCK_DGRAM as _,
+            SocketType::Raw => uv::SOCK_RAW as _,
+            SocketType::Other(t) => t,
+        }
+    }
+}
+
+/// A protocol, for the protocol of an AddrInfo.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Protocol {
+    /// Any protocol (0).
+    Any,
+
+    /// TCP (IPPROTO_TCP).
+    Tcp,
+
+    /// UDP (IPPROTO_UDP).
+    Udp,
+
+    /// Some other IPPROTO_* constant.
+    Other(u32),
+}
+
+impl FromInner<u32> for Protocol {
+    fn from_inner(protocol: u32) -> Protocol {
+        match protocol {
+            0 => Protocol::Any,
+            p if p == uv::IPPROTO_TCP as u32 => Protocol::Tcp,
+            p if p == uv::IPPROTO_UDP as u32 => Protocol::Udp,
+            p => Protocol::Other(p),
+        }
+    }
+}
+
+impl IntoInner<u32> for Protocol {
+    fn into_inner(self) -> u32 {
+        match self {
+            Protocol::Any => 0,
+            Protocol::Tcp => uv::IPPROTO_TCP as _,
+            Protocol::Udp => uv::IPPROTO_UDP as _,
+            Protocol::Other(p) => p,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags for the flags of an AddrInfo. See getaddrinfo(3).
+    pub struct AiFlags: u32 {
+        /// Return addresses suitable for binding a listening socket (AI_PASSIVE).
+        const PASSIVE = uv::AI_PASSIVE as _;
+
+        /// Return the canonical name of the host in the first result (AI_CANONNAME).
+        const CANONNAME = uv::AI_CANONNAME as _;
+
+        /// The node is a numeric address, and must not be looked up (AI_NUMERICHOST).
+        const NUMERICHOST = uv::AI_NUMERICHOST as _;
+
+        /// The service is a numeric port, and must not be looked up (AI_NUMERICSERV).
+        const NUMERICSERV = uv::AI_NUMERICSERV as _;
+
+        /// If IPv6 addresses were asked for but none were found, return IPv4-mapped IPv6
+        /// addresses (AI_V4MAPPED).
+        const V4MAPPED = uv::AI_V4MAPPED as _;
+
+        /// With V4MAPPED, return both IPv6 and IPv4-mapped IPv6 addresses (AI_ALL).
+        const ALL = uv::AI_ALL as _;
+
+        /// Only return IPv4 or IPv6 addresses if the host has an address of that family configured
+        /// (AI_ADDRCONFIG).
+        const ADDRCONFIG = uv::AI_ADDRCONFIG as _;
+    }
+}
+
+impl AddrInfo {
+    /// Create hints for getaddrinfo().
+    pub fn hints(
+        family: AddressFamily,
+        socktype: SocketType,
+        protocol: Protocol,
+        flags: AiFlags,
+    ) -> AddrInfo {
+        AddrInfo {
+            flags: flags.bits(),
+            family: family.into_inner(),
+            socktype: socktype.into_inner(),
+            protocol: protocol.into_inner(),
+            canonical_name: None,
+            addr: None,
+        }
+    }
+
+    /// The flags, as AiFlags. Unknown flags are dropped.
+    pub fn ai_flags(&self) -> AiFlags {
+        AiFlags::from_bits_truncate(self.flags)
+    }
+
+    /// The family, as an AddressFamily.
+    pub fn address_family(&self) -> AddressFamily {
+        AddressFamily::from_inner(self.family)
+    }
+
+    /// The socktype, as a SocketType.
+    pub fn socket_type(&self) -> SocketType {
+        SocketType::from_inner(self.socktype)
+    }
+
+    /// The protocol, as a Protocol.
+    pub fn ip_protocol(&self) -> Protocol {
+        Protocol::from_inner(self.protocol)
+    }
+}
+
+/// Remove AddrInfos whose address duplicates an earlier one's, keeping the first. getaddrinfo()
+/// returns each address once per socket type unless the hints restrict it, for example.
+/// AddrInfos without an address are removed.
+pub fn dedup_addrinfos(infos: &mut Vec<AddrInfo>) {
+    let mut seen = HashSet::new();
+    infos.retain(|info| match info.addr {
+        Some(addr) => seen.insert(addr),
+        None => false,
+    });
+}
+
+/// Sort addresses into the order they should be tried in, following the destination address
+/// selection rules of RFC 6724 that don't depend on the source address: addresses are sorted by
+/// the precedence in the default policy table (rule 6), then by smaller scope (rule 8). The sort
+/// is stable, so otherwise the order from the resolver is kept (rule 10).
+///
+/// With the default policy table, this prefers IPv6 to IPv4, except that IPv4 is preferred to
+/// 6to4, Teredo, ULA and deprecated IPv6 addresses.
+pub fn sort_addrs(addrs: &mut [SocketAddr]) {
+    addrs.sort_by_key(|addr| sort_key(&addr.ip()));
+}
+
+/// Sort AddrInfos by their addresses, as sort_addrs() does. AddrInfos without an address are
+/// sorted last.
+pub fn sort_addrinfos(infos: &mut [AddrInfo]) {
+    infos.sort_by_key(|info| match info.addr {
+        Some(addr) => sort_key(&addr.ip()),
+        None => (u8::MAX, u8::MAX),
+    });
+}
+
+/// The key for sorting addresses: the precedence inverted, so that higher precedence sorts first,
+/// then the scope.
+fn sort_key(ip: &IpAddr) -> (u8, u8) {
+    let ip = match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+        IpAddr::V6(ip) => *ip,
+    };
+    (u8::MAX - precedence(&ip), scope(&ip))
+}
+
+/// The precedence of an address in the default policy table of RFC 6724, section 2.1.
+fn precedence(ip: &Ipv6Addr) -> u8 {
+    let s = ip.segments();
+    if *ip == Ipv6Addr::LOCALHOST {
+        50
+    } else if s[..5] == [0, 0, 0, 0, 0] && s[5] == 0xffff {
+        // ::ffff:0:0/96, IPv4
+        35
+    } else if s[0] == 0x2002 {
+        // 2002::/16, 6to4
+        30
+    } else if s[0] == 0x2001 && s[1] == 0 {
+        // 2001::/32, Teredo
+        5
+    } else if s[0] & 0xfe00 == 0xfc00 {
+        // fc00::/7, unique local
+        3
+    } else if s[..6] == [0, 0, 0, 0, 0, 0] || s[0] & 0xffc0 == 0xfec0 || s[0] == 0x3ffe {
+        // ::/96 (IPv4-compatible), fec0::/10 (site-local) and 3ffe::/16 (6bone), deprecated
+        1
+    } else {
+        40
+    }
+}
+
+/// The scope of an address, as defined in RFC 6724, section 3.1.
+fn scope(ip: &Ipv6Addr) -> u8 {
+    const LINK_LOCAL: u8 = 0x2;
+    const SITE_LOCAL: u8 = 0x5;
+    const GLOBAL: u8 = 0xe;
+
+    let s = ip.segments();
+    if s[0] & 0xff00 == 0xff00 {
+        // multicast addresses carry their scope
+        (s[0] & 0xf) as u8
+    } else if *ip == Ipv6Addr::LOCALHOST || s[0] & 0xffc0 == 0xfe80 {
+        LINK_LOCAL
+    } else if s[0] & 0xffc0 == 0xfec0 {
+        SITE_LOCAL
+    } else if let Some(ip) = ip.to_ipv4() {
+        // RFC 6724, section 3.2: IPv4 loopback and autoconfiguration addresses are link-local,
+        // and everything else (including private addresses) is global
+        let o = ip.octets();
+        if s[5] == 0xffff && (o[0] == 127 || (o[0] == 169 && o[1] == 254)) {
+            LINK_LOCAL
+        } else {
+            GLOBAL
+        }
+    } else {
+        GLOBAL
+    }
+}
+
+/// A builder for getaddrinfo() requests, with typed hints. By default, the results are
+/// deduplicated with dedup_addrinfos() and sorted with sort_addrinfos(), and the lookup is made
+/// with SystemResolver.
+#[derive(Clone)]
+pub struct ResolverBuilder {
+    r#loop: crate::Loop,
+    resolver: Rc<dyn Resolver>,
+    node: Option<String>,
+    service: Option<String>,
+    family: AddressFamily,
+    socktype: SocketType,
+    protocol: Protocol,
+    flags: AiFlags,
+    dedup: bool,
+    sort: bool,
+}
+
+impl ResolverBuilder {
+    /// Create a new builder, which resolves nothing until a node or service is set.
+    pub fn new(r#loop: &crate::Loop) -> ResolverBuilder {
+        ResolverBuilder {
+            r#loop: r#loop.clone(),
+            resolver: Rc::new(SystemResolver),
+            node: None,
+            service: None,
+            family: AddressFamily::Unspecified,
+            socktype: SocketType::Any,
+            protocol: Protocol::Any,
+            flags: AiFlags::empty(),
+            dedup: true,
+            sort: true,
+        }
+    }
+
+    /// The Resolver to make the lookup with.
+    pub fn resolver(mut self, resolver: Rc<dyn Resolver>) -> ResolverBuilder {
+        self.resolver = resolver;
+        self
+    }
+
+    /// The host name or numeric address to resolve.
+    pub fn node(mut self, node: &str) -> ResolverBuilder {
+        self.node = Some(node.to_owned());
+        self
+    }
+
+    /// The service name or numeric port to resolve.
+    pub fn service(mut self, service: &str) -> ResolverBuilder {
+        self.service = Some(service.to_owned());
+        self
+    }
+
+    /// A numeric port. This sets the service, and the NUMERICSERV flag.
+    pub fn port(mut self, port: u16) -> ResolverBuilder {
+        self.flags |= AiFlags::NUMERICSERV;
+        self.service(&port.to_string())
+    }
+
+    /// Restrict the results to an address family.
+    pub fn family(mut self, family: AddressFamily) -> ResolverBuilder {
+        self.family = family;
+        self
+    }
+
+    /// Restrict the results to a socket type.
+    pub fn socket_type(mut self, socktype: SocketType) -> ResolverBuilder {
+        self.socktype = socktype;
+        self
+    }
+
+    /// Restrict the results to a protocol.
+    pub fn protocol(mut self, protocol: Protocol) -> ResolverBuilder {
+        self.protocol = protocol;
+        self
+    }
+
+    /// Add flags.
+    pub fn flags(mut self, flags: AiFlags) -> ResolverBuilder {
+        self.flags |= flags;
+        self
+    }
+
+    /// Whether to remove duplicate addresses from the results. Defaults to true.
+    pub fn dedup(mut self, dedup: bool) -> ResolverBuilder {
+        self.dedup = dedup;
+        self
+    }
+
+    /// Whether to sort the results into the order they should be tried in. Defaults to true.
+    pub fn sort(mut self, sort: bool) -> ResolverBuilder {
+        self.sort = sort;
+        self
+    }
+
+    /// Returns the hints passed to the Resolver.
+    pub fn hints(&self) -> AddrInfo {
+        AddrInfo::hints(self.family, self.socktype, self.protocol, self.flags)
+    }
+
+    fn finish(&self, mut infos: Vec<AddrInfo>) -> Vec<AddrInfo> {
+        if self.dedup {
+            dedup_addrinfos(&mut infos);
+        }
+        if self.sort {
+            sort_addrinfos(&mut infos);
+        }
+        infos
+    }
+
+    fn key(&self) -> CacheKey {
+        CacheKey {
+            node: self.node.clone(),
+            service: self.service.clone(),
+            family: self.family,
+            socktype: self.socktype,
+            protocol: self.protocol,
+            flags: self.flags,
+        }
+    }
+
+    /// Resolve asynchronously. The callback is passed the results. If the loop has a
+    /// ResolverCache, it is used.
+    pub fn resolve<CB: Into<ResolveCB<'static>>>(
+        &self,
+        cb: CB,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut cb = cb.into();
+        let builder = self.clone();
+        if let Some(cache) = self.r#loop.resolver_cache() {
+            return cache.lookup(
+                self.key(),
+                &*self.resolver,
+                (move |result: crate::Result<Vec<AddrInfo>>| {
+                    cb.call(result.map(|infos| builder.finish(infos)))
+                })
+                .into(),
+            );
+        }
+
+        self.resolver.resolve(
+            &self.r#loop,
+            self.node.as_deref(),
+            self.service.as_deref(),
+            &self.hints(),
+            (move |result: crate::Result<Vec<AddrInfo>>| {
+                cb.call(result.map(|infos| builder.finish(infos)))
+            })
+            .into(),
+        )
+    }
+
+    /// Resolve synchronously. If the loop has a ResolverCache, it is used. Not every Resolver can
+    /// resolve synchronously: those which can't fail with ENOTSUP.
+    pub fn resolve_sync(&self) -> Result<Vec<AddrInfo>, Box<dyn std::error::Error>> {
+        let infos = match self.r#loop.resolver_cache() {
+            Some(cache) => cache.lookup_sync(self.key(), &*self.resolver)?,
+            None => self.resolver.resolve_sync(
+                &self.r#loop,
+                self.node.as_deref(),
+                self.service.as_deref(),
+                &self.hints(),
+            )?,
+        };
+        Ok(self.finish(infos))
+    }
+}
+
+impl crate::Loop {
+    /// Create a ResolverBuilder. See ResolverBuilder.
+    pub fn resolver_builder(&self) -> ResolverBuilder {
+        ResolverBuilder::new(self)
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{AddrInfo, GetAddrInfoReq, ResolveCB};
+
+/// Something that turns host names and services into addresses, the way getaddrinfo() does.
+/// Helpers which resolve names, such as ResolverBuilder and Loop::tcp_connect_host(), accept any
+/// Resolver, and use SystemResolver by default.
+pub trait Resolver {
+    /// Resolve node and service, using the given hints (see AddrInfo::hints()). The callback must
+    /// be called exactly once, on the loop, and never before this returns. If this returns an
+    /// error, the callback is not called.
+    fn resolve(
+        &self,
+        r#loop: &crate::Loop,
+        node: Option<&str>,
+        service: Option<&str>,
+        hints: &AddrInfo,
+        cb: ResolveCB<'static>,
+    ) -> Result<(), Box<dyn std::error::Error>>;
+
+    /// Resolve node and service synchronously. Resolvers which can only work on the loop don't
+    /// need to implement this: by default, it fails with ENOTSUP.
+    fn resolve_sync(
+        &self,
+        _loop: &crate::Loop,
+        _node: Option<&str>,
+        _service: Option<&str>,
+        _hints: &AddrInfo,
+    ) -> Result<Vec<AddrInfo>, Box<dyn std::error::Error>> {
+        Err(Box::new(crate::Error::ENOTSUP))
+    }
+}
+
+/// The default Resolver, which calls the system's getaddrinfo() on the threadpool. It reads
+/// /etc/hosts, /etc/nsswitch.conf, and so on, like every other program on the system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemResolver;
+
+impl Resolver for SystemResolver {
+    fn resolve(
+        &self,
+        r#loop: &crate::Loop,
+        node: Option<&str>,
+        service: Option<&str>,
+        hints: &AddrInfo,
+        mut cb: ResolveCB<'static>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        r#loop.getaddrinfo(
+            node,
+            service,
+            Some(hints.clone()),
+            move |_: GetAddrInfoReq, status: crate::Result<u32>, infos: Vec<AddrInfo>| {
+                cb.call(status.map(|_| infos))
+            },
+        )?;
+        Ok(())
+    }
+
+    fn resolve_sync(
+        &self,
+        r#loop: &crate::Loop,
+        node: Option<&str>,
+        service: Option<&str>,
+        hints: &AddrInfo,
+    ) -> Result<Vec<AddrInfo>, Box<dyn std::error::Error>> {
+        r#loop.getaddrinfo_sync(node, service, Some(hints.clone()))
+    }
+}
@@ -0,0 +1,322 @@
+use crate::{AddrInfo, AddressFamily, AiFlags, Protocol, ResolveCB, Resolver, SocketType};
+use crate::{HandleTrait, IntoInner, TimerHandle};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::rc::Rc;
+
+/// Options for ResolverCache.
+#[derive(Clone, Copy, Debug)]
+pub struct ResolverCacheOptions {
+    /// How long successful lookups are cached, in milliseconds. Resolvers don't report the TTLs of
+    /// the records they return, so this applies to every lookup. Defaults to 60 seconds.
+    pub ttl: u64,
+
+    /// How long failed lookups are cached, in milliseconds. Only failures which say the name
+    /// doesn't exist (EAI_NONAME, EAI_NODATA and EAI_ADDRFAMILY) are cached; transient failures,
+    /// such as EAI_AGAIN, are not. Defaults to 5 seconds; 0 disables negative caching.
+    pub negative_ttl: u64,
+
+    /// The most lookups to cache. When the cache is full, expired lookups are removed, then the
+    /// lookup closest to expiring. Defaults to 1024.
+    pub max_entries: usize,
+}
+
+impl Default for ResolverCacheOptions {
+    fn default() -> ResolverCacheOptions {
+        ResolverCacheOptions {
+            ttl: 60_000,
+            negative_ttl: 5_000,
+            max_entries: 1024,
+        }
+    }
+}
+
+/// What was looked up: the arguments to getaddrinfo().
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub(crate) struct CacheKey {
+    pub(crate) node: Option<String>,
+    pub(crate) service: Option<String>,
+    pub(crate) family: AddressFamily,
+    pub(crate) socktype: SocketType,
+    pub(crate) protocol: Protocol,
+    pub(crate) flags: AiFlags,
+}
+
+impl CacheKey {
+    fn hints(&self) -> AddrInfo {
+        AddrInfo::hints(self.family, self.socktype, self.protocol, self.flags)
+    }
+}
+
+struct CacheEntry {
+    result: crate::Result<Vec<AddrInfo>>,
+
+    /// When the entry expires, in loop time
+    expires: u64,
+}
+
+struct CacheState {
+    r#loop: crate::Loop,
+    options: ResolverCacheOptions,
+    entries: HashMap<CacheKey, CacheEntry>,
+
+    /// Callbacks waiting for lookups in progress
+    in_flight: HashMap<CacheKey, Vec<ResolveCB<'static>>>,
+
+    /// Overrides, by lowercased host name
+    hosts: HashMap<String, Vec<IpAddr>>,
+}
+
+impl CacheState {
+    /// Returns the override for the key's node, or its cached result, if there is one.
+    fn get(&mut self, key: &CacheKey) -> Option<crate::Result<Vec<AddrInfo>>> {
+        if let Some(infos) = self.get_host(key) {
+            return Some(Ok(infos));
+        }
+
+        let now = self.r#loop.now();
+        match self.entries.get(key) {
+            Some(entry) if entry.expires > now => Some(entry.result.clone()),
+            Some(_) => {
+                self.entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Build AddrInfos for an overridden host. The service must be a port number, or absent.
+    fn get_host(&self, key: &CacheKey) -> Option<Vec<AddrInfo>> {
+        let ips = self.hosts.get(&key.node.as_ref()?.to_lowercase())?;
+        let port = match &key.service {
+            Some(service) => service.parse().ok()?,
+            None => 0,
+        };
+        let hints = key.hints();
+        Some(
+            ips.iter()
+                .filter(|ip| match key.family {
+                    AddressFamily::Inet => ip.is_ipv4(),
+                    AddressFamily::Inet6 => ip.is_ipv6(),
+                    _ => true,
+                })
+                .map(|ip| AddrInfo {
+                    flags: 0,
+                    family: if ip.is_ipv4() {
+                        AddressFamily::Inet.into_inner()
+                    } else {
+                        AddressFamily::Inet6.into_inner()
+                    },
+                    socktype: hints.socktype,
+                    protocol: hints.protocol,
+                    canonical_name: None,
+                    addr: Some(SocketAddr::new(*ip, port)),
+                })
+                .collect(),
+        )
+    }
+
+    /// Cache the result of a lookup, if it should be.
+    fn insert(&mut self, key: CacheKey, result: &crate::Result<Vec<AddrInfo>>) {
+        let ttl = match result {
+            Ok(_) => self.options.ttl,
+            Err(crate::Error::EAI_NONAME)
+            | Err(crate::Error::EAI_NODATA)
+            | Err(crate::Error::EAI_ADDRFAMILY) => self.options.negative_ttl,
+            Err(_) => 0,
+        };
+        if ttl == 0 || self.options.max_entries == 0 {
+            return;
+        }
+
+        let now = self.r#loop.now();
+        if self.entries.len() >= self.options.max_entries {
+            self.entries.retain(|_, entry| entry.expires > now);
+        }
+        if self.entries.len() >= self.options.max_entries {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.expires)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+        self.entries.insert(
+            key,
+            CacheEntry {
+                result: result.clone(),
+                expires: now + ttl,
+            },
+        );
+    }
+}
+
+/// A cache of the results of name lookups. Once installed on a loop with
+/// Loop::set_resolver_cache(), lookups made with ResolverBuilder (and so Loop::tcp_connect_host())
+/// go through the cache:
+///
+///   * Results are cached for a fixed time, since Resolvers don't report TTLs.
+///   * Names which don't exist are cached too, for a shorter time.
+///   * Concurrent lookups of the same name, with the same hints, share one lookup.
+///   * Host names can be overridden with fixed addresses, like entries in a hosts file, which is
+///     useful in tests.
+///
+/// Results from the cache are still delivered asynchronously, on the next loop iteration.
+///
+/// Results are cached by what was looked up, not by the Resolver which looked it up, so lookups
+/// made with different Resolvers share results.
+///
+/// ResolverCaches are cheap to clone: clones share the same cache.
+#[derive(Clone)]
+pub struct ResolverCache {
+    state: Rc<RefCell<CacheState>>,
+}
+
+impl ResolverCache {
+    /// Create a new, empty cache.
+    pub fn new(r#loop: &crate::Loop, options: ResolverCacheOptions) -> ResolverCache {
+        ResolverCache {
+            state: Rc::new(RefCell::new(CacheState {
+                r#loop: r#loop.clone(),
+                options,
+                entries: HashMap::new(),
+                in_flight: HashMap::new(),
+                hosts: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Resolve host to addrs, instead of looking it up. Host names are matched case-insensitively,
+    /// and lookups of overridden hosts must use a numeric port as the service, if any.
+    pub fn add_host(&self, host: &str, addrs: &[IpAddr]) {
+        self.state
+            .borrow_mut()
+            .hosts
+            .insert(host.to_lowercase(), addrs.to_vec());
+    }
+
+    /// Remove an override added with add_host().
+    pub fn remove_host(&self, host: &str) {
+        self.state.borrow_mut().hosts.remove(&host.to_lowercase());
+    }
+
+    /// Remove every cached result. Overrides are kept.
+    pub fn clear(&self) {
+        self.state.borrow_mut().entries.clear();
+    }
+
+    /// Returns the number of cached results, including any which have expired but haven't been
+    /// removed yet.
+    pub fn len(&self) -> usize {
+        self.state.borrow().entries.len()
+    }
+
+    /// Returns true if no results are cached.
+    pub fn is_empty(&self) -> bool {
+        self.state.borrow().entries.is_empty()
+    }
+
+    /// Look up key, from the cache if possible, or else with resolver.
+    pub(crate) fn lookup(
+        &self,
+        key: CacheKey,
+        resolver: &dyn Resolver,
+        cb: ResolveCB<'static>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let (r#loop, cached) = {
+            let mut state = self.state.borrow_mut();
+            (state.r#loop.clone(), state.get(&key))
+        };
+        if let Some(result) = cached {
+            return deliver_later(&r#loop, result, cb);
+        }
+
+        {
+            let mut state = self.state.borrow_mut();
+            if let Some(waiters) = state.in_flight.get_mut(&key) {
+                waiters.push(cb);
+                return Ok(());
+            }
+            state.in_flight.insert(key.clone(), vec![cb]);
+        }
+
+        let cache = self.clone();
+        let lookup_key = key.clone();
+        let result = resolver.resolve(
+            &r#loop,
+            key.node.as_deref(),
+            key.service.as_deref(),
+            &key.hints(),
+            (move |result: crate::Result<Vec<AddrInfo>>| {
+                let waiters = {
+                    let mut state = cache.state.borrow_mut();
+                    state.insert(lookup_key.clone(), &result);
+                    state.in_flight.remove(&lookup_key).unwrap_or_default()
+                };
+                for mut cb in waiters {
+                    cb.call(result.clone());
+                }
+            })
+            .into(),
+        );
+        if result.is_err() {
+            self.state.borrow_mut().in_flight.remove(&key);
+        }
+        result
+    }
+
+    /// Look up key synchronously, from the cache if possible, or else with resolver.
+    pub(crate) fn lookup_sync(
+        &self,
+        key: CacheKey,
+        resolver: &dyn Resolver,
+    ) -> Result<Vec<AddrInfo>, Box<dyn std::error::Error>> {
+        let (r#loop, cached) = {
+            let mut state = self.state.borrow_mut();
+            (state.r#loop.clone(), state.get(&key))
+        };
+        if let Some(result) = cached {
+            return Ok(result?);
+        }
+
+        let result = resolver.resolve_sync(
+            &r#loop,
+            key.node.as_deref(),
+            key.service.as_deref(),
+            &key.hints(),
+        );
+        let cached = match &result {
+            Ok(infos) => Some(Ok(infos.clone())),
+            Err(e) => e.downcast_ref::<crate::Error>().map(|e| Err(*e)),
+        };
+        if let Some(cached) = cached {
+            self.state.borrow_mut().insert(key, &cached);
+        }
+        result
+    }
+}
+
+/// Call cb with result on the next loop iteration, so that results which are known immediately are
+/// delivered the same way as results from getaddrinfo().
+pub(crate) fn deliver_later(
+    r#loop: &crate::Loop,
+    result: crate::Result<Vec<AddrInfo>>,
+    cb: ResolveCB<'static>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut timer = r#loop.timer()?;
+    let mut delivery = Some((result, cb));
+    let started = timer.start(0, 0, move |mut timer: TimerHandle| {
+        timer.close(());
+        if let Some((result, mut cb)) = delivery.take() {
+            cb.call(result);
+        }
+    });
+    if let Err(e) = started {
+        timer.close(());
+        return Err(Box::new(e));
+    }
+    Ok(())
+}
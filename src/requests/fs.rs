@@ -46,6 +46,10 @@ impl FsReq {
         }
 
         let fs_cb = cb.into();
+        if !fs_cb.is_nil() {
+            // requests with a callback run on the threadpool
+            crate::threadpool::mark_started();
+        }
         crate::Req::initialize_data(uv_handle!(req), super::FsData(FsDataFields { fs_cb }));
 
         Ok(FsReq { req })
@@ -134,6 +134,9 @@ impl crate::Loop {
         let node = node.map(CString::new).transpose()?;
         let service = service.map(CString::new).transpose()?;
         let mut req = GetAddrInfoReq::new(cb)?;
+        if uv_cb.is_some() {
+            crate::threadpool::mark_started();
+        }
         let hints = hints.map(|h| h.into_inner());
         let result = crate::uvret(unsafe {
             uv_getaddrinfo(
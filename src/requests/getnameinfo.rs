@@ -144,22 +144,25 @@ impl crate::Loop {
     fn _getnameinfo<CB: Into<GetNameInfoCB<'static>>>(
         &self,
         addr: &SocketAddr,
-        flags: u32,
+        flags: crate::NameInfoFlags,
         cb: CB,
     ) -> Result<GetNameInfoReq, Box<dyn std::error::Error>> {
-        let mut sockaddr: uv::sockaddr = unsafe { std::mem::zeroed() };
+        let mut sockaddr: uv::sockaddr_storage = unsafe { std::mem::zeroed() };
         crate::fill_sockaddr(&mut sockaddr, addr)?;
 
         let cb = cb.into();
         let uv_cb = use_c_callback!(uv_getnameinfo_cb, cb);
         let mut req = GetNameInfoReq::new(cb)?;
+        if uv_cb.is_some() {
+            crate::threadpool::mark_started();
+        }
         let result = crate::uvret(unsafe {
             uv_getnameinfo(
                 self.into_inner(),
                 req.inner(),
                 uv_cb,
-                &sockaddr as _,
-                flags as _,
+                uv_handle!(&sockaddr),
+                flags.bits() as _,
             )
         });
         if result.is_err() {
@@ -172,12 +175,10 @@ impl crate::Loop {
     ///
     /// If successful, the callback will get called sometime in the future with the lookup result.
     /// Consult man -s 3 getnameinfo for more details.
-    ///
-    /// flags is the bitwise OR of NI_* constants
     pub fn getnameinfo<CB: Into<GetNameInfoCB<'static>>>(
         &self,
         addr: &SocketAddr,
-        flags: u32,
+        flags: crate::NameInfoFlags,
         cb: CB,
     ) -> Result<GetNameInfoReq, Box<dyn std::error::Error>> {
         self._getnameinfo(addr, flags, cb)
@@ -186,12 +187,10 @@ impl crate::Loop {
     /// Synchronous getnameinfo(3).
     ///
     /// If successful, will return a tuple of (host, service) Strings.
-    ///
-    /// flags is the bitwise OR of NI_* constants
     pub fn getnameinfo_sync(
         &self,
         addr: &SocketAddr,
-        flags: u32,
+        flags: crate::NameInfoFlags,
     ) -> Result<(String, String), Box<dyn std::error::Error>> {
         self._getnameinfo(addr, flags, ()).map(|mut req| {
             let res = (req.host(), req.service());
@@ -138,6 +138,7 @@ impl crate::Loop {
     ) -> crate::Result<RandomReq> {
         let mut req = RandomReq::new(cb)?;
         let mut buf = std::mem::ManuallyDrop::new(Vec::<u8>::with_capacity(buflen));
+        crate::threadpool::mark_started();
         let result = crate::uvret(unsafe {
             uv_random(
                 self.into_inner(),
@@ -1,4 +1,10 @@
-use crate::{FromInner, Inner, IntoInner};
+use crate::{FromInner, Inner, IntoInner, ReqTrait, TimerHandle};
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
 use uv::{uv_queue_work, uv_work_t};
 
 callbacks! {
@@ -120,26 +126,263 @@ impl crate::ToReq for WorkReq {
 
 impl crate::ReqTrait for WorkReq {}
 
+/// The callback passed to BlockingTask::on_complete()
+type BlockingTaskCB<T> = Box<dyn FnOnce(Result<T, crate::TaskError>)>;
+
+struct BlockingTaskState<T> {
+    r#loop: crate::Loop,
+
+    /// The request, until it finishes and is freed
+    req: Option<WorkReq>,
+    result: Option<Result<T, crate::TaskError>>,
+    cb: Option<BlockingTaskCB<T>>,
+    waker: Option<Waker>,
+}
+
+/// A function running on the threadpool, started with Loop::spawn_blocking(). Its result is
+/// delivered on the loop thread, either to a callback registered with on_complete(), or by awaiting
+/// the task, which is a Future. Dropping the task does not cancel it.
+pub struct BlockingTask<T> {
+    state: Rc<RefCell<BlockingTaskState<T>>>,
+}
+
+impl<T: 'static> BlockingTask<T> {
+    /// Call cb on the loop thread with the result of the task. If the task has already finished,
+    /// cb is called on the next loop iteration. Fails with EINVAL if the result was already taken
+    /// by awaiting the task.
+    pub fn on_complete<CB: FnOnce(Result<T, crate::TaskError>) + 'static>(
+        self,
+        cb: CB,
+    ) -> crate::Result<()> {
+        let r#loop = {
+            let mut state = self.state.borrow_mut();
+            if state.req.is_some() {
+                state.cb = Some(Box::new(cb));
+                return Ok(());
+            }
+            if state.result.is_none() {
+                return Err(crate::Error::EINVAL);
+            }
+            state.r#loop.clone()
+        };
+
+        let mut timer = r#loop.timer()?;
+        let result = self.state.borrow_mut().result.take();
+        let mut delivery = result.map(|result| (cb, result));
+        let started = timer.start(0, 0, move |mut timer: TimerHandle| {
+            timer.close(());
+            if let Some((cb, result)) = delivery.take() {
+                cb(result);
+            }
+        });
+        if let Err(e) = started {
+            timer.close(());
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Cancel the task. This only succeeds if the task is still waiting for a threadpool thread,
+    /// in which case its function never runs and it completes with TaskError::Canceled. Once the
+    /// function has started, or the task has finished, this fails with EBUSY.
+    pub fn cancel(&self) -> crate::Result<()> {
+        match self.state.borrow().req {
+            Some(mut req) => req.cancel(),
+            None => Err(crate::Error::EBUSY),
+        }
+    }
+
+    /// Returns true once the task has finished, whether or not its result has been delivered.
+    pub fn is_finished(&self) -> bool {
+        self.state.borrow().req.is_none()
+    }
+}
+
+impl<T> Future for BlockingTask<T> {
+    type Output = Result<T, crate::TaskError>;
+
+    /// The task is woken on the loop thread, so it must be awaited by an executor which runs on
+    /// the loop thread too.
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.state.borrow_mut();
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
 impl crate::Loop {
     /// Initializes a work request which will run the given work_cb in a thread from the
     /// threadpool. Once work_cb is completed, after_work_cb will be called on the loop thread.
+    /// Fails with EINVAL if work_cb is ().
     ///
     /// This request can be cancelled with Req::cancel().
-    pub fn queue_work<CB: Into<WorkCB<'static>>, ACB: Into<AfterWorkCB<'static>>>(
+    ///
+    /// work_cb runs on another thread, so it must be Send. spawn_blocking() also passes a result
+    /// back to the loop thread, so it is usually a better choice.
+    pub fn queue_work<CB: Into<WorkCB<'static>> + Send, ACB: Into<AfterWorkCB<'static>>>(
         &self,
         work_cb: CB,
         after_work_cb: ACB,
     ) -> crate::Result<WorkReq> {
         let work_cb = work_cb.into();
-        let uv_work_cb = use_c_callback!(uv_work_cb, work_cb);
+        if work_cb.is_nil() {
+            return Err(crate::Error::EINVAL);
+        }
         let mut req = WorkReq::new(work_cb, after_work_cb)?;
-        let uv_after_work_cb = Some(uv_after_work_cb as _);
-        let result = crate::uvret(unsafe {
-            uv_queue_work(self.into_inner(), req.inner(), uv_work_cb, uv_after_work_cb)
-        });
+        let result = crate::uvret(self.submit_work(req));
         if result.is_err() {
             req.destroy();
         }
         result.map(|_| req)
     }
+
+    /// Queue a request created with WorkReq::new(). Its work callback must not be nil, and must
+    /// have been Send before it was converted to a WorkCB. If the request can't be queued, its
+    /// after_work_cb is called with the error on the next loop iteration; either way, the request
+    /// is freed once after_work_cb returns.
+    pub(crate) fn queue_work_req(&self, req: WorkReq) {
+        let status = self.submit_work(req);
+        if status < 0 {
+            // libuv only sets the loop once the request is queued
+            let req: *mut uv_work_t = req.inner();
+            unsafe { (*req).loop_ = self.into_inner() };
+            self.call_soon(move || uv_after_work_cb(req, status));
+        }
+    }
+
+    /// Call f on the next loop iteration, with a 0ms timer, so that a callback is never called
+    /// before the function which started it returns. If the timer can't be started, which only
+    /// happens when memory runs out, f is called straight away instead.
+    pub(crate) fn call_soon<F: FnOnce() + 'static>(&self, f: F) {
+        let f = Rc::new(Cell::new(Some(f)));
+        let timer_f = f.clone();
+        let started = self.timer().and_then(|mut timer| {
+            let started = timer.start(0, 0, move |mut timer: TimerHandle| {
+                timer.close(());
+                if let Some(f) = timer_f.take() {
+                    f();
+                }
+            });
+            if started.is_err() {
+                timer.close(());
+            }
+            started
+        });
+        if started.is_err() {
+            if let Some(f) = f.take() {
+                f();
+            }
+        }
+    }
+
+    /// Private implementation for queue_work() and queue_work_req()
+    fn submit_work(&self, req: WorkReq) -> std::os::raw::c_int {
+        crate::threadpool::mark_started();
+        unsafe {
+            uv_queue_work(
+                self.into_inner(),
+                req.inner(),
+                Some(uv_work_cb as _),
+                Some(uv_after_work_cb as _),
+            )
+        }
+    }
+
+    /// Run f on the threadpool, then pass its return value to done on the loop thread. done is
+    /// passed Canceled if the request is cancelled before f runs, or Panicked if f panics; the
+    /// panic is caught on the threadpool thread, as it cannot unwind through libuv.
+    fn run_blocking<T, F, D>(&self, f: F, done: D) -> crate::Result<WorkReq>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+        D: FnOnce(Result<T, crate::TaskError>) + 'static,
+    {
+        let mut f = Some(f);
+        let mut done = Some(done);
+        let result: Arc<Mutex<Option<std::thread::Result<T>>>> = Default::default();
+        let work_result = result.clone();
+        self.queue_work(
+            move |_: WorkReq| {
+                if let Some(f) = f.take() {
+                    let value = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+                    if let Ok(mut result) = work_result.lock() {
+                        *result = Some(value);
+                    }
+                }
+            },
+            move |_: WorkReq, _: crate::Result<u32>| {
+                // there is no result if the request was cancelled before f ran
+                let value = result.lock().ok().and_then(|mut result| result.take());
+                if let Some(done) = done.take() {
+                    done(match value {
+                        Some(Ok(value)) => Ok(value),
+                        Some(Err(payload)) => Err(crate::TaskError::Panicked(payload)),
+                        None => Err(crate::TaskError::Canceled),
+                    });
+                }
+            },
+        )
+    }
+
+    /// Run f on the threadpool, then pass its result to done on the loop thread. If the request
+    /// is cancelled before f runs, done is passed ECANCELED. f is this crate's own code, so a
+    /// panic in it is a bug, which is resumed on the loop thread like a panic in any other
+    /// callback.
+    pub(crate) fn queue_blocking<T, F, D>(&self, f: F, mut done: D) -> crate::Result<WorkReq>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> crate::Result<T> + Send + 'static,
+        D: FnMut(crate::Result<T>) + 'static,
+    {
+        self.run_blocking(
+            f,
+            move |result: Result<crate::Result<T>, crate::TaskError>| match result {
+                Ok(result) => done(result),
+                Err(crate::TaskError::Canceled) => done(Err(crate::Error::ECANCELED)),
+                Err(crate::TaskError::Panicked(payload)) => std::panic::resume_unwind(payload),
+            },
+        )
+    }
+
+    /// Run f on a threadpool thread, and deliver its return value to the loop thread through the
+    /// returned BlockingTask, which can be awaited, or given a callback with on_complete().
+    ///
+    /// The task completes with TaskError::Canceled if it is cancelled before it starts, or with
+    /// TaskError::Panicked if f panics. The panic is caught on the threadpool thread, as it cannot
+    /// unwind through libuv; its payload can be passed to std::panic::resume_unwind().
+    pub fn spawn_blocking<T, F>(&self, f: F) -> crate::Result<BlockingTask<T>>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+    {
+        let state = Rc::new(RefCell::new(BlockingTaskState {
+            r#loop: self.clone(),
+            req: None,
+            result: None,
+            cb: None,
+            waker: None,
+        }));
+        let done_state = state.clone();
+        let req = self.run_blocking(f, move |result: Result<T, crate::TaskError>| {
+            let (cb, waker) = {
+                let mut state = done_state.borrow_mut();
+                state.req = None;
+                (state.cb.take(), state.waker.take())
+            };
+            match cb {
+                Some(cb) => cb(result),
+                None => done_state.borrow_mut().result = Some(result),
+            }
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        })?;
+        state.borrow_mut().req = Some(req);
+        Ok(BlockingTask { state })
+    }
 }
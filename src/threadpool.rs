@@ -0,0 +1,354 @@
+//! Configuration of libuv's threadpool, which runs queue_work(), fs requests with callbacks,
+//! getaddrinfo(), getnameinfo() and random(), and a scheduler which limits how much of it each
+//! class of work may occupy.
+use crate::{TaskError, WorkCB, WorkReq};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The size of the threadpool if UV_THREADPOOL_SIZE isn't set.
+pub const DEFAULT_SIZE: usize = 4;
+
+/// The largest threadpool libuv supports; larger sizes are clamped to this.
+pub const MAX_SIZE: usize = 1024;
+
+/// Neither started nor being configured
+const IDLE: usize = 0;
+
+/// configure() is setting UV_THREADPOOL_SIZE
+const CONFIGURING: usize = 1;
+
+/// Something may have started the threadpool
+const STARTED: usize = 2;
+
+static STATE: AtomicUsize = AtomicUsize::new(IDLE);
+
+/// Record that the threadpool may be about to start, so configure() can no longer take effect.
+/// This is called before anything is submitted to the threadpool.
+pub(crate) fn mark_started() {
+    loop {
+        match STATE.compare_exchange(IDLE, STARTED, Ordering::SeqCst, Ordering::SeqCst) {
+            Ok(_) | Err(STARTED) => return,
+            // wait for configure() to finish setting the variable, so libuv reads the new value
+            Err(_) => std::thread::yield_now(),
+        }
+    }
+}
+
+/// Set the size of the threadpool, by setting UV_THREADPOOL_SIZE. libuv reads the variable once,
+/// when the threadpool starts, so this must be called before anything uses the threadpool: it
+/// fails with EBUSY if this crate has already submitted work to it, or if another thread is
+/// calling configure() at the same time. size must be between 1 and MAX_SIZE, or this fails with
+/// EINVAL.
+///
+/// Setting an environment variable is not safe while other threads may be reading the
+/// environment, so call this at the start of the program, before starting any threads. This crate
+/// can't tell whether other libraries in the process have already started libuv's threadpool.
+pub fn configure(size: usize) -> crate::Result<()> {
+    if size == 0 || size > MAX_SIZE {
+        return Err(crate::Error::EINVAL);
+    }
+    if STATE
+        .compare_exchange(IDLE, CONFIGURING, Ordering::SeqCst, Ordering::SeqCst)
+        .is_err()
+    {
+        return Err(crate::Error::EBUSY);
+    }
+    std::env::set_var("UV_THREADPOOL_SIZE", size.to_string());
+    STATE.store(IDLE, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Returns true if this crate may have started the threadpool, after which configure() fails.
+pub fn is_started() -> bool {
+    STATE.load(Ordering::SeqCst) == STARTED
+}
+
+/// Returns the size of the threadpool, as libuv works it out from UV_THREADPOOL_SIZE: DEFAULT_SIZE
+/// if the variable isn't set, and otherwise its value clamped to between 1 and MAX_SIZE.
+pub fn size() -> usize {
+    match std::env::var("UV_THREADPOOL_SIZE") {
+        Ok(value) => {
+            // libuv parses the variable with atoi(), which stops at the first non-digit
+            let digits: String = value
+                .trim_start()
+                .chars()
+                .take_while(|c| c.is_ascii_digit())
+                .collect();
+            digits.parse::<usize>().unwrap_or(0).max(1).min(MAX_SIZE)
+        }
+        Err(_) => DEFAULT_SIZE,
+    }
+}
+
+/// The class of some work submitted to a WorkScheduler. Each class has its own limit on how many
+/// threads it may occupy, so a flood of one class can't starve the other.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum WorkClass {
+    /// Work which keeps a thread busy computing, such as hashing or compression.
+    Cpu,
+
+    /// Work which mostly waits, such as fs requests, getaddrinfo() or getnameinfo().
+    SlowIo,
+}
+
+impl WorkClass {
+    fn index(self) -> usize {
+        match self {
+            WorkClass::Cpu => 0,
+            WorkClass::SlowIo => 1,
+        }
+    }
+}
+
+/// Limits for Loop::work_scheduler(): the most threadpool threads each class of work may occupy
+/// at once. Limits below 1 are treated as 1.
+#[derive(Clone, Copy, Debug)]
+pub struct WorkLimits {
+    /// Defaults to one less than the size of the threadpool (but at least 1), so that CPU work
+    /// can't occupy every thread while there is other work, such as fs requests, which the
+    /// scheduler doesn't see.
+    pub cpu: usize,
+
+    /// Defaults to half the size of the threadpool, rounded up, which is the same limit libuv
+    /// applies to its own getaddrinfo() and getnameinfo() requests.
+    pub slow_io: usize,
+}
+
+impl Default for WorkLimits {
+    fn default() -> WorkLimits {
+        let size = size();
+        WorkLimits {
+            cpu: (size - 1).max(1),
+            slow_io: (size + 1) / 2,
+        }
+    }
+}
+
+impl WorkLimits {
+    fn get(&self, class: WorkClass) -> usize {
+        match class {
+            WorkClass::Cpu => self.cpu,
+            WorkClass::SlowIo => self.slow_io,
+        }
+        .max(1)
+    }
+}
+
+/// Work waiting for a permit
+type QueuedWork = Box<dyn FnOnce(WorkPermit)>;
+
+struct SchedulerState {
+    r#loop: crate::Loop,
+    limits: WorkLimits,
+    in_flight: [usize; 2],
+    queued: [VecDeque<QueuedWork>; 2],
+
+    /// Set while start_queued() is running for a class, so that permits dropped by the work it
+    /// starts don't start more work recursively
+    starting: [bool; 2],
+}
+
+/// A work request waiting for a permit. It is freed if the scheduler is dropped before the
+/// request is queued.
+struct UnqueuedWork(Option<WorkReq>);
+
+impl Drop for UnqueuedWork {
+    fn drop(&mut self) {
+        if let Some(mut req) = self.0.take() {
+            req.destroy();
+        }
+    }
+}
+
+/// Permission to occupy a threadpool thread with one piece of work of some class. It is passed
+/// to work submitted with WorkScheduler::submit(), and the slot is freed when it is dropped, so
+/// it should be moved into the callback of the request it starts.
+pub struct WorkPermit {
+    scheduler: WorkScheduler,
+    class: WorkClass,
+}
+
+impl WorkPermit {
+    /// The class of work this permit is for.
+    pub fn class(&self) -> WorkClass {
+        self.class
+    }
+}
+
+impl Drop for WorkPermit {
+    fn drop(&mut self) {
+        let starting = {
+            let mut state = self.scheduler.state.borrow_mut();
+            state.in_flight[self.class.index()] -= 1;
+            state.starting[self.class.index()]
+        };
+        if !starting {
+            self.scheduler.start_queued(self.class);
+        }
+    }
+}
+
+/// Limits how many threadpool threads each class of work may occupy, queueing work on the loop
+/// thread until a thread of its class is free. Only work submitted through the scheduler is
+/// limited; use one scheduler for everything which should share the limits.
+///
+/// The scheduler does not see anything else which uses the threadpool. That includes the rest of
+/// this crate: fs requests with callbacks, and the helpers built on them (AsyncFile, ReadDir,
+/// fs_read_file() and friends), getaddrinfo() and the resolvers built on it, and
+/// getnameinfo_batch(). To classify those, start them from a closure passed to submit(), and move
+/// the permit into their callback. Otherwise, they compete for threads with the scheduler's work.
+#[derive(Clone)]
+pub struct WorkScheduler {
+    state: Rc<RefCell<SchedulerState>>,
+}
+
+impl WorkScheduler {
+    /// Create a new scheduler with the given limits.
+    pub fn new(r#loop: &crate::Loop, limits: WorkLimits) -> WorkScheduler {
+        WorkScheduler {
+            state: Rc::new(RefCell::new(SchedulerState {
+                r#loop: r#loop.clone(),
+                limits,
+                in_flight: [0; 2],
+                queued: [VecDeque::new(), VecDeque::new()],
+                starting: [false; 2],
+            })),
+        }
+    }
+
+    /// Call start with a WorkPermit once fewer than the limit of work of the given class is in
+    /// progress. start should use the permit to start a request, such as an fs request or
+    /// getaddrinfo(), and move the permit into the request's callback, so that it is dropped when
+    /// the request finishes. If there is a free slot, start is called before this returns.
+    pub fn submit<F: FnOnce(WorkPermit) + 'static>(&self, class: WorkClass, start: F) {
+        self.state.borrow_mut().queued[class.index()].push_back(Box::new(start));
+        self.start_queued(class);
+    }
+
+    /// Queue work, like Loop::queue_work(), once a slot of the given class is free. The slot is
+    /// freed when work_cb finishes, just before after_work_cb is called. Fails with EINVAL if
+    /// work_cb is (). If the work can't be queued once a slot is free, after_work_cb is called
+    /// with the error on the next loop iteration.
+    pub fn queue_work<CB: Into<WorkCB<'static>> + Send, ACB: Into<crate::AfterWorkCB<'static>>>(
+        &self,
+        class: WorkClass,
+        work_cb: CB,
+        after_work_cb: ACB,
+    ) -> crate::Result<()> {
+        let work_cb = work_cb.into();
+        if work_cb.is_nil() {
+            return Err(crate::Error::EINVAL);
+        }
+        let mut after_work_cb = after_work_cb.into();
+        let permit: Rc<Cell<Option<WorkPermit>>> = Default::default();
+        let req_permit = permit.clone();
+        // work_cb was Send before it was converted
+        let req = WorkReq::new(work_cb, move |req: WorkReq, status: crate::Result<u32>| {
+            drop(req_permit.take());
+            after_work_cb.call(req, status);
+        })?;
+        let mut req = UnqueuedWork(Some(req));
+        let r#loop = self.state.borrow().r#loop.clone();
+        self.submit(class, move |start_permit: WorkPermit| {
+            permit.set(Some(start_permit));
+            if let Some(req) = req.0.take() {
+                r#loop.queue_work_req(req);
+            }
+        });
+        Ok(())
+    }
+
+    /// Run f on the threadpool, like Loop::spawn_blocking(), once a slot of the given class is
+    /// free, and pass its result to cb on the loop thread. The slot is freed just before cb is
+    /// called. If f cannot be queued, cb is passed TaskError::Canceled. cb is never called before
+    /// this returns.
+    pub fn spawn_blocking<T, F, CB>(&self, class: WorkClass, f: F, cb: CB)
+    where
+        T: Send + 'static,
+        F: FnOnce() -> T + Send + 'static,
+        CB: FnOnce(Result<T, TaskError>) + 'static,
+    {
+        let r#loop = self.state.borrow().r#loop.clone();
+        self.submit(class, move |permit: WorkPermit| {
+            match r#loop.spawn_blocking(f) {
+                Ok(task) => {
+                    // a task which has just been spawned can't have finished, so this can't fail
+                    let _ = task.on_complete(move |result| {
+                        drop(permit);
+                        cb(result);
+                    });
+                }
+                Err(_) => {
+                    drop(permit);
+                    r#loop.call_soon(move || cb(Err(TaskError::Canceled)));
+                }
+            }
+        });
+    }
+
+    /// Returns how much work of the given class is in progress.
+    pub fn in_flight(&self, class: WorkClass) -> usize {
+        self.state.borrow().in_flight[class.index()]
+    }
+
+    /// Returns how much work of the given class is waiting for a free slot.
+    pub fn queued(&self, class: WorkClass) -> usize {
+        self.state.borrow().queued[class.index()].len()
+    }
+
+    /// Returns the scheduler's limits.
+    pub fn limits(&self) -> WorkLimits {
+        self.state.borrow().limits
+    }
+
+    /// Change the scheduler's limits. Raising a limit starts queued work straight away; lowering
+    /// one doesn't affect work which is already in progress.
+    pub fn set_limits(&self, limits: WorkLimits) {
+        self.state.borrow_mut().limits = limits;
+        self.start_queued(WorkClass::Cpu);
+        self.start_queued(WorkClass::SlowIo);
+    }
+
+    /// Start queued work of the given class until its limit is reached.
+    fn start_queued(&self, class: WorkClass) {
+        let i = class.index();
+        {
+            let mut state = self.state.borrow_mut();
+            if state.starting[i] {
+                return;
+            }
+            state.starting[i] = true;
+        }
+        loop {
+            let start = {
+                let mut state = self.state.borrow_mut();
+                if state.in_flight[i] >= state.limits.get(class) {
+                    None
+                } else {
+                    let start = state.queued[i].pop_front();
+                    if start.is_some() {
+                        state.in_flight[i] += 1;
+                    }
+                    start
+                }
+            };
+            match start {
+                Some(start) => start(WorkPermit {
+                    scheduler: self.clone(),
+                    class,
+                }),
+                None => break,
+            }
+        }
+        self.state.borrow_mut().starting[i] = false;
+    }
+}
+
+impl crate::Loop {
+    /// Create a new WorkScheduler with the given limits.
+    pub fn work_scheduler(&self, limits: WorkLimits) -> WorkScheduler {
+        WorkScheduler::new(self, limits)
+    }
+}
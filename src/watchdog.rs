@@ -0,0 +1,282 @@
+//! A watchdog that detects callbacks which block the event loop. See Loop::enable_watchdog().
+use crate::{HandleTrait, HandleType, ReqTrait, ReqType};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Where a callback came from.
+#[derive(Clone, Copy, Debug)]
+pub enum CallbackSource {
+    /// The callback was generated by a handle of the given type.
+    Handle(HandleType),
+
+    /// The callback was generated by a request of the given type.
+    Req(ReqType),
+}
+
+/// Details about a callback that ran for longer than the watchdog's threshold.
+#[derive(Clone, Copy, Debug)]
+pub struct SlowCallback {
+    /// The name of the callback type, such as "TimerCB" or "ReadCB".
+    pub callback: &'static str,
+
+    /// The handle or request that generated the callback, if known.
+    pub source: Option<CallbackSource>,
+
+    /// How long the callback ran.
+    pub duration: Duration,
+}
+
+/// The watchdog's configuration for one loop. While one exists, WATCHDOGS is non-zero and
+/// callbacks are timed.
+struct Watchdog {
+    threshold: u64,
+    hook: Option<Box<dyn FnMut(&SlowCallback)>>,
+}
+
+impl Watchdog {
+    fn new(threshold: u64, hook: Box<dyn FnMut(&SlowCallback)>) -> Watchdog {
+        WATCHDOGS.fetch_add(1, Ordering::Relaxed);
+        Watchdog {
+            threshold,
+            hook: Some(hook),
+        }
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        WATCHDOGS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// A loop's watchdog, if it has one. This is stored in the loop's data, and shared with the
+/// thread running the loop for as long as Loop::run() is running.
+#[derive(Default)]
+pub(crate) struct LoopWatchdog {
+    watchdog: RefCell<Option<Watchdog>>,
+}
+
+/// The number of loops which currently have a watchdog enabled. Callbacks check this before
+/// anything else, so that they aren't timed at all unless some loop has a watchdog.
+static WATCHDOGS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The watchdog of the loop currently running on this thread.
+    static RUNNING: RefCell<Option<Rc<LoopWatchdog>>> = RefCell::new(None);
+}
+
+/// Makes a loop's watchdog the one that callbacks on the current thread are reported to, for as
+/// long as the loop runs. The watchdog of any loop that was already running on the thread is
+/// restored when the guard is dropped.
+pub(crate) struct RunGuard {
+    previous: Option<Rc<LoopWatchdog>>,
+}
+
+impl RunGuard {
+    pub(crate) fn enter(watchdog: Option<Rc<LoopWatchdog>>) -> RunGuard {
+        let previous = RUNNING.try_with(|r| r.replace(watchdog)).ok().flatten();
+        RunGuard { previous }
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        let _ = RUNNING.try_with(|r| *r.borrow_mut() = previous);
+    }
+}
+
+/// Implemented by the first argument of a callback when it identifies the handle or request that
+/// generated the callback. Callbacks whose first argument doesn't implement this, such as those
+/// passed only a result, are reported without a source.
+pub(crate) trait WatchdogSource {
+    fn watchdog_source(&self) -> CallbackSource;
+}
+
+/// Looks up the source of a callback from its first argument, for the callbacks! macro, which
+/// calls `(&first).callback_source()`. Method resolution tries the receiver `&T` before `&&T`, so
+/// this impl is chosen when T implements WatchdogSource, and NoCallbackSource otherwise.
+pub(crate) trait KnownCallbackSource {
+    fn callback_source(&self) -> Option<CallbackSource>;
+}
+
+impl<T: WatchdogSource> KnownCallbackSource for T {
+    fn callback_source(&self) -> Option<CallbackSource> {
+        Some(self.watchdog_source())
+    }
+}
+
+/// The fallback for KnownCallbackSource, for first arguments which don't identify a source.
+pub(crate) trait NoCallbackSource {
+    fn callback_source(&self) -> Option<CallbackSource>;
+}
+
+impl<T> NoCallbackSource for &T {
+    fn callback_source(&self) -> Option<CallbackSource> {
+        None
+    }
+}
+
+macro_rules! impl_handle_source {
+    ($($T:ty),+) => {
+        $(impl WatchdogSource for $T {
+            fn watchdog_source(&self) -> CallbackSource {
+                CallbackSource::Handle(self.get_type())
+            }
+        })+
+    };
+}
+
+macro_rules! impl_req_source {
+    ($($T:ty),+) => {
+        $(impl WatchdogSource for $T {
+            fn watchdog_source(&self) -> CallbackSource {
+                CallbackSource::Req(self.get_type())
+            }
+        })+
+    };
+}
+
+impl_handle_source!(
+    crate::AsyncHandle,
+    crate::CheckHandle,
+    crate::FsEventHandle,
+    crate::FsPollHandle,
+    crate::Handle,
+    crate::IdleHandle,
+    crate::PipeHandle,
+    crate::PollHandle,
+    crate::PrepareHandle,
+    crate::ProcessHandle,
+    crate::SignalHandle,
+    crate::StreamHandle,
+    crate::TcpHandle,
+    crate::TimerHandle,
+    crate::TtyHandle,
+    crate::UdpHandle
+);
+
+impl_req_source!(
+    crate::ConnectReq,
+    crate::FsReq,
+    crate::GetAddrInfoReq,
+    crate::GetNameInfoReq,
+    crate::RandomReq,
+    crate::Req,
+    crate::ShutdownReq,
+    crate::UdpSendReq,
+    crate::WorkReq,
+    crate::WriteReq
+);
+
+/// Times a single callback invocation. Created by the callbacks! macro right before a callback is
+/// called; the duration is checked when the guard is dropped.
+pub(crate) struct CallbackGuard {
+    callback: &'static str,
+    source: Option<CallbackSource>,
+    start: u64,
+    watchdog: Option<Rc<LoopWatchdog>>,
+}
+
+impl CallbackGuard {
+    /// Returns a guard for a callback which isn't timed.
+    pub(crate) fn untimed(callback: &'static str) -> CallbackGuard {
+        CallbackGuard {
+            callback,
+            source: None,
+            start: 0,
+            watchdog: None,
+        }
+    }
+
+    pub(crate) fn new<F: FnOnce() -> Option<CallbackSource>>(
+        callback: &'static str,
+        source: F,
+    ) -> CallbackGuard {
+        let watchdog = if WATCHDOGS.load(Ordering::Relaxed) == 0 {
+            None
+        } else {
+            RUNNING
+                .try_with(|r| r.borrow().clone())
+                .ok()
+                .flatten()
+                .filter(|w| w.watchdog.borrow().is_some())
+        };
+        if watchdog.is_none() {
+            return CallbackGuard::untimed(callback);
+        }
+
+        CallbackGuard {
+            callback,
+            source: source(),
+            start: crate::hrtime(),
+            watchdog,
+        }
+    }
+}
+
+impl Drop for CallbackGuard {
+    fn drop(&mut self) {
+        let watchdog = match self.watchdog.take() {
+            Some(watchdog) => watchdog,
+            None => return,
+        };
+        let elapsed = crate::hrtime().saturating_sub(self.start);
+
+        // The hook is taken out of the watchdog while it runs, so that it may safely call
+        // enable_watchdog() or disable_watchdog() on the loop itself.
+        let hook = match watchdog.watchdog.borrow_mut().as_mut() {
+            Some(w) if elapsed >= w.threshold => w.hook.take(),
+            _ => None,
+        };
+
+        if let Some(mut hook) = hook {
+            hook(&SlowCallback {
+                callback: self.callback,
+                source: self.source,
+                duration: Duration::from_nanos(elapsed),
+            });
+
+            if let Some(w) = watchdog.watchdog.borrow_mut().as_mut() {
+                if w.hook.is_none() {
+                    w.hook = Some(hook);
+                }
+            }
+        }
+    }
+}
+
+impl crate::Loop {
+    /// Enable the slow-callback watchdog on this loop. While the loop is running, every callback
+    /// it dispatches is timed, and any callback that runs for at least `threshold` is reported to
+    /// `hook`, along with the type of handle or request that generated it, if known. This is
+    /// useful for catching callbacks that accidentally do blocking work and stall the loop. Any
+    /// watchdog previously enabled on the loop is replaced.
+    ///
+    /// Callbacks that run on the threadpool (such as the work callback passed to queue_work())
+    /// are never timed, since they are expected to block. Callbacks which are called outside of
+    /// run() aren't timed either.
+    ///
+    /// Note: a slow callback that dispatches another callback synchronously (for example, a
+    /// SignalSet callback is dispatched from a SignalHandle callback) will be reported more than
+    /// once.
+    pub fn enable_watchdog<F>(&self, threshold: Duration, hook: F)
+    where
+        F: FnMut(&SlowCallback) + 'static,
+    {
+        if let Some(watchdog) = self.watchdog() {
+            let threshold = threshold.as_nanos().min(u64::MAX as u128) as u64;
+            let new = Watchdog::new(threshold, Box::new(hook));
+            watchdog.watchdog.replace(Some(new));
+        }
+    }
+
+    /// Disable the slow-callback watchdog on this loop.
+    pub fn disable_watchdog(&self) {
+        if let Some(watchdog) = self.watchdog() {
+            watchdog.watchdog.replace(None);
+        }
+    }
+}